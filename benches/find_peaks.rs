@@ -0,0 +1,119 @@
+//! Compare peak matching with a binary search to the original linear scan.
+//!
+//! Run with `cargo bench`. The bundled test file is used when it is present,
+//! along with synthetic profile spectra of 50,000 points.
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use mzsniffer::defaults::DefaultPolymers;
+use mzsniffer::mzml::{MzMLReader, Spectrum};
+use mzsniffer::polymer::IonSeries;
+use mzsniffer::search::find_peaks;
+
+const TEST_FILE: &str = "data/MSV000081544.20170728_MS1_17k_plasmaspikedPEG_3.mzML";
+
+/// The original O(precursors x peaks) implementation.
+fn find_peaks_linear(
+    query_vec: &[f64],
+    tol_vec: &[f64],
+    mz_vec: &[f64],
+    intensity_vec: &[f64],
+) -> f64 {
+    let mut total_intensity = 0.;
+    for (query_mz, tol) in query_vec.iter().zip(tol_vec.iter()) {
+        let mut biggest = 0.;
+        for (mz, intensity) in mz_vec.iter().zip(intensity_vec.iter()) {
+            if (mz - query_mz).abs() <= *tol && intensity > &biggest {
+                biggest = *intensity;
+            }
+        }
+        total_intensity += biggest;
+    }
+    total_intensity
+}
+
+type FindPeaks = fn(&[f64], &[f64], &[f64], &[f64]) -> f64;
+
+fn match_all(spectra: &[Spectrum], series: &[IonSeries], func: FindPeaks) -> f64 {
+    let mut total = 0.;
+    for spectrum in spectra.iter() {
+        for ions in series.iter() {
+            total += func(
+                &ions.precursors,
+                &ions.tols,
+                &spectrum.mz,
+                &spectrum.intensity,
+            );
+        }
+    }
+    total
+}
+
+/// The ion series of the default polymers, up to `max_mz`.
+fn default_series(max_mz: f64) -> Vec<IonSeries> {
+    DefaultPolymers::new()
+        .0
+        .into_iter()
+        .flat_map(|mut poly| {
+            poly.calculate_bounds(&max_mz, &10., "ppm");
+            poly.series.unwrap()
+        })
+        .collect()
+}
+
+/// Profile spectra with evenly spaced points from 100 to 2000 m/z.
+fn profile_spectra(n_spectra: usize, n_points: usize) -> Vec<Spectrum> {
+    let step = 1900. / n_points as f64;
+    (0..n_spectra)
+        .map(|i| Spectrum {
+            ms_level: 1,
+            mz: (0..n_points).map(|j| 100. + j as f64 * step).collect(),
+            intensity: (0..n_points).map(|j| ((i + j) % 1000) as f64).collect(),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn read_test_file() -> Option<Vec<Spectrum>> {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let mzml = tokio::fs::File::open(TEST_FILE).await.ok()?;
+        let mzml = tokio::io::BufReader::new(mzml);
+        let spectra = MzMLReader::new().parse(mzml).await.ok()?;
+        Some(spectra.spectra)
+    })
+}
+
+fn bench_profile(c: &mut Criterion) {
+    let spectra = profile_spectra(5, 50_000);
+    let series = default_series(2000.);
+    let mut group = c.benchmark_group("profile_50k");
+    group.bench_function("binary_search", |b| {
+        b.iter(|| match_all(black_box(&spectra), &series, find_peaks))
+    });
+    group.bench_function("linear", |b| {
+        b.iter(|| match_all(black_box(&spectra), &series, find_peaks_linear))
+    });
+    group.finish();
+}
+
+fn bench_test_file(c: &mut Criterion) {
+    let spectra = match read_test_file() {
+        Some(spectra) => spectra,
+        None => {
+            eprintln!("Skipping the test file benchmark; {} not found.", TEST_FILE);
+            return;
+        }
+    };
+    let series = default_series(1500.);
+    let mut group = c.benchmark_group("test_file");
+    group.sample_size(10);
+    group.bench_function("binary_search", |b| {
+        b.iter(|| match_all(black_box(&spectra), &series, find_peaks))
+    });
+    group.bench_function("linear", |b| {
+        b.iter(|| match_all(black_box(&spectra), &series, find_peaks_linear))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_profile, bench_test_file);
+criterion_main!(benches);
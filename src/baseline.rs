@@ -0,0 +1,305 @@
+//! Compare runs to the polymers found in previously saved results.
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::calibration::median;
+use crate::search::PolymerResults;
+
+/// The default fold change in %TIC that flags a polymer.
+pub const DEFAULT_MAX_FOLD: f64 = 2.;
+
+/// A %TIC added to both sides of a fold change, so that changes between
+/// trace levels of a polymer are not flagged.
+const PSEUDOCOUNT: f64 = 0.01;
+
+/// The %TIC of each polymer in a set of reference runs.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Baseline {
+    /// The number of reference runs.
+    pub runs: usize,
+    /// The median %TIC of each polymer across the reference runs.
+    pub percent_tic: BTreeMap<String, f64>,
+    /// The largest %TIC of each polymer across the reference runs.
+    pub max_percent_tic: BTreeMap<String, f64>,
+}
+
+/// Saved results may be a single run or the list written by mzsniffer.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SavedResults {
+    Many(Vec<PolymerResults>),
+    One(Box<PolymerResults>),
+}
+
+impl From<SavedResults> for Vec<PolymerResults> {
+    fn from(saved: SavedResults) -> Self {
+        match saved {
+            SavedResults::Many(results) => results,
+            SavedResults::One(results) => vec![*results],
+        }
+    }
+}
+
+impl Baseline {
+    /// Summarize the %TIC of each polymer in reference runs.
+    ///
+    /// A polymer that was not searched in a run does not count towards
+    /// its median there.
+    pub fn new(results: &[PolymerResults]) -> Self {
+        let mut values: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+        for run in results.iter() {
+            for poly in run.polymers.iter() {
+                values
+                    .entry(poly.name.clone())
+                    .or_default()
+                    .push(run.percent_tic(poly));
+            }
+        }
+
+        Self {
+            runs: results.len(),
+            max_percent_tic: values
+                .iter()
+                .map(|(name, x)| (name.clone(), x.iter().copied().fold(0., f64::max)))
+                .collect(),
+            percent_tic: values
+                .into_iter()
+                .map(|(name, x)| (name, median(x)))
+                .collect(),
+        }
+    }
+
+    /// Read saved results from a JSON or pickle file written with
+    /// `--format`.
+    ///
+    /// The format is chosen using the file extension.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, BaselineError> {
+        let path = path.as_ref();
+        let ext = path
+            .extension()
+            .and_then(|x| x.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+
+        let saved: SavedResults = match ext.as_str() {
+            "json" => serde_json::from_reader(std::io::BufReader::new(std::fs::File::open(path)?))?,
+            "pkl" | "pickle" => serde_pickle::from_reader(
+                std::fs::File::open(path)?,
+                serde_pickle::de::DeOptions::new(),
+            )?,
+            _ => return Err(BaselineError::UnknownFormat(path.display().to_string())),
+        };
+        let results: Vec<PolymerResults> = saved.into();
+        if results.is_empty() {
+            return Err(BaselineError::Empty(path.display().to_string()));
+        }
+        Ok(Self::new(&results))
+    }
+
+    /// Compare the %TIC of each polymer in a run to the baseline.
+    ///
+    /// A polymer is flagged when its %TIC changed by at least `max_fold`
+    /// in either direction. Polymers that are not in the baseline are
+    /// skipped.
+    pub fn compare(&self, results: &PolymerResults, max_fold: f64) -> Comparison {
+        let changes: Vec<Change> = results
+            .polymers
+            .iter()
+            .filter_map(|poly| {
+                let baseline = *self.percent_tic.get(&poly.name)?;
+                let percent_tic = results.percent_tic(poly);
+                let fold_change = (percent_tic + PSEUDOCOUNT) / (baseline + PSEUDOCOUNT);
+                let flag = if fold_change >= max_fold {
+                    Flag::Increased
+                } else if fold_change <= 1. / max_fold {
+                    Flag::Decreased
+                } else {
+                    Flag::Unchanged
+                };
+                Some(Change {
+                    name: poly.name.clone(),
+                    percent_tic,
+                    baseline_percent_tic: baseline,
+                    fold_change,
+                    flag,
+                })
+            })
+            .collect();
+
+        Comparison {
+            filename: results.filename.clone(),
+            worse: changes.iter().any(|x| x.flag == Flag::Increased),
+            changes,
+        }
+    }
+}
+
+/// Whether a polymer changed relative to the baseline.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Flag {
+    Increased,
+    Decreased,
+    Unchanged,
+}
+
+/// The change of one polymer relative to the baseline.
+#[derive(Serialize, Debug, Clone)]
+pub struct Change {
+    pub name: String,
+    pub percent_tic: f64,
+    /// The median %TIC of the reference runs.
+    pub baseline_percent_tic: f64,
+    /// The ratio of the %TIC to the baseline, each with a small
+    /// pseudocount.
+    pub fold_change: f64,
+    pub flag: Flag,
+}
+
+/// The comparison of every polymer in a run to the baseline.
+#[derive(Serialize, Debug, Clone)]
+pub struct Comparison {
+    pub filename: String,
+    /// Did any polymer increase relative to the baseline?
+    pub worse: bool,
+    pub changes: Vec<Change>,
+}
+
+impl Comparison {
+    /// Log a table of the changes to stderr.
+    pub fn log(&self) {
+        let status = match self.worse {
+            true => "WORSE THAN BASELINE",
+            false => "NORMAL",
+        };
+        log::info!("Baseline comparison for {}: {}", self.filename, status);
+        log::info!("{}", "+".repeat(70));
+        log::info!("Polymer                         %TIC  Baseline     Fold  Flag");
+        log::info!("{}", "+".repeat(70));
+        for change in self.changes.iter() {
+            let flag = match change.flag {
+                Flag::Increased => "INCREASED",
+                Flag::Decreased => "DECREASED",
+                Flag::Unchanged => "-",
+            };
+            log::info!(
+                "{:26}  {:>8.4}  {:>8.4}  {:>7.2}  {}",
+                change.name,
+                change.percent_tic,
+                change.baseline_percent_tic,
+                change.fold_change,
+                flag
+            );
+        }
+        log::info!("{}", "+".repeat(70));
+        log::info!("");
+    }
+}
+
+#[derive(Debug)]
+pub enum BaselineError {
+    UnknownFormat(String),
+    Empty(String),
+    IOError(std::io::Error),
+    JsonError(serde_json::Error),
+    PickleError(serde_pickle::Error),
+}
+
+impl std::fmt::Display for BaselineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BaselineError::UnknownFormat(s) => write!(
+                f,
+                "BaselineError: unknown results format for {} (expected .json or .pickle)",
+                s
+            ),
+            BaselineError::Empty(s) => write!(f, "BaselineError: no runs in {}", s),
+            BaselineError::IOError(s) => write!(f, "BaselineError: IO error {}", s),
+            BaselineError::JsonError(s) => write!(f, "BaselineError: JSON error {}", s),
+            BaselineError::PickleError(s) => write!(f, "BaselineError: pickle error {}", s),
+        }
+    }
+}
+
+impl Error for BaselineError {}
+
+impl From<std::io::Error> for BaselineError {
+    fn from(residual: std::io::Error) -> Self {
+        Self::IOError(residual)
+    }
+}
+
+impl From<serde_json::Error> for BaselineError {
+    fn from(residual: serde_json::Error) -> Self {
+        Self::JsonError(residual)
+    }
+}
+
+impl From<serde_pickle::Error> for BaselineError {
+    fn from(residual: serde_pickle::Error) -> Self {
+        Self::PickleError(residual)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Baseline, Flag, SavedResults};
+    use crate::search::PolymerResults;
+
+    fn run(filename: &str, peg: f64, ppg: f64) -> PolymerResults {
+        PolymerResults::from_totals(filename, &[("PEG", peg), ("PPG", ppg)], &[100.])
+    }
+
+    #[test]
+    fn test_baseline() {
+        let reference = vec![run("a", 1., 2.), run("b", 3., 2.), run("c", 2., 20.)];
+        let baseline = Baseline::new(&reference);
+        assert_eq!(baseline.runs, 3);
+        assert_eq!(baseline.percent_tic["PEG"], 2.);
+        assert_eq!(baseline.percent_tic["PPG"], 2.);
+        assert_eq!(baseline.max_percent_tic["PPG"], 20.);
+
+        let comparison = baseline.compare(&run("new", 10., 0.5), 2.);
+        assert!(comparison.worse);
+        assert_eq!(comparison.changes[0].flag, Flag::Increased);
+        assert!((comparison.changes[0].fold_change - 10.01 / 2.01).abs() < 1e-9);
+        assert_eq!(comparison.changes[1].flag, Flag::Decreased);
+
+        let comparison = baseline.compare(&run("new", 3., 2.), 2.);
+        assert!(!comparison.worse);
+        assert_eq!(comparison.changes[0].flag, Flag::Unchanged);
+
+        // Trace amounts are not flagged:
+        let baseline = Baseline::new(&[run("a", 0., 0.)]);
+        let comparison = baseline.compare(&run("new", 0.001, 0.), 2.);
+        assert!(!comparison.worse);
+    }
+
+    #[test]
+    fn test_saved_results() {
+        let mut reference = vec![run("a", 1., 2.), run("b", 3., 4.)];
+        reference[0].polymers[0].xic = vec![1.];
+        let json = serde_json::to_string(&reference).unwrap();
+        let saved: Vec<PolymerResults> =
+            serde_json::from_str::<SavedResults>(&json).unwrap().into();
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved[0].polymers[0].xic, vec![1.]);
+        assert_eq!(saved[1].polymers[1].total, 4.);
+
+        let json = serde_json::to_string(&reference[1]).unwrap();
+        let saved: Vec<PolymerResults> =
+            serde_json::from_str::<SavedResults>(&json).unwrap().into();
+        assert_eq!(saved[0].filename, "b");
+
+        let pickle =
+            serde_pickle::to_vec(&reference, serde_pickle::ser::SerOptions::new()).unwrap();
+        let saved: Vec<PolymerResults> =
+            serde_pickle::from_slice::<SavedResults>(&pickle, serde_pickle::de::DeOptions::new())
+                .unwrap()
+                .into();
+        assert_eq!(saved[1].polymers[0].total, 3.);
+    }
+}
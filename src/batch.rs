@@ -0,0 +1,290 @@
+//! Compare polymer contamination across a batch of runs.
+use serde::Serialize;
+
+use crate::calibration::{median, MAD_SCALE};
+use crate::search::PolymerResults;
+
+/// Scale the mean absolute deviation to estimate a standard deviation.
+/// This is used when more than half of the runs have the same %TIC.
+const MEAN_AD_SCALE: f64 = 1.2533;
+
+/// The robust z-score above which a run is an outlier for a polymer.
+const Z_THRESHOLD: f64 = 3.5;
+
+/// The fewest runs needed to call outliers.
+const MIN_RUNS: usize = 3;
+
+/// A comparison of the polymers found in many runs.
+#[derive(Serialize, Debug, Clone)]
+pub struct BatchSummary {
+    /// The file of each run, in the order they were searched.
+    pub files: Vec<String>,
+    /// The name of each polymer.
+    pub polymers: Vec<String>,
+    /// The %TIC of each polymer (columns) in each run (rows).
+    pub percent_tic: Vec<Vec<f64>>,
+    /// The runs, from the most to the least contaminated.
+    pub worst_runs: Vec<RunRank>,
+    /// The distribution of the %TIC of each polymer across runs.
+    pub polymer_stats: Vec<PolymerStats>,
+}
+
+/// The total contamination of a run.
+#[derive(Serialize, Debug, Clone)]
+pub struct RunRank {
+    pub filename: String,
+    /// The %TIC of all polymers combined.
+    pub total_percent_tic: f64,
+    /// The polymer with the largest %TIC, if any were found.
+    pub top_polymer: Option<String>,
+}
+
+/// A summary of the %TIC of one polymer across runs.
+#[derive(Serialize, Debug, Clone)]
+pub struct PolymerStats {
+    pub name: String,
+    pub median: f64,
+    /// The median absolute deviation from the median.
+    pub mad: f64,
+    pub max: f64,
+    /// The run with the largest %TIC.
+    pub max_file: String,
+    /// The runs whose %TIC has a robust z-score above 3.5. Runs with less
+    /// of the polymer than usual are never outliers.
+    pub outliers: Vec<Outlier>,
+}
+
+/// A run with an unusually high %TIC for a polymer.
+#[derive(Serialize, Debug, Clone)]
+pub struct Outlier {
+    pub filename: String,
+    pub percent_tic: f64,
+    pub z_score: f64,
+}
+
+impl BatchSummary {
+    /// Summarize the results of each run.
+    ///
+    /// Polymers are matched between runs by name, and a polymer that was
+    /// not searched in a run has a %TIC of 0 there.
+    pub fn new(results: &[PolymerResults]) -> Self {
+        let mut polymers: Vec<String> = Vec::new();
+        for poly in results.iter().flat_map(|x| x.polymers.iter()) {
+            if !polymers.contains(&poly.name) {
+                polymers.push(poly.name.clone());
+            }
+        }
+
+        let files: Vec<String> = results.iter().map(|x| x.filename.clone()).collect();
+        let percent_tic: Vec<Vec<f64>> = results
+            .iter()
+            .map(|run| {
+                polymers
+                    .iter()
+                    .map(|name| {
+                        run.polymers
+                            .iter()
+                            .find(|x| &x.name == name)
+                            .map_or(0., |x| run.percent_tic(x))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut worst_runs: Vec<RunRank> = files
+            .iter()
+            .zip(percent_tic.iter())
+            .map(|(filename, row)| {
+                let top_polymer = row
+                    .iter()
+                    .zip(polymers.iter())
+                    .filter(|(pct, _)| **pct > 0.)
+                    .max_by(|a, b| a.0.total_cmp(b.0))
+                    .map(|(_, name)| name.clone());
+                RunRank {
+                    filename: filename.clone(),
+                    total_percent_tic: row.iter().sum(),
+                    top_polymer,
+                }
+            })
+            .collect();
+        worst_runs.sort_by(|a, b| b.total_percent_tic.total_cmp(&a.total_percent_tic));
+
+        let polymer_stats = match files.is_empty() {
+            true => Vec::new(),
+            false => polymers
+                .iter()
+                .enumerate()
+                .map(|(idx, name)| {
+                    let values: Vec<f64> = percent_tic.iter().map(|row| row[idx]).collect();
+                    PolymerStats::new(name, &values, &files)
+                })
+                .collect(),
+        };
+
+        Self {
+            files,
+            polymers,
+            percent_tic,
+            worst_runs,
+            polymer_stats,
+        }
+    }
+
+    /// Log the worst runs and the statistics of each polymer to stderr.
+    pub fn log(&self, n_worst: usize) {
+        log::info!("Batch summary of {} runs:", self.files.len());
+        log::info!("{}", "+".repeat(76));
+        log::info!("Rank  File                                   Total %TIC  Top polymer");
+        log::info!("{}", "+".repeat(76));
+        for (rank, run) in self.worst_runs.iter().take(n_worst).enumerate() {
+            log::info!(
+                "{:>4}  {:37}  {:>10.4}  {}",
+                rank + 1,
+                run.filename,
+                run.total_percent_tic,
+                run.top_polymer.as_deref().unwrap_or("-")
+            );
+        }
+        log::info!("{}", "+".repeat(76));
+        log::info!("");
+
+        log::info!("{}", "+".repeat(64));
+        log::info!("Polymer                     Median %TIC  Max %TIC  Outliers");
+        log::info!("{}", "+".repeat(64));
+        for stats in self.polymer_stats.iter() {
+            log::info!(
+                "{:26}  {:>11.4}  {:>8.4}  {:>8}",
+                stats.name,
+                stats.median,
+                stats.max,
+                stats.outliers.len()
+            );
+        }
+        log::info!("{}", "+".repeat(64));
+        for stats in self.polymer_stats.iter() {
+            for outlier in stats.outliers.iter() {
+                log::warn!(
+                    "{} is an outlier in {}: {:.4} %TIC (z = {:.1})",
+                    stats.name,
+                    outlier.filename,
+                    outlier.percent_tic,
+                    outlier.z_score
+                );
+            }
+        }
+        log::info!("");
+    }
+}
+
+impl PolymerStats {
+    /// Summarize the %TIC of a polymer in each of a non-empty set of runs.
+    fn new(name: &str, values: &[f64], files: &[String]) -> Self {
+        let center = median(values.to_vec());
+        let mad = median(values.iter().map(|x| (x - center).abs()).collect());
+        let (max_idx, max) = values
+            .iter()
+            .copied()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+
+        // Fall back to the mean absolute deviation when the MAD is zero,
+        // so that a few contaminated runs in an otherwise clean batch
+        // still stand out:
+        let spread = match mad > 0. {
+            true => MAD_SCALE * mad,
+            false => {
+                let mean_ad =
+                    values.iter().map(|x| (x - center).abs()).sum::<f64>() / values.len() as f64;
+                MEAN_AD_SCALE * mean_ad
+            }
+        };
+
+        let outliers = match values.len() >= MIN_RUNS && spread > 0. {
+            true => values
+                .iter()
+                .zip(files.iter())
+                .map(|(pct, filename)| (pct, filename, (pct - center) / spread))
+                .filter(|x| x.2 > Z_THRESHOLD)
+                .map(|(pct, filename, z_score)| Outlier {
+                    filename: filename.clone(),
+                    percent_tic: *pct,
+                    z_score,
+                })
+                .collect(),
+            false => Vec::new(),
+        };
+
+        Self {
+            name: name.to_string(),
+            median: center,
+            mad,
+            max,
+            max_file: files[max_idx].clone(),
+            outliers,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BatchSummary;
+    use crate::search::PolymerResults;
+
+    fn run(filename: &str, peg: f64, ppg: f64) -> PolymerResults {
+        PolymerResults::from_totals(filename, &[("PEG", peg), ("PPG", ppg)], &[100.])
+    }
+
+    #[test]
+    fn test_batch_summary() {
+        let mut results: Vec<PolymerResults> = (0..9)
+            .map(|i| run(&format!("run{}.mzML", i), 1. + (i % 3) as f64, 0.))
+            .collect();
+        results.push(run("bad.mzML", 50., 5.));
+        let summary = BatchSummary::new(&results);
+
+        assert_eq!(summary.polymers, vec!["PEG", "PPG"]);
+        assert_eq!(summary.percent_tic[1], vec![2., 0.]);
+        assert_eq!(summary.worst_runs[0].filename, "bad.mzML");
+        assert_eq!(summary.worst_runs[0].total_percent_tic, 55.);
+        assert_eq!(summary.worst_runs[0].top_polymer.as_deref(), Some("PEG"));
+        assert_eq!(summary.worst_runs[9].top_polymer.as_deref(), Some("PEG"));
+
+        let peg = &summary.polymer_stats[0];
+        assert_eq!(peg.median, 2.);
+        assert_eq!(peg.mad, 1.);
+        assert_eq!(peg.max, 50.);
+        assert_eq!(peg.max_file, "bad.mzML");
+        assert_eq!(peg.outliers.len(), 1);
+        assert!((peg.outliers[0].z_score - 48. / 1.4826).abs() < 1e-9);
+
+        // PPG is only in one run, so its MAD is zero:
+        let ppg = &summary.polymer_stats[1];
+        assert_eq!(ppg.mad, 0.);
+        assert_eq!(ppg.outliers.len(), 1);
+        assert_eq!(ppg.outliers[0].filename, "bad.mzML");
+    }
+
+    #[test]
+    fn test_small_batch() {
+        let summary = BatchSummary::new(&[run("a.mzML", 0., 0.), run("b.mzML", 10., 0.)]);
+        assert!(summary.polymer_stats.iter().all(|x| x.outliers.is_empty()));
+        assert_eq!(summary.worst_runs[1].top_polymer, None);
+        assert!(BatchSummary::new(&[]).polymer_stats.is_empty());
+    }
+
+    #[test]
+    fn test_clean_run() {
+        // A run with much less PEG than the rest is not an outlier:
+        let peg = [10., 10., 11., 9., 10., 10., 0.];
+        let results: Vec<PolymerResults> = peg
+            .iter()
+            .enumerate()
+            .map(|(i, x)| run(&format!("run{}.mzML", i), *x, 0.))
+            .collect();
+        let summary = BatchSummary::new(&results);
+        assert_eq!(summary.polymer_stats[0].mad, 0.);
+        assert!(summary.polymer_stats[0].outliers.is_empty());
+    }
+}
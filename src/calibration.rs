@@ -0,0 +1,412 @@
+//! Summarize mass errors and estimate a data-driven tolerance.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::search::PolymerResults;
+
+/// The fewest supported matches needed to estimate a tolerance.
+const MIN_MATCHES: usize = 20;
+
+/// The number of standard deviations that the tolerance spans.
+const N_SIGMA: f64 = 4.;
+
+/// Scale the median absolute deviation to estimate a standard deviation.
+pub(crate) const MAD_SCALE: f64 = 1.4826;
+
+/// The observed error of a matched polymer peak.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct MassError {
+    /// The observed minus the theoretical m/z, in Da.
+    pub da: f64,
+    /// The observed minus the theoretical m/z, in ppm.
+    pub ppm: f64,
+    /// The intensity of the matched peak.
+    pub intensity: f64,
+    /// Was the adjacent oligomer also matched in the same spectrum?
+    /// Supported matches are unlikely to be chance matches.
+    pub supported: bool,
+}
+
+impl MassError {
+    pub fn new(theoretical: f64, observed: f64, intensity: f64) -> Self {
+        let da = observed - theoretical;
+        Self {
+            da,
+            ppm: 1_000_000.0 * da / theoretical,
+            intensity,
+            supported: false,
+        }
+    }
+
+    /// The error in the provided unit, either "ppm" or "da".
+    pub fn value(&self, unit: &str) -> f64 {
+        match &unit.to_lowercase()[..] {
+            "da" => self.da,
+            "ppm" => self.ppm,
+            _ => unreachable!("Invalid unit {}", unit),
+        }
+    }
+}
+
+/// A summary of the distribution of mass errors.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ErrorSummary {
+    /// The number of matched peaks.
+    pub n: usize,
+    pub mean: f64,
+    /// The standard deviation.
+    pub sd: f64,
+    pub median: f64,
+    /// The median absolute deviation from the median.
+    pub mad: f64,
+}
+
+impl ErrorSummary {
+    /// Summarize a collection of errors, if there are any.
+    pub fn new(values: &[f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        let n = values.len();
+        let mean = values.iter().sum::<f64>() / n as f64;
+        let sd = match n > 1 {
+            true => {
+                let ss: f64 = values.iter().map(|x| (x - mean).powi(2)).sum();
+                (ss / (n - 1) as f64).sqrt()
+            }
+            false => 0.,
+        };
+        let center = median(values.to_vec());
+        let mad = median(values.iter().map(|x| (x - center).abs()).collect());
+        Some(Self {
+            n,
+            mean,
+            sd,
+            median: center,
+            mad,
+        })
+    }
+}
+
+/// The median of a non-empty collection of values.
+pub(crate) fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_unstable_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    match values.len() % 2 {
+        0 => (values[mid - 1] + values[mid]) / 2.,
+        _ => values[mid],
+    }
+}
+
+/// The width of the bins used to count errors in ppm.
+const PPM_BIN: f64 = 0.01;
+
+/// The width of the bins used to count errors in Da.
+const DA_BIN: f64 = 0.00001;
+
+/// A distribution of errors, counted in fixed-width bins.
+///
+/// Only bins that an error fell into are stored, so memory is bounded by
+/// the search tolerance rather than by the number of errors. The sum of
+/// the errors in each bin is kept too, so the median of errors that share
+/// a bin is exact.
+#[derive(Clone, Debug)]
+pub struct ErrorHistogram {
+    bin_width: f64,
+    n: usize,
+    sum: f64,
+    sum_sq: f64,
+    /// The count and sum of the errors in each bin.
+    bins: BTreeMap<i64, (usize, f64)>,
+}
+
+impl ErrorHistogram {
+    pub fn new(bin_width: f64) -> Self {
+        Self {
+            bin_width,
+            n: 0,
+            sum: 0.,
+            sum_sq: 0.,
+            bins: BTreeMap::new(),
+        }
+    }
+
+    /// Count an error.
+    pub fn push(&mut self, value: f64) {
+        self.n += 1;
+        self.sum += value;
+        self.sum_sq += value * value;
+        let bin = self
+            .bins
+            .entry((value / self.bin_width).floor() as i64)
+            .or_insert((0, 0.));
+        bin.0 += 1;
+        bin.1 += value;
+    }
+
+    /// Add the errors from another histogram with the same bins.
+    pub fn merge(&mut self, other: &Self) {
+        self.n += other.n;
+        self.sum += other.sum;
+        self.sum_sq += other.sum_sq;
+        for (key, (count, sum)) in other.bins.iter() {
+            let bin = self.bins.entry(*key).or_insert((0, 0.));
+            bin.0 += count;
+            bin.1 += sum;
+        }
+    }
+
+    /// The number of errors.
+    pub fn len(&self) -> usize {
+        self.n
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    /// Summarize the errors, if there are any.
+    ///
+    /// The mean and standard deviation are exact, and the median and MAD
+    /// use the mean error of each bin.
+    pub fn summary(&self) -> Option<ErrorSummary> {
+        if self.n == 0 {
+            return None;
+        }
+        let n = self.n as f64;
+        let mean = self.sum / n;
+        let sd = match self.n > 1 {
+            true => ((self.sum_sq - n * mean * mean).max(0.) / (n - 1.)).sqrt(),
+            false => 0.,
+        };
+
+        let values: Vec<(f64, usize)> = self
+            .bins
+            .values()
+            .map(|(count, sum)| (sum / *count as f64, *count))
+            .collect();
+        let center = weighted_median(values.clone());
+        let mad = weighted_median(
+            values
+                .into_iter()
+                .map(|(x, count)| ((x - center).abs(), count))
+                .collect(),
+        );
+        Some(ErrorSummary {
+            n: self.n,
+            mean,
+            sd,
+            median: center,
+            mad,
+        })
+    }
+}
+
+/// The median of a non-empty collection of values, each with a count.
+fn weighted_median(mut values: Vec<(f64, usize)>) -> f64 {
+    values.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+    let n: usize = values.iter().map(|x| x.1).sum();
+    let nth = |k: usize| {
+        let mut seen = 0;
+        for (value, count) in values.iter() {
+            seen += count;
+            if seen > k {
+                return *value;
+            }
+        }
+        unreachable!("The rank is out of bounds.")
+    };
+    match n % 2 {
+        0 => (nth(n / 2 - 1) + nth(n / 2)) / 2.,
+        _ => nth(n / 2),
+    }
+}
+
+/// The mass errors of the matched peaks of a polymer, in ppm and Da.
+///
+/// Errors are counted in histograms rather than kept individually, so
+/// memory does not grow with the length of a run. Supported errors are
+/// also counted separately to estimate the calibration.
+#[derive(Clone, Debug)]
+pub struct MassErrors {
+    ppm: ErrorHistogram,
+    da: ErrorHistogram,
+    supported_ppm: ErrorHistogram,
+    supported_da: ErrorHistogram,
+}
+
+impl Default for MassErrors {
+    fn default() -> Self {
+        Self {
+            ppm: ErrorHistogram::new(PPM_BIN),
+            da: ErrorHistogram::new(DA_BIN),
+            supported_ppm: ErrorHistogram::new(PPM_BIN),
+            supported_da: ErrorHistogram::new(DA_BIN),
+        }
+    }
+}
+
+impl MassErrors {
+    /// Count the error of a matched peak.
+    pub fn push(&mut self, error: &MassError) {
+        self.ppm.push(error.ppm);
+        self.da.push(error.da);
+        if error.supported {
+            self.supported_ppm.push(error.ppm);
+            self.supported_da.push(error.da);
+        }
+    }
+
+    /// Add the errors counted by another polymer.
+    pub fn merge(&mut self, other: &Self) {
+        self.ppm.merge(&other.ppm);
+        self.da.merge(&other.da);
+        self.supported_ppm.merge(&other.supported_ppm);
+        self.supported_da.merge(&other.supported_da);
+    }
+
+    /// The number of matched peaks.
+    pub fn len(&self) -> usize {
+        self.ppm.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ppm.is_empty()
+    }
+
+    /// The number of matched peaks whose adjacent oligomer also matched.
+    pub fn n_supported(&self) -> usize {
+        self.supported_ppm.len()
+    }
+
+    /// The errors in the provided unit, either "ppm" or "da".
+    pub fn errors(&self, unit: &str) -> &ErrorHistogram {
+        match &unit.to_lowercase()[..] {
+            "da" => &self.da,
+            "ppm" => &self.ppm,
+            _ => unreachable!("Invalid unit {}", unit),
+        }
+    }
+
+    /// The supported errors in the provided unit, either "ppm" or "da".
+    pub fn supported(&self, unit: &str) -> &ErrorHistogram {
+        match &unit.to_lowercase()[..] {
+            "da" => &self.supported_da,
+            "ppm" => &self.supported_ppm,
+            _ => unreachable!("Invalid unit {}", unit),
+        }
+    }
+}
+
+/// A systematic mass offset and tolerance estimated from polymer matches.
+#[derive(Clone, Debug)]
+pub struct Calibration {
+    /// The systematic offset of observed m/z values.
+    pub offset: f64,
+    /// The tolerance around the offset m/z values.
+    pub tol: f64,
+    /// The unit of the offset and tolerance, either "ppm" or "da".
+    pub unit: String,
+    /// The number of matches used to estimate the calibration.
+    pub n: usize,
+}
+
+impl Calibration {
+    /// Estimate the calibration from the supported matches of a search.
+    ///
+    /// The offset is the median error and the tolerance spans four robust
+    /// standard deviations, estimated from the median absolute deviation.
+    /// Returns `None` if there are too few supported matches.
+    pub fn estimate(results: &PolymerResults, unit: &str) -> Option<Self> {
+        let mut errors = MassErrors::default();
+        for poly in results.polymers.iter() {
+            errors.merge(&poly.mass_errors);
+        }
+
+        if errors.n_supported() < MIN_MATCHES {
+            return None;
+        }
+
+        let summary = errors.supported(unit).summary()?;
+        let min_tol = match &unit.to_lowercase()[..] {
+            "da" => 0.001,
+            _ => 1.,
+        };
+        Some(Self {
+            offset: summary.median,
+            tol: (N_SIGMA * MAD_SCALE * summary.mad).max(min_tol),
+            unit: unit.to_string(),
+            n: summary.n,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Calibration, ErrorHistogram, ErrorSummary, MassError, MassErrors};
+    use crate::search::{PolymerResult, PolymerResults};
+
+    #[test]
+    fn test_summary() {
+        let summary = ErrorSummary::new(&[1., 2., 3., 10.]).unwrap();
+        assert_eq!(summary.n, 4);
+        assert_eq!(summary.mean, 4.);
+        assert_eq!(summary.median, 2.5);
+        assert_eq!(summary.mad, 1.);
+        assert!(ErrorSummary::new(&[]).is_none());
+
+        let error = MassError::new(500., 500.001, 10.);
+        assert!((error.ppm - 2.).abs() < 1e-6);
+        assert!((error.value("da") - 0.001).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_histogram() {
+        let values = [1., 2., 3., 10., -0.5, 2.];
+        let mut hist = ErrorHistogram::new(0.01);
+        values.iter().for_each(|x| hist.push(*x));
+        let summary = hist.summary().unwrap();
+        let expected = ErrorSummary::new(&values).unwrap();
+        assert_eq!(summary.n, expected.n);
+        assert!((summary.mean - expected.mean).abs() < 1e-9);
+        assert!((summary.sd - expected.sd).abs() < 1e-9);
+        assert!((summary.median - expected.median).abs() < 1e-9);
+        assert!((summary.mad - expected.mad).abs() < 1e-9);
+        assert!(ErrorHistogram::new(0.01).summary().is_none());
+
+        // Memory is bounded by the range of the errors:
+        let mut hist = ErrorHistogram::new(0.01);
+        (0..100_000).for_each(|i| hist.push(((i % 1000) as f64 + 0.5) / 100.));
+        assert_eq!(hist.len(), 100_000);
+        assert_eq!(hist.bins.len(), 1000);
+
+        let mut merged = ErrorHistogram::new(0.01);
+        merged.merge(&hist);
+        merged.merge(&hist);
+        assert_eq!(merged.len(), 200_000);
+        assert_eq!(merged.bins.len(), 1000);
+    }
+
+    #[test]
+    fn test_calibration() {
+        let mut results = PolymerResults::new("test".to_string(), &[]);
+        let mut poly = PolymerResult::new("PEG");
+        for i in 0..40 {
+            poly.mass_errors.push(&MassError {
+                ppm: 3. + (i % 5) as f64 - 2.,
+                supported: i < 30,
+                ..Default::default()
+            });
+        }
+        results.polymers.push(poly);
+
+        let calibration = Calibration::estimate(&results, "ppm").unwrap();
+        assert_eq!(calibration.n, 30);
+        assert_eq!(calibration.offset, 3.);
+        assert!((calibration.tol - 4. * 1.4826).abs() < 1e-9);
+
+        results.polymers[0].mass_errors = MassErrors::default();
+        assert!(Calibration::estimate(&results, "ppm").is_none());
+    }
+}
@@ -0,0 +1,186 @@
+//! Find chromatographic peaks in extracted ion chromatograms (XICs).
+use serde::{Deserialize, Serialize};
+
+/// Settings for finding peaks in an XIC.
+#[derive(Debug, Clone, Copy)]
+pub struct PeakSettings {
+    /// The number of scans on each side of a scan to average when
+    /// smoothing the XIC.
+    pub half_window: usize,
+    /// The minimum height of a peak, relative to the most intense point of
+    /// the smoothed XIC.
+    pub min_height: f64,
+    /// A peak ends where the smoothed XIC falls below this fraction of its
+    /// apex, or where it starts rising again.
+    pub boundary: f64,
+}
+
+impl Default for PeakSettings {
+    fn default() -> Self {
+        Self {
+            half_window: 2,
+            min_height: 0.1,
+            boundary: 0.05,
+        }
+    }
+}
+
+/// A peak in an XIC.
+///
+/// Retention times are in the units of the mzML file, which are usually
+/// minutes.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChromPeak {
+    /// The retention time of the most intense point of the smoothed XIC.
+    pub apex_rt: f64,
+    /// The intensity of the smoothed XIC at the apex.
+    pub apex_intensity: f64,
+    /// The retention time where the peak starts.
+    pub start_rt: f64,
+    /// The retention time where the peak ends.
+    pub end_rt: f64,
+    /// The full width of the peak at half of its maximum.
+    pub fwhm: f64,
+    /// The area under the XIC between the start and end of the peak.
+    pub area: f64,
+}
+
+/// Smooth an XIC with a centered moving average.
+pub fn smooth(xic: &[f64], half_window: usize) -> Vec<f64> {
+    (0..xic.len())
+        .map(|idx| {
+            let start = idx.saturating_sub(half_window);
+            let end = (idx + half_window + 1).min(xic.len());
+            xic[start..end].iter().sum::<f64>() / (end - start) as f64
+        })
+        .collect()
+}
+
+/// Find the peaks in an XIC, most intense first.
+///
+/// The XIC is smoothed before finding its local maxima. Local maxima that
+/// fall within a more intense peak are not reported.
+pub fn pick_peaks(ret_times: &[f64], xic: &[f64], settings: &PeakSettings) -> Vec<ChromPeak> {
+    let smoothed = smooth(xic, settings.half_window);
+    let max_height = smoothed.iter().copied().fold(0., f64::max);
+    if max_height <= 0. {
+        return Vec::new();
+    }
+
+    let mut apexes: Vec<usize> = (0..smoothed.len())
+        .filter(|idx| {
+            let height = smoothed[*idx];
+            let left = idx.checked_sub(1).map_or(0., |x| smoothed[x]);
+            let right = smoothed.get(idx + 1).copied().unwrap_or(0.);
+            height >= settings.min_height * max_height && height > left && height >= right
+        })
+        .collect();
+    apexes.sort_by(|a, b| smoothed[*b].total_cmp(&smoothed[*a]));
+
+    let mut peaks: Vec<(usize, usize, ChromPeak)> = Vec::new();
+    for apex in apexes {
+        if peaks
+            .iter()
+            .any(|(start, end, _)| *start <= apex && apex <= *end)
+        {
+            continue;
+        }
+
+        let height = smoothed[apex];
+        let floor = settings.boundary * height;
+        let mut start = apex;
+        while start > 0 && smoothed[start - 1] > floor && smoothed[start - 1] <= smoothed[start] {
+            start -= 1;
+        }
+        let mut end = apex;
+        while end + 1 < smoothed.len()
+            && smoothed[end + 1] > floor
+            && smoothed[end + 1] <= smoothed[end]
+        {
+            end += 1;
+        }
+
+        let area = (start..end)
+            .map(|idx| (ret_times[idx + 1] - ret_times[idx]) * (xic[idx] + xic[idx + 1]) / 2.)
+            .sum();
+
+        let peak = ChromPeak {
+            apex_rt: ret_times[apex],
+            apex_intensity: height,
+            start_rt: ret_times[start],
+            end_rt: ret_times[end],
+            fwhm: half_max_rt(ret_times, &smoothed, apex, end, 1)
+                - half_max_rt(ret_times, &smoothed, apex, start, -1),
+            area,
+        };
+        peaks.push((start, end, peak));
+    }
+
+    peaks.into_iter().map(|(_, _, peak)| peak).collect()
+}
+
+/// The retention time where the smoothed XIC falls to half of the apex,
+/// searching from the apex towards `limit` in the `step` direction.
+///
+/// If the XIC doesn't fall to half of the apex before `limit`, the
+/// retention time of `limit` is used.
+fn half_max_rt(ret_times: &[f64], smoothed: &[f64], apex: usize, limit: usize, step: isize) -> f64 {
+    let half = smoothed[apex] / 2.;
+    let mut idx = apex;
+    while idx != limit {
+        let next = (idx as isize + step) as usize;
+        if smoothed[next] <= half {
+            // Interpolate between the two points:
+            let frac = (smoothed[idx] - half) / (smoothed[idx] - smoothed[next]);
+            return ret_times[idx] + frac * (ret_times[next] - ret_times[idx]);
+        }
+        idx = next;
+    }
+    ret_times[limit]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pick_peaks, smooth, PeakSettings};
+
+    #[test]
+    fn test_smooth() {
+        let smoothed = smooth(&[0., 3., 0., 3., 0.], 1);
+        assert_eq!(smoothed, vec![1.5, 1., 2., 1., 1.5]);
+        assert_eq!(smooth(&[1., 2.], 0), vec![1., 2.]);
+    }
+
+    #[test]
+    fn test_pick_peaks() {
+        // A sharp early peak and a broad, shorter late hump:
+        let ret_times: Vec<f64> = (0..40).map(|x| x as f64 * 0.5).collect();
+        let xic: Vec<f64> = ret_times
+            .iter()
+            .map(|rt| {
+                100. * (-(rt - 3.).powi(2) / (2. * 0.5f64.powi(2))).exp()
+                    + 40. * (-(rt - 14.).powi(2) / (2. * 2f64.powi(2))).exp()
+            })
+            .collect();
+
+        let settings = PeakSettings {
+            half_window: 0,
+            ..Default::default()
+        };
+        let peaks = pick_peaks(&ret_times, &xic, &settings);
+        assert_eq!(peaks.len(), 2);
+
+        let (sharp, broad) = (&peaks[0], &peaks[1]);
+        assert_eq!(sharp.apex_rt, 3.);
+        assert_eq!(broad.apex_rt, 14.);
+        assert!((sharp.fwhm - 2.355 * 0.5).abs() < 0.2);
+        assert!((broad.fwhm - 2.355 * 2.).abs() < 0.2);
+
+        // The areas of Gaussian peaks are height * sigma * sqrt(2 * pi),
+        // less the tails beyond the peak boundaries:
+        assert!((sharp.area / (100. * 0.5 * 2.5066) - 1.).abs() < 0.1);
+        assert!((broad.area / (40. * 2. * 2.5066) - 1.).abs() < 0.1);
+        assert!(sharp.start_rt < 3. && sharp.end_rt > 3.);
+
+        assert!(pick_peaks(&ret_times, &[0.; 40], &settings).is_empty());
+    }
+}
@@ -4,26 +4,43 @@ use crate::polymer::Polymer;
 #[derive(Debug)]
 pub struct DefaultPolymers(pub Vec<Polymer>);
 
+/// The default adducts of a protonated polymer, as well as `[M+Na]+`.
+const SODIATED: &[&str] = &["[M+H]+", "[M+Na]+", "[M-H]-", "[M+Cl]-", "[M+HCOO]-"];
+
+/// Polysorbates are only found as sodium adducts.
+const SODIUM_ONLY: &[&str] = &["[M+Na]+"];
+
 impl DefaultPolymers {
     pub fn new() -> Self {
-        let polys = vec![
-            Polymer::new("PEG+1H", "H2O", "C2H4O", 1, true),
-            Polymer::new("PEG+2H", "H2O", "C2H4O", 2, true),
-            Polymer::new("PEG+3H", "H2O", "C2H4O", 3, true),
-            Polymer::new("PPG", "H2O", "C3H6O", 1, true),
-            Polymer::new("Triton X-100", "C14H22O", "C2H4O", 1, true),
-            Polymer::new("Triton X-100 (Reduced)", "C14H28O", "C2H4O", 1, true),
-            Polymer::new("Triton X-100 (Na)", "C14H22ONa", "C2H4O", 1, false),
-            Polymer::new("Triton X-100 (Reduced, Na)", "C14H28ONa", "C2H4O", 1, false),
-            Polymer::new("Triton X-101", "C15H24O", "C2H4O", 1, true),
-            Polymer::new("Triton X-101 (Reduced)", "C15H30O", "C2H4O", 1, true),
-            Polymer::new("Polysiloxane", "", "C2H6SiO", 1, true),
-            Polymer::new("Tween-20", "C18H34O6Na", "C2H4O", 1, false),
-            Polymer::new("Tween-40", "C22H42O6Na", "C2H4O", 1, false),
-            Polymer::new("Tween-60", "C24H46O6Na", "C2H4O", 1, false),
-            Polymer::new("Tween-80", "C24H44O6Na", "C2H4O", 1, false),
-            Polymer::new("IGEPAL CA-630 (NP-40)", "C15H24O", "C2H4O", 1, true),
+        // An empty list of adducts uses the defaults for the charge.
+        let polys: [(&str, &str, &str, i32, &[&str]); 14] = [
+            ("PEG+1H", "H2O", "C2H4O", 1, &[]),
+            ("PEG+2H", "H2O", "C2H4O", 2, &[]),
+            ("PEG+3H", "H2O", "C2H4O", 3, &[]),
+            ("PPG", "H2O", "C3H6O", 1, &[]),
+            ("Triton X-100", "C14H22O", "C2H4O", 1, SODIATED),
+            ("Triton X-100 (Reduced)", "C14H28O", "C2H4O", 1, SODIATED),
+            ("Triton X-101", "C15H24O", "C2H4O", 1, &[]),
+            ("Triton X-101 (Reduced)", "C15H30O", "C2H4O", 1, &[]),
+            ("Polysiloxane", "", "C2H6SiO", 1, &[]),
+            ("Tween-20", "C18H34O6", "C2H4O", 1, SODIUM_ONLY),
+            ("Tween-40", "C22H42O6", "C2H4O", 1, SODIUM_ONLY),
+            ("Tween-60", "C24H46O6", "C2H4O", 1, SODIUM_ONLY),
+            ("Tween-80", "C24H44O6", "C2H4O", 1, SODIUM_ONLY),
+            ("IGEPAL CA-630 (NP-40)", "C15H24O", "C2H4O", 1, &[]),
         ];
+        let polys = polys
+            .into_iter()
+            .map(|(name, core, rep, charge, adducts)| {
+                let adducts = adducts
+                    .iter()
+                    .map(|x| x.parse().expect("Invalid default adduct."))
+                    .collect();
+                Polymer::new(name, core, rep, charge, true)
+                    .expect("Invalid default polymer.")
+                    .with_adducts(adducts)
+            })
+            .collect();
         Self(polys)
     }
 }
@@ -37,9 +54,32 @@ impl Default for DefaultPolymers {
 #[cfg(test)]
 mod tests {
     use super::DefaultPolymers;
+    use crate::polymer::Polymer;
 
     #[test]
     fn test_smoke() {
         let _ = DefaultPolymers::new();
     }
+
+    #[test]
+    fn test_sodium_adducts() {
+        let polymers = DefaultPolymers::new().0;
+        assert!(!polymers.iter().any(|x| x.name.contains("(Na)")));
+
+        let triton = polymers.iter().find(|x| x.name == "Triton X-100").unwrap();
+        let names: Vec<String> = triton.adducts().into_iter().map(|x| x.name).collect();
+        assert_eq!(names[..2], ["[M+H]+", "[M+Na]+"]);
+
+        // The same ions as a sodium-containing core with an intrinsic charge:
+        let mut tween = polymers.into_iter().find(|x| x.name == "Tween-20").unwrap();
+        let mut sodiated = Polymer::new("Tween-20", "C18H34O6Na", "C2H4O", 1, false).unwrap();
+        tween.calculate_bounds(&1000., &10., "ppm");
+        sodiated.calculate_bounds(&1000., &10., "ppm");
+        let mz = |poly: &Polymer| poly.series.as_ref().unwrap()[0].precursors.clone();
+        let (expected, observed) = (mz(&sodiated), mz(&tween));
+        assert_eq!(expected.len(), observed.len());
+        for (a, b) in expected.iter().zip(observed.iter()) {
+            assert!((a - b).abs() < 1e-9);
+        }
+    }
 }
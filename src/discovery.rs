@@ -0,0 +1,495 @@
+//! Find homologous series of unknown polymers.
+//!
+//! The MS1 peaks from every spectrum are pooled, then the m/z differences
+//! between pairs of peaks are counted to find repeat unit candidates. Peaks
+//! that are linked by a candidate repeat form a homologous series, which
+//! all share a Kendrick mass defect. The charge of each series is inferred
+//! from the spacing of its isotope peaks.
+use std::collections::HashMap;
+
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+
+use crate::mass::{Adduct, ISOTOPE_SPACING};
+use crate::mzml::{MzMLError, Polarity, Spectrum};
+use crate::polymer::Polymer;
+
+/// The width of the bins used to count repeat unit candidates, in Da.
+const DELTA_BIN: f64 = 0.005;
+
+/// Settings for finding unknown polymers.
+#[derive(Debug, Clone)]
+pub struct DiscoverySettings {
+    /// The m/z tolerance.
+    pub tol: f64,
+    /// The unit of the tolerance, either "ppm" or "da".
+    pub unit: String,
+    /// The smallest repeat unit mass to consider.
+    pub min_repeat: f64,
+    /// The largest repeat unit mass to consider.
+    pub max_repeat: f64,
+    /// The largest charge to consider.
+    pub max_charge: i32,
+    /// The fewest peaks that make up a series.
+    pub min_members: usize,
+    /// The number of most intense peaks to use from each spectrum.
+    pub peaks_per_spectrum: usize,
+    /// The number of most intense pooled peaks to compare.
+    pub max_peaks: usize,
+    /// The number of repeat unit candidates to test.
+    pub max_candidates: usize,
+}
+
+impl Default for DiscoverySettings {
+    fn default() -> Self {
+        Self {
+            tol: 10.,
+            unit: "ppm".to_string(),
+            min_repeat: 14.,
+            max_repeat: 200.,
+            max_charge: 3,
+            min_members: 5,
+            peaks_per_spectrum: 200,
+            max_peaks: 2000,
+            max_candidates: 20,
+        }
+    }
+}
+
+impl DiscoverySettings {
+    /// The tolerance around an m/z value, in Da.
+    fn tol_at(&self, mz: f64) -> f64 {
+        match &self.unit.to_lowercase()[..] {
+            "da" => self.tol,
+            "ppm" => self.tol * mz / 1_000_000.0,
+            _ => unreachable!("Invalid unit {}", self.unit),
+        }
+    }
+}
+
+/// A candidate homologous series of an unknown polymer.
+#[derive(Serialize, Debug, Clone)]
+pub struct CandidateSeries {
+    /// The mass of the repeat unit.
+    pub repeat_mass: f64,
+    /// The charge of the ions.
+    pub charge: i32,
+    /// The polarity of the spectra the series was found in.
+    #[serde(skip)]
+    pub polarity: Polarity,
+    /// The mean Kendrick mass defect of the members, using the repeat
+    /// unit as the Kendrick base.
+    pub kendrick_mass_defect: f64,
+    /// The m/z of each member of the series.
+    pub members: Vec<f64>,
+    /// The summed intensity of the members.
+    pub intensity: f64,
+    /// The summed intensity as a percentage of the total ion current.
+    pub percent_tic: f64,
+}
+
+/// The candidate series found in one file.
+#[derive(Serialize, Debug, Clone)]
+pub struct DiscoveryResults {
+    pub filename: String,
+    pub candidates: Vec<CandidateSeries>,
+}
+
+impl CandidateSeries {
+    /// A default name for the series, such as `Unknown 44.0262 (1+)`.
+    pub fn name(&self) -> String {
+        let sign = match self.polarity {
+            Polarity::Negative => "-",
+            _ => "+",
+        };
+        format!("Unknown {:.4} ({}{})", self.repeat_mass, self.charge, sign)
+    }
+
+    /// A polymer that can be used to search for this series.
+    ///
+    /// The composition of the polymer is unknown, so it is defined by the
+    /// mass of its repeat unit and the mass left over from its lightest
+    /// member.
+    pub fn to_polymer(&self, name: &str) -> Polymer {
+        let adduct = match self.polarity {
+            Polarity::Negative => Adduct::deprotonated(self.charge),
+            _ => Adduct::protonated(self.charge),
+        };
+        let mass = adduct.neutral_mass(self.members[0]);
+        let core_mass = mass - (mass / self.repeat_mass).floor() * self.repeat_mass;
+        let mut poly = Polymer::from_masses(name, core_mass, self.repeat_mass, self.charge, true)
+            .with_adducts(vec![adduct]);
+        poly.description = Some(format!(
+            "Discovered by mzsniffer with {} members and a Kendrick mass defect of {:.4}.",
+            self.members.len(),
+            self.kendrick_mass_defect,
+        ));
+        poly
+    }
+}
+
+/// The Kendrick mass defect of a mass, using a repeat unit as the base.
+pub fn kendrick_mass_defect(mass: f64, rep_mass: f64) -> f64 {
+    let kendrick_mass = mass * rep_mass.round() / rep_mass;
+    kendrick_mass.round() - kendrick_mass
+}
+
+/// The least-squares spacing of consecutive members of a series.
+fn fit_step(members: &[f64]) -> Option<f64> {
+    let n = members.len() as f64;
+    let mean_idx = (n - 1.) / 2.;
+    let mean_mz = members.iter().sum::<f64>() / n;
+    let (cov, var) = members
+        .iter()
+        .enumerate()
+        .fold((0., 0.), |(cov, var), (idx, mz)| {
+            let dx = idx as f64 - mean_idx;
+            (cov + dx * (mz - mean_mz), var + dx * dx)
+        });
+    match var > 0. {
+        true => Some(cov / var),
+        false => None,
+    }
+}
+
+/// A pooled MS1 peak.
+#[derive(Debug, Clone, Copy)]
+struct Peak {
+    mz: f64,
+    intensity: f64,
+}
+
+/// Pool MS1 peaks and find homologous series among them.
+pub struct Discovery {
+    settings: DiscoverySettings,
+    /// The pooled peaks for each polarity, as binned (m/z * intensity, intensity).
+    bins: HashMap<(bool, i64), (f64, f64)>,
+    /// The total ion current for each polarity.
+    tic: HashMap<bool, f64>,
+}
+
+impl Discovery {
+    pub fn new(settings: DiscoverySettings) -> Self {
+        Self {
+            settings,
+            bins: HashMap::new(),
+            tic: HashMap::new(),
+        }
+    }
+
+    /// Add the most intense peaks of a spectrum to the pool.
+    pub fn push(&mut self, spectrum: &Spectrum) {
+        if spectrum.ms_level != 1 {
+            return;
+        }
+        let negative = spectrum.polarity == Polarity::Negative;
+        *self.tic.entry(negative).or_default() += spectrum.total_ion_current;
+
+        let mut peaks: Vec<(f64, f64)> = spectrum
+            .mz
+            .iter()
+            .copied()
+            .zip(spectrum.intensity.iter().copied())
+            .filter(|(mz, intensity)| *mz > 0. && *intensity > 0.)
+            .collect();
+        peaks.sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+        peaks.truncate(self.settings.peaks_per_spectrum);
+
+        for (mz, intensity) in peaks {
+            let entry = self.bins.entry((negative, self.bin(mz))).or_default();
+            entry.0 += mz * intensity;
+            entry.1 += intensity;
+        }
+    }
+
+    /// The pooling bin of an m/z value. Bins are one tolerance wide.
+    fn bin(&self, mz: f64) -> i64 {
+        match &self.settings.unit.to_lowercase()[..] {
+            "da" => (mz / self.settings.tol).round() as i64,
+            "ppm" => (mz.ln() / (self.settings.tol / 1_000_000.0)).round() as i64,
+            _ => unreachable!("Invalid unit {}", self.settings.unit),
+        }
+    }
+
+    /// Find the homologous series in the pooled peaks, most intense first.
+    pub fn finish(&self) -> Vec<CandidateSeries> {
+        let mut candidates = Vec::new();
+        for negative in [false, true] {
+            let mut peaks: Vec<Peak> = self
+                .bins
+                .iter()
+                .filter(|((neg, _), _)| *neg == negative)
+                .map(|(_, (weighted, intensity))| Peak {
+                    mz: weighted / intensity,
+                    intensity: *intensity,
+                })
+                .collect();
+
+            if peaks.is_empty() {
+                continue;
+            }
+
+            peaks.sort_unstable_by(|a, b| b.intensity.total_cmp(&a.intensity));
+            peaks.truncate(self.settings.max_peaks);
+            peaks.sort_unstable_by(|a, b| a.mz.total_cmp(&b.mz));
+
+            let polarity = match negative {
+                true => Polarity::Negative,
+                false => Polarity::Positive,
+            };
+            let tic = self.tic.get(&negative).copied().unwrap_or_default();
+            candidates.extend(self.find_series(&peaks, polarity, tic));
+        }
+        candidates
+    }
+
+    /// Find homologous series among peaks sorted by m/z.
+    fn find_series(&self, peaks: &[Peak], polarity: Polarity, tic: f64) -> Vec<CandidateSeries> {
+        let mut chains: Vec<(f64, i32, Vec<usize>)> = Vec::new();
+        for step in self.repeat_deltas(peaks) {
+            for chain in self.chains(peaks, step) {
+                let charge = self.charge(peaks, &chain);
+                chains.push((step, charge, chain));
+            }
+        }
+
+        let intensity = |chain: &[usize]| chain.iter().map(|i| peaks[*i].intensity).sum::<f64>();
+        chains.sort_by(|a, b| intensity(&b.2).total_cmp(&intensity(&a.2)));
+
+        // Keep the most intense series first. A series is redundant if
+        // most of its peaks belong to, or are isotopes of, a kept series.
+        let mut claimed = vec![false; peaks.len()];
+        let mut series = Vec::new();
+        for (step, charge, chain) in chains {
+            let n_claimed = chain.iter().filter(|i| claimed[**i]).count();
+            if 2 * n_claimed >= chain.len() {
+                continue;
+            }
+
+            for i in chain.iter() {
+                claimed[*i] = true;
+                for isotope in 1..=3 {
+                    let mz = peaks[*i].mz + isotope as f64 * ISOTOPE_SPACING / charge as f64;
+                    if let Some(j) = self.nearest(peaks, mz) {
+                        claimed[j] = true;
+                    }
+                }
+            }
+
+            let members: Vec<f64> = chain.iter().map(|i| peaks[*i].mz).collect();
+            let repeat_mass = fit_step(&members).unwrap_or(step) * charge as f64;
+            let kendrick_mass_defect = members
+                .iter()
+                .map(|mz| kendrick_mass_defect(mz * charge as f64, repeat_mass))
+                .sum::<f64>()
+                / members.len() as f64;
+            let total = intensity(&chain);
+
+            series.push(CandidateSeries {
+                repeat_mass,
+                charge,
+                polarity,
+                kendrick_mass_defect,
+                members,
+                intensity: total,
+                percent_tic: match tic > 0. {
+                    true => 100. * total / tic,
+                    false => 0.,
+                },
+            });
+        }
+        series
+    }
+
+    /// The most frequent m/z differences between peaks.
+    fn repeat_deltas(&self, peaks: &[Peak]) -> Vec<f64> {
+        let min_delta = self.settings.min_repeat / self.settings.max_charge as f64;
+        let max_delta = self.settings.max_repeat;
+
+        let mut counts: HashMap<i64, (usize, f64)> = HashMap::new();
+        for (i, peak) in peaks.iter().enumerate() {
+            for other in peaks[i + 1..].iter() {
+                let delta = other.mz - peak.mz;
+                if delta > max_delta {
+                    break;
+                }
+                if delta < min_delta {
+                    continue;
+                }
+                let entry = counts
+                    .entry((delta / DELTA_BIN).round() as i64)
+                    .or_default();
+                entry.0 += 1;
+                entry.1 += delta;
+            }
+        }
+
+        // Combine each bin with its neighbors, since a delta may straddle two:
+        let mut bins: Vec<(i64, usize, f64)> = counts
+            .keys()
+            .map(|bin| {
+                let (count, total) = (bin - 1..=bin + 1)
+                    .filter_map(|x| counts.get(&x))
+                    .fold((0, 0.), |acc, x| (acc.0 + x.0, acc.1 + x.1));
+                (*bin, count, total / count as f64)
+            })
+            .filter(|(_, count, _)| *count + 1 >= self.settings.min_members)
+            .collect();
+        bins.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let mut deltas: Vec<(i64, f64)> = Vec::new();
+        for (bin, _, delta) in bins {
+            if deltas.len() >= self.settings.max_candidates {
+                break;
+            }
+            if deltas.iter().all(|(other, _)| (other - bin).abs() > 2) {
+                deltas.push((bin, delta));
+            }
+        }
+        deltas.into_iter().map(|(_, delta)| delta).collect()
+    }
+
+    /// Link peaks that are separated by `step` into chains.
+    fn chains(&self, peaks: &[Peak], step: f64) -> Vec<Vec<usize>> {
+        let mut visited = vec![false; peaks.len()];
+        let mut chains = Vec::new();
+        for start in 0..peaks.len() {
+            if visited[start] {
+                continue;
+            }
+            let mut chain = vec![start];
+            visited[start] = true;
+            let mut current = start;
+            while let Some(next) = self.nearest(peaks, peaks[current].mz + step) {
+                if visited[next] {
+                    break;
+                }
+                visited[next] = true;
+                chain.push(next);
+                current = next;
+            }
+
+            if chain.len() >= self.settings.min_members {
+                chains.push(chain);
+            }
+        }
+        chains
+    }
+
+    /// Infer the charge of a chain from the spacing of its isotope peaks.
+    ///
+    /// The isotope peaks of a 2+ ion are also 1 Da apart, so the largest
+    /// charge with isotope peaks for at least half the members is chosen.
+    fn charge(&self, peaks: &[Peak], chain: &[usize]) -> i32 {
+        (2..=self.settings.max_charge)
+            .rev()
+            .find(|charge| {
+                let spacing = ISOTOPE_SPACING / *charge as f64;
+                let n_isotopes = chain
+                    .iter()
+                    .filter(|i| self.nearest(peaks, peaks[**i].mz + spacing).is_some())
+                    .count();
+                2 * n_isotopes >= chain.len()
+            })
+            .unwrap_or(1)
+    }
+
+    /// The most intense peak within the tolerance of an m/z value.
+    fn nearest(&self, peaks: &[Peak], mz: f64) -> Option<usize> {
+        let tol = self.settings.tol_at(mz);
+        let start = peaks.partition_point(|x| x.mz < mz - tol);
+        peaks[start..]
+            .iter()
+            .enumerate()
+            .take_while(|(_, x)| x.mz <= mz + tol)
+            .max_by(|a, b| a.1.intensity.total_cmp(&b.1.intensity))
+            .map(|(i, _)| start + i)
+    }
+}
+
+/// Find homologous series in spectra as they are read.
+pub async fn discover_stream<S>(
+    spectra: S,
+    settings: DiscoverySettings,
+) -> Result<Vec<CandidateSeries>, MzMLError>
+where
+    S: Stream<Item = Result<Spectrum, MzMLError>>,
+{
+    let mut discovery = Discovery::new(settings);
+    futures::pin_mut!(spectra);
+    while let Some(spectrum) = spectra.next().await {
+        discovery.push(&spectrum?);
+    }
+    Ok(discovery.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{kendrick_mass_defect, Discovery, DiscoverySettings};
+    use crate::mass::{formula_mass, Adduct};
+    use crate::mzml::Spectrum;
+    use crate::polymer::Polymer;
+
+    /// A spectrum with PEG ions, their M+1 isotope peaks, and some noise.
+    fn peg_spectrum(charge: i32) -> Spectrum {
+        let adduct = Adduct::protonated(charge);
+        let mut peaks: Vec<(f64, f64)> = (5..20)
+            .flat_map(|n| {
+                let mz = adduct
+                    .mz(formula_mass("H2O").unwrap() + n as f64 * formula_mass("C2H4O").unwrap());
+                [(mz, 1000.), (mz + 1.00336 / charge as f64, 400.)]
+            })
+            .collect();
+        peaks.extend([(301.1234, 500.), (415.9876, 300.), (522.2222, 200.)]);
+        peaks.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let (mz, intensity): (Vec<f64>, Vec<f64>) = peaks.into_iter().unzip();
+        Spectrum {
+            ms_level: 1,
+            total_ion_current: intensity.iter().sum(),
+            mz,
+            intensity,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_kendrick_mass_defect() {
+        let rep_mass = formula_mass("C2H4O").unwrap();
+        let a = kendrick_mass_defect(formula_mass("H2O").unwrap() + 5. * rep_mass, rep_mass);
+        let b = kendrick_mass_defect(formula_mass("H2O").unwrap() + 12. * rep_mass, rep_mass);
+        assert!((a - b).abs() < 1e-9);
+    }
+
+    #[test]
+    fn find_peg() {
+        for charge in [1, 2] {
+            let mut discovery = Discovery::new(DiscoverySettings::default());
+            discovery.push(&peg_spectrum(charge));
+            discovery.push(&peg_spectrum(charge));
+            let series = discovery.finish();
+
+            assert_eq!(series.len(), 1, "{:?}", series);
+            let peg = &series[0];
+            assert_eq!(peg.charge, charge);
+            assert_eq!(peg.members.len(), 15);
+            assert!((peg.repeat_mass - formula_mass("C2H4O").unwrap()).abs() < 1e-4);
+            assert!(peg.percent_tic > 60.);
+        }
+    }
+
+    #[test]
+    fn to_polymer() {
+        let mut discovery = Discovery::new(DiscoverySettings::default());
+        discovery.push(&peg_spectrum(1));
+        let peg = &discovery.finish()[0];
+
+        let mut poly: Polymer = peg.to_polymer(&peg.name());
+        assert_eq!(poly.name, "Unknown 44.0262 (1+)");
+        poly.calculate_bounds(&1000., &10., "ppm");
+        let precursors = &poly.series.as_ref().unwrap()[0].precursors;
+        for member in peg.members.iter() {
+            assert!(precursors.iter().any(|x| (x - member).abs() < 1e-3));
+        }
+    }
+}
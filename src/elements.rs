@@ -0,0 +1,726 @@
+//! The elements, with their masses and natural isotope abundances.
+//!
+//! Isotope masses and abundances are from the NIST Atomic Weights and
+//! Isotopic Compositions database. Elements without stable isotopes are
+//! represented by their longest-lived isotope.
+
+/// A chemical element.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Element {
+    /// The element symbol, such as `Na`.
+    pub symbol: &'static str,
+    /// The atomic number.
+    pub number: u8,
+    /// The standard atomic weight.
+    pub average_mass: f64,
+    /// The (mass, abundance) of each naturally occurring isotope, from
+    /// lightest to heaviest.
+    pub isotopes: &'static [(f64, f64)],
+}
+
+impl Element {
+    /// The mass of the lightest isotope, which is not always the most
+    /// abundant one.
+    pub fn monoisotopic_mass(&self) -> f64 {
+        self.isotopes[0].0
+    }
+}
+
+const fn element(
+    symbol: &'static str,
+    number: u8,
+    average_mass: f64,
+    isotopes: &'static [(f64, f64)],
+) -> Element {
+    Element {
+        symbol,
+        number,
+        average_mass,
+        isotopes,
+    }
+}
+
+/// The elements from hydrogen to uranium, in order of atomic number.
+pub const ELEMENTS: [Element; 92] = [
+    element(
+        "H",
+        1,
+        1.008,
+        &[(1.007825032071, 0.999885), (2.0141017778, 0.000115)],
+    ),
+    element(
+        "He",
+        2,
+        4.002602,
+        &[(3.0160293201, 1.34e-06), (4.00260325413, 0.99999866)],
+    ),
+    element(
+        "Li",
+        3,
+        6.94,
+        &[(6.0151228874, 0.0759), (7.0160034366, 0.9241)],
+    ),
+    element("Be", 4, 9.0121831, &[(9.012183065, 1.0)]),
+    element("B", 5, 10.81, &[(10.01293695, 0.199), (11.00930536, 0.801)]),
+    element("C", 6, 12.011, &[(12.0, 0.9893), (13.0033548378, 0.0107)]),
+    element(
+        "N",
+        7,
+        14.007,
+        &[(14.00307400486, 0.99636), (15.0001088982, 0.00364)],
+    ),
+    element(
+        "O",
+        8,
+        15.999,
+        &[
+            (15.9949146195616, 0.99757),
+            (16.9991317, 0.00038),
+            (17.999161, 0.00205),
+        ],
+    ),
+    element("F", 9, 18.998403163, &[(18.99840316273, 1.0)]),
+    element(
+        "Ne",
+        10,
+        20.1797,
+        &[
+            (19.9924401762, 0.9048),
+            (20.993846685, 0.0027),
+            (21.991385114, 0.0925),
+        ],
+    ),
+    element("Na", 11, 22.98976928, &[(22.989769282019, 1.0)]),
+    element(
+        "Mg",
+        12,
+        24.305,
+        &[
+            (23.985041697, 0.7899),
+            (24.985836976, 0.1),
+            (25.982592968, 0.1101),
+        ],
+    ),
+    element("Al", 13, 26.9815385, &[(26.98153853, 1.0)]),
+    element(
+        "Si",
+        14,
+        28.085,
+        &[
+            (27.97692653505, 0.92223),
+            (28.9764947, 0.04685),
+            (29.97377017, 0.03092),
+        ],
+    ),
+    element("P", 15, 30.973761998, &[(30.97376199842, 1.0)]),
+    element(
+        "S",
+        16,
+        32.06,
+        &[
+            (31.9720711744, 0.9499),
+            (32.9714589098, 0.0075),
+            (33.967867004, 0.0425),
+            (35.96708071, 0.0001),
+        ],
+    ),
+    element(
+        "Cl",
+        17,
+        35.45,
+        &[(34.968852682, 0.7576), (36.96590259, 0.2424)],
+    ),
+    element(
+        "Ar",
+        18,
+        39.948,
+        &[
+            (35.967545105, 0.003336),
+            (37.96273211, 0.000629),
+            (39.9623831237, 0.996035),
+        ],
+    ),
+    element(
+        "K",
+        19,
+        39.0983,
+        &[
+            (38.9637064864, 0.932581),
+            (39.96399848, 0.000117),
+            (40.96182576, 0.067302),
+        ],
+    ),
+    element(
+        "Ca",
+        20,
+        40.078,
+        &[
+            (39.962590863, 0.96941),
+            (41.95861783, 0.00647),
+            (42.95876644, 0.00135),
+            (43.9554816, 0.02086),
+            (45.953689, 4e-05),
+            (47.95252276, 0.00187),
+        ],
+    ),
+    element("Sc", 21, 44.955908, &[(44.95590828, 1.0)]),
+    element(
+        "Ti",
+        22,
+        47.867,
+        &[
+            (45.95262772, 0.0825),
+            (46.95175879, 0.0744),
+            (47.94794198, 0.7372),
+            (48.94786568, 0.0541),
+            (49.94478689, 0.0518),
+        ],
+    ),
+    element(
+        "V",
+        23,
+        50.9415,
+        &[(49.94715601, 0.0025), (50.94395704, 0.9975)],
+    ),
+    element(
+        "Cr",
+        24,
+        51.9961,
+        &[
+            (49.94604183, 0.04345),
+            (51.94050623, 0.83789),
+            (52.94064815, 0.09501),
+            (53.93887916, 0.02365),
+        ],
+    ),
+    element("Mn", 25, 54.938044, &[(54.93804391, 1.0)]),
+    element(
+        "Fe",
+        26,
+        55.845,
+        &[
+            (53.93960899, 0.05845),
+            (55.93493633, 0.91754),
+            (56.93539284, 0.02119),
+            (57.93327443, 0.00282),
+        ],
+    ),
+    element("Co", 27, 58.933194, &[(58.93319429, 1.0)]),
+    element(
+        "Ni",
+        28,
+        58.6934,
+        &[
+            (57.93534241, 0.68077),
+            (59.93078588, 0.26223),
+            (60.93105557, 0.011399),
+            (61.92834537, 0.036346),
+            (63.92796682, 0.009255),
+        ],
+    ),
+    element(
+        "Cu",
+        29,
+        63.546,
+        &[(62.92959772, 0.6915), (64.9277897, 0.3085)],
+    ),
+    element(
+        "Zn",
+        30,
+        65.38,
+        &[
+            (63.92914201, 0.4917),
+            (65.92603381, 0.2773),
+            (66.92712775, 0.0404),
+            (67.92484455, 0.1845),
+            (69.9253192, 0.0061),
+        ],
+    ),
+    element(
+        "Ga",
+        31,
+        69.723,
+        &[(68.9255735, 0.60108), (70.92470258, 0.39892)],
+    ),
+    element(
+        "Ge",
+        32,
+        72.63,
+        &[
+            (69.92424875, 0.2057),
+            (71.922075826, 0.2745),
+            (72.923458956, 0.0775),
+            (73.921177761, 0.365),
+            (75.921402726, 0.0773),
+        ],
+    ),
+    element("As", 33, 74.921595, &[(74.92159457, 1.0)]),
+    element(
+        "Se",
+        34,
+        78.971,
+        &[
+            (73.922475934, 0.0089),
+            (75.919213704, 0.0937),
+            (76.919914154, 0.0763),
+            (77.91730928, 0.2377),
+            (79.9165218, 0.4961),
+            (81.9166995, 0.0873),
+        ],
+    ),
+    element(
+        "Br",
+        35,
+        79.904,
+        &[(78.9183376, 0.5069), (80.9162897, 0.4931)],
+    ),
+    element(
+        "Kr",
+        36,
+        83.798,
+        &[
+            (77.92036494, 0.00355),
+            (79.91637808, 0.02286),
+            (81.91348273, 0.11593),
+            (82.91412716, 0.115),
+            (83.9114977282, 0.56987),
+            (85.9106106269, 0.17279),
+        ],
+    ),
+    element(
+        "Rb",
+        37,
+        85.4678,
+        &[(84.9117897379, 0.7217), (86.909180531, 0.2783)],
+    ),
+    element(
+        "Sr",
+        38,
+        87.62,
+        &[
+            (83.9134191, 0.0056),
+            (85.9092606, 0.0986),
+            (86.9088775, 0.07),
+            (87.9056125, 0.8258),
+        ],
+    ),
+    element("Y", 39, 88.90584, &[(88.9058403, 1.0)]),
+    element(
+        "Zr",
+        40,
+        91.224,
+        &[
+            (89.9046977, 0.5145),
+            (90.9056396, 0.1122),
+            (91.9050347, 0.1715),
+            (93.9063108, 0.1738),
+            (95.9082714, 0.028),
+        ],
+    ),
+    element("Nb", 41, 92.90637, &[(92.906373, 1.0)]),
+    element(
+        "Mo",
+        42,
+        95.95,
+        &[
+            (91.90680796, 0.1453),
+            (93.9050849, 0.0915),
+            (94.90583877, 0.1584),
+            (95.90467612, 0.1667),
+            (96.90601812, 0.096),
+            (97.90540482, 0.2439),
+            (99.9074718, 0.0982),
+        ],
+    ),
+    element("Tc", 43, 98.0, &[(97.9072124, 1.0)]),
+    element(
+        "Ru",
+        44,
+        101.07,
+        &[
+            (95.90759025, 0.0554),
+            (97.9052868, 0.0187),
+            (98.9059341, 0.1276),
+            (99.9042143, 0.126),
+            (100.9055769, 0.1706),
+            (101.9043441, 0.3155),
+            (103.9054275, 0.1862),
+        ],
+    ),
+    element("Rh", 45, 102.9055, &[(102.905498, 1.0)]),
+    element(
+        "Pd",
+        46,
+        106.42,
+        &[
+            (101.9056022, 0.0102),
+            (103.9040305, 0.1114),
+            (104.9050796, 0.2233),
+            (105.9034804, 0.2733),
+            (107.9038916, 0.2646),
+            (109.9051722, 0.1172),
+        ],
+    ),
+    element(
+        "Ag",
+        47,
+        107.8682,
+        &[(106.9050916, 0.51839), (108.9047553, 0.48161)],
+    ),
+    element(
+        "Cd",
+        48,
+        112.414,
+        &[
+            (105.9064599, 0.0125),
+            (107.9041834, 0.0089),
+            (109.90300661, 0.1249),
+            (110.90418287, 0.128),
+            (111.90276287, 0.2413),
+            (112.90440813, 0.1222),
+            (113.90336509, 0.2873),
+            (115.90476315, 0.0749),
+        ],
+    ),
+    element(
+        "In",
+        49,
+        114.818,
+        &[(112.90406184, 0.0429), (114.903878776, 0.9571)],
+    ),
+    element(
+        "Sn",
+        50,
+        118.71,
+        &[
+            (111.90482387, 0.0097),
+            (113.9027827, 0.0066),
+            (114.903344699, 0.0034),
+            (115.9017428, 0.1454),
+            (116.90295398, 0.0768),
+            (117.90160657, 0.2422),
+            (118.90331117, 0.0859),
+            (119.90220163, 0.3258),
+            (121.9034438, 0.0463),
+            (123.9052766, 0.0579),
+        ],
+    ),
+    element(
+        "Sb",
+        51,
+        121.76,
+        &[(120.903812, 0.5721), (122.9042132, 0.4279)],
+    ),
+    element(
+        "Te",
+        52,
+        127.6,
+        &[
+            (119.9040593, 0.0009),
+            (121.9030435, 0.0255),
+            (122.9042698, 0.0089),
+            (123.9028171, 0.0474),
+            (124.9044299, 0.0707),
+            (125.9033109, 0.1884),
+            (127.90446128, 0.3174),
+            (129.906222748, 0.3408),
+        ],
+    ),
+    element("I", 53, 126.90447, &[(126.9044719, 1.0)]),
+    element(
+        "Xe",
+        54,
+        131.293,
+        &[
+            (123.905892, 0.000952),
+            (125.9042983, 0.00089),
+            (127.903531, 0.019102),
+            (128.9047808611, 0.264006),
+            (129.903509349, 0.04071),
+            (130.90508406, 0.212324),
+            (131.9041550856, 0.269086),
+            (133.90539466, 0.104357),
+            (135.907214484, 0.088573),
+        ],
+    ),
+    element("Cs", 55, 132.90545196, &[(132.905451961, 1.0)]),
+    element(
+        "Ba",
+        56,
+        137.327,
+        &[
+            (129.9063207, 0.00106),
+            (131.9050611, 0.00101),
+            (133.90450818, 0.02417),
+            (134.90568838, 0.06592),
+            (135.90457573, 0.07854),
+            (136.90582714, 0.11232),
+            (137.905247, 0.71698),
+        ],
+    ),
+    element(
+        "La",
+        57,
+        138.90547,
+        &[(137.9071149, 0.0008881), (138.9063563, 0.9991119)],
+    ),
+    element(
+        "Ce",
+        58,
+        140.116,
+        &[
+            (135.90712921, 0.00185),
+            (137.905991, 0.00251),
+            (139.9054431, 0.8845),
+            (141.9092504, 0.11114),
+        ],
+    ),
+    element("Pr", 59, 140.90766, &[(140.9076576, 1.0)]),
+    element(
+        "Nd",
+        60,
+        144.242,
+        &[
+            (141.907729, 0.27152),
+            (142.90982, 0.12174),
+            (143.910093, 0.23798),
+            (144.9125793, 0.08293),
+            (145.9131226, 0.17189),
+            (147.9168993, 0.05756),
+            (149.9209022, 0.05638),
+        ],
+    ),
+    element("Pm", 61, 145.0, &[(144.9127559, 1.0)]),
+    element(
+        "Sm",
+        62,
+        150.36,
+        &[
+            (143.9120065, 0.0307),
+            (146.9149044, 0.1499),
+            (147.9148292, 0.1124),
+            (148.9171921, 0.1382),
+            (149.9172829, 0.0738),
+            (151.9197397, 0.2675),
+            (153.9222169, 0.2275),
+        ],
+    ),
+    element(
+        "Eu",
+        63,
+        151.964,
+        &[(150.9198578, 0.4781), (152.921238, 0.5219)],
+    ),
+    element(
+        "Gd",
+        64,
+        157.25,
+        &[
+            (151.9197995, 0.002),
+            (153.9208741, 0.0218),
+            (154.9226305, 0.148),
+            (155.9221312, 0.2047),
+            (156.9239686, 0.1565),
+            (157.9241123, 0.2484),
+            (159.9270624, 0.2186),
+        ],
+    ),
+    element("Tb", 65, 158.92535, &[(158.9253547, 1.0)]),
+    element(
+        "Dy",
+        66,
+        162.5,
+        &[
+            (155.9242847, 0.00056),
+            (157.9244159, 0.00095),
+            (159.9252046, 0.02329),
+            (160.9269405, 0.18889),
+            (161.9268056, 0.25475),
+            (162.9287383, 0.24896),
+            (163.9291819, 0.2826),
+        ],
+    ),
+    element("Ho", 67, 164.93033, &[(164.9303288, 1.0)]),
+    element(
+        "Er",
+        68,
+        167.259,
+        &[
+            (161.9287884, 0.00139),
+            (163.9292088, 0.01601),
+            (165.9302995, 0.33503),
+            (166.9320546, 0.22869),
+            (167.9323767, 0.26978),
+            (169.9354702, 0.1491),
+        ],
+    ),
+    element("Tm", 69, 168.93422, &[(168.9342179, 1.0)]),
+    element(
+        "Yb",
+        70,
+        173.045,
+        &[
+            (167.9338896, 0.00123),
+            (169.9347664, 0.02982),
+            (170.9363302, 0.1409),
+            (171.9363859, 0.2168),
+            (172.9382151, 0.16103),
+            (173.9388664, 0.32026),
+            (175.9425764, 0.12996),
+        ],
+    ),
+    element(
+        "Lu",
+        71,
+        174.9668,
+        &[(174.9407752, 0.97401), (175.9426897, 0.02599)],
+    ),
+    element(
+        "Hf",
+        72,
+        178.49,
+        &[
+            (173.9400461, 0.0016),
+            (175.9414076, 0.0526),
+            (176.9432277, 0.186),
+            (177.9437058, 0.2728),
+            (178.9458232, 0.1362),
+            (179.946557, 0.3508),
+        ],
+    ),
+    element(
+        "Ta",
+        73,
+        180.94788,
+        &[(179.9474648, 0.0001201), (180.9479958, 0.9998799)],
+    ),
+    element(
+        "W",
+        74,
+        183.84,
+        &[
+            (179.9467108, 0.0012),
+            (181.94820394, 0.265),
+            (182.95022275, 0.1431),
+            (183.95093092, 0.3064),
+            (185.9543628, 0.2843),
+        ],
+    ),
+    element(
+        "Re",
+        75,
+        186.207,
+        &[(184.9529545, 0.374), (186.9557501, 0.626)],
+    ),
+    element(
+        "Os",
+        76,
+        190.23,
+        &[
+            (183.9524885, 0.0002),
+            (185.953835, 0.0159),
+            (186.9557474, 0.0196),
+            (187.9558352, 0.1324),
+            (188.9581442, 0.1615),
+            (189.9584437, 0.2626),
+            (191.961477, 0.4078),
+        ],
+    ),
+    element(
+        "Ir",
+        77,
+        192.217,
+        &[(190.9605893, 0.373), (192.9629216, 0.627)],
+    ),
+    element(
+        "Pt",
+        78,
+        195.084,
+        &[
+            (189.9599297, 0.00012),
+            (191.9610387, 0.00782),
+            (193.9626809, 0.3286),
+            (194.9647917, 0.3378),
+            (195.96495209, 0.2521),
+            (197.9678949, 0.07356),
+        ],
+    ),
+    element("Au", 79, 196.966569, &[(196.96656879, 1.0)]),
+    element(
+        "Hg",
+        80,
+        200.592,
+        &[
+            (195.9658326, 0.0015),
+            (197.9667686, 0.0997),
+            (198.96828064, 0.1687),
+            (199.96832659, 0.231),
+            (200.97030284, 0.1318),
+            (201.9706434, 0.2986),
+            (203.97349398, 0.0687),
+        ],
+    ),
+    element(
+        "Tl",
+        81,
+        204.38,
+        &[(202.9723446, 0.2952), (204.9744278, 0.7048)],
+    ),
+    element(
+        "Pb",
+        82,
+        207.2,
+        &[
+            (203.973044, 0.014),
+            (205.9744657, 0.241),
+            (206.9758973, 0.221),
+            (207.9766525, 0.524),
+        ],
+    ),
+    element("Bi", 83, 208.9804, &[(208.9803991, 1.0)]),
+    element("Po", 84, 209.0, &[(208.9824308, 1.0)]),
+    element("At", 85, 210.0, &[(209.9871479, 1.0)]),
+    element("Rn", 86, 222.0, &[(222.0175782, 1.0)]),
+    element("Fr", 87, 223.0, &[(223.019736, 1.0)]),
+    element("Ra", 88, 226.0, &[(226.0254103, 1.0)]),
+    element("Ac", 89, 227.0, &[(227.0277523, 1.0)]),
+    element("Th", 90, 232.0377, &[(232.0380558, 1.0)]),
+    element("Pa", 91, 231.03588, &[(231.0358842, 1.0)]),
+    element(
+        "U",
+        92,
+        238.02891,
+        &[
+            (234.0409523, 5.4e-05),
+            (235.0439301, 0.007204),
+            (238.0507884, 0.992742),
+        ],
+    ),
+];
+
+/// Look up an element by its symbol.
+pub fn element_by_symbol(symbol: &str) -> Option<&'static Element> {
+    ELEMENTS.iter().find(|x| x.symbol == symbol)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{element_by_symbol, ELEMENTS};
+
+    #[test]
+    fn test_elements() {
+        for (i, element) in ELEMENTS.iter().enumerate() {
+            assert_eq!(element.number as usize, i + 1);
+            let total: f64 = element.isotopes.iter().map(|x| x.1).sum();
+            assert!((total - 1.).abs() < 2e-3, "{}", element.symbol);
+        }
+
+        assert_eq!(element_by_symbol("C").unwrap().monoisotopic_mass(), 12.);
+        assert_eq!(element_by_symbol("P").unwrap().number, 15);
+        // The lightest isotope of iron is not the most abundant:
+        let iron = element_by_symbol("Fe").unwrap();
+        assert!((iron.monoisotopic_mass() - 53.93960899).abs() < 1e-9);
+        assert!(element_by_symbol("Xx").is_none());
+        assert!(element_by_symbol("c").is_none());
+    }
+}
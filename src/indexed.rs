@@ -0,0 +1,469 @@
+//! Random access to the spectra of indexed mzML files.
+//!
+//! Most mzML files are wrapped in an `<indexedmzML>` element that ends with
+//! the byte offset of every spectrum. With these offsets, individual
+//! spectra can be read without parsing the whole file and chunks of
+//! spectra can be decoded in parallel.
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+
+use futures::{Stream, StreamExt};
+use log::warn;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::input::{SpectrumStream, GZIP_MAGIC};
+use crate::mzml::{MS1Spectra, MzMLError, Spectrum, SpectrumParser};
+
+/// The number of bytes at the end of a file to search for the
+/// `<indexListOffset>` element.
+const TAIL_SIZE: u64 = 4096;
+
+/// The default number of spectra decoded by each parallel task.
+const CHUNK_SIZE: usize = 256;
+
+/// The location of a spectrum in an indexed mzML file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// The native id of the spectrum.
+    pub id: String,
+    /// The byte offset of the `<spectrum>` element.
+    pub offset: u64,
+}
+
+impl IndexEntry {
+    /// The scan number from the native id, such as `scan=42`.
+    pub fn scan(&self) -> Option<usize> {
+        self.id
+            .split_whitespace()
+            .find_map(|x| x.strip_prefix("scan="))?
+            .parse()
+            .ok()
+    }
+}
+
+/// The spectrum offsets of an indexed mzML file.
+#[derive(Debug, Clone, Default)]
+pub struct MzMLIndex {
+    /// The spectra, in the order they appear in the file.
+    pub spectra: Vec<IndexEntry>,
+    /// The offset after which there are no more spectra.
+    end: u64,
+}
+
+impl MzMLIndex {
+    /// Read the index at the end of an mzML file.
+    ///
+    /// Returns `None` if the file has no index, such as when it is not an
+    /// indexed mzML file or when it is gzipped. Returns an error if the
+    /// index is malformed or its offsets don't point to spectra.
+    pub async fn read(file: &mut File) -> Result<Option<Self>, MzMLError> {
+        let mut head = [0; 2];
+        file.seek(SeekFrom::Start(0)).await?;
+        if file.read_exact(&mut head).await.is_err() || head == GZIP_MAGIC {
+            return Ok(None);
+        }
+
+        let len = file.seek(SeekFrom::End(0)).await?;
+        file.seek(SeekFrom::Start(len.saturating_sub(TAIL_SIZE)))
+            .await?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail).await?;
+        let index_offset = match find(&tail, b"<indexListOffset>") {
+            Some(start) => {
+                let start = start + b"<indexListOffset>".len();
+                let end = start + find(&tail[start..], b"<").ok_or(MzMLError::Malformed)?;
+                std::str::from_utf8(&tail[start..end])?
+                    .trim()
+                    .parse::<u64>()?
+            }
+            None => return Ok(None),
+        };
+        if index_offset >= len {
+            return Err(MzMLError::Malformed);
+        }
+
+        file.seek(SeekFrom::Start(index_offset)).await?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).await?;
+        if !bytes.starts_with(b"<indexList") {
+            return Err(MzMLError::Malformed);
+        }
+        let index = Self::parse(&bytes, index_offset)?;
+        index.validate(file).await?;
+        Ok(Some(index))
+    }
+
+    /// Parse the `<indexList>` element, which starts at `index_offset`.
+    fn parse(bytes: &[u8], index_offset: u64) -> Result<Self, MzMLError> {
+        let mut reader = Reader::from_reader(bytes);
+        let mut buf = Vec::new();
+        let mut index_name = Vec::new();
+        let mut id = None;
+        let mut spectra = Vec::new();
+        let mut end = index_offset;
+        loop {
+            match reader.read_event_into(&mut buf)? {
+                Event::Start(ref ev) => match ev.name().into_inner() {
+                    b"index" => {
+                        index_name = ev
+                            .try_get_attribute(b"name")?
+                            .ok_or(MzMLError::Malformed)?
+                            .value
+                            .to_vec();
+                    }
+                    b"offset" => {
+                        let attr = ev
+                            .try_get_attribute(b"idRef")?
+                            .ok_or(MzMLError::Malformed)?;
+                        id = Some(attr.unescape_value()?.to_string());
+                    }
+                    _ => {}
+                },
+                Event::Text(ev) => {
+                    if let Some(id) = id.take() {
+                        let offset = ev.unescape()?.trim().parse::<u64>()?;
+                        match &index_name[..] {
+                            b"spectrum" => spectra.push(IndexEntry { id, offset }),
+                            // Chromatograms follow the spectra:
+                            _ => end = end.min(offset),
+                        }
+                    }
+                }
+                Event::End(ref ev) if ev.name().into_inner() == b"indexList" => break,
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        // The offsets must increase and precede the index:
+        let increasing = spectra.windows(2).all(|x| x[0].offset < x[1].offset);
+        match spectra.last() {
+            Some(last) if increasing && last.offset < index_offset => Ok(Self {
+                end: match end > last.offset {
+                    true => end,
+                    false => index_offset,
+                },
+                spectra,
+            }),
+            _ => Err(MzMLError::Malformed),
+        }
+    }
+
+    /// Check that the first, middle, and last offsets point to spectra.
+    async fn validate(&self, file: &mut File) -> Result<(), MzMLError> {
+        let n = self.spectra.len();
+        for entry in [&self.spectra[0], &self.spectra[n / 2], &self.spectra[n - 1]] {
+            let mut tag = [0; 9];
+            file.seek(SeekFrom::Start(entry.offset)).await?;
+            file.read_exact(&mut tag).await?;
+            if &tag != b"<spectrum" {
+                return Err(MzMLError::Malformed);
+            }
+        }
+        Ok(())
+    }
+
+    /// The byte range of the spectra from `start` up to `end`.
+    fn range(&self, start: usize, end: usize) -> (u64, u64) {
+        let to = match self.spectra.get(end) {
+            Some(entry) => entry.offset,
+            None => self.end,
+        };
+        (self.spectra[start].offset, to)
+    }
+}
+
+/// An indexed mzML file.
+#[derive(Debug, Clone)]
+pub struct IndexedMzML {
+    path: PathBuf,
+    index: MzMLIndex,
+    chunk_size: usize,
+}
+
+impl IndexedMzML {
+    /// Open an mzML file using its index.
+    ///
+    /// Returns `None` if the file has no usable index, in which case it
+    /// must be read sequentially with [`MzMLReader`]. A corrupt index is
+    /// reported as a warning.
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Option<Self>, MzMLError> {
+        let path = path.as_ref();
+        let mut file = File::open(path).await?;
+        match MzMLIndex::read(&mut file).await {
+            Ok(Some(index)) => Ok(Some(Self {
+                path: path.to_path_buf(),
+                index,
+                chunk_size: CHUNK_SIZE,
+            })),
+            Ok(None) => Ok(None),
+            Err(err) => {
+                warn!(
+                    "Ignoring the index of {}, which is invalid ({}). Reading it sequentially.",
+                    path.display(),
+                    err
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// Decode this many spectra in each parallel task.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// The spectrum offsets.
+    pub fn index(&self) -> &MzMLIndex {
+        &self.index
+    }
+
+    /// Read a single spectrum by its native id.
+    ///
+    /// Returns `None` if there is no such spectrum or it is not an MS1 or
+    /// MS2 spectrum. As with [`MzMLReader::stream`], the peaks of MS2
+    /// spectra are not decoded.
+    pub async fn spectrum_by_id(&self, id: &str) -> Result<Option<Spectrum>, MzMLError> {
+        match self.index.spectra.iter().position(|x| x.id == id) {
+            Some(idx) => self.spectrum_at(idx).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Read a single spectrum by its scan number.
+    ///
+    /// Returns `None` if there is no such spectrum or it is not an MS1 or
+    /// MS2 spectrum.
+    pub async fn spectrum_by_scan(&self, scan: usize) -> Result<Option<Spectrum>, MzMLError> {
+        match self
+            .index
+            .spectra
+            .iter()
+            .position(|x| x.scan() == Some(scan))
+        {
+            Some(idx) => self.spectrum_at(idx).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn spectrum_at(&self, idx: usize) -> Result<Option<Spectrum>, MzMLError> {
+        let (start, end) = self.index.range(idx, idx + 1);
+        let bytes = read_range(&self.path, start, end).await?;
+        let mut spectra = parse_bytes(&bytes).await?;
+        Ok(spectra.pop())
+    }
+
+    /// Decode the chunks of spectra in parallel, yielding them in order.
+    ///
+    /// The spectra in each chunk are read from the file and then decoded
+    /// on a blocking thread, since decoding bytes in memory never waits.
+    fn chunks(&self) -> impl Stream<Item = Result<Vec<Spectrum>, MzMLError>> {
+        let n = self.index.spectra.len();
+        let ranges: Vec<(u64, u64)> = (0..n)
+            .step_by(self.chunk_size)
+            .map(|start| self.index.range(start, (start + self.chunk_size).min(n)))
+            .collect();
+        let workers = std::thread::available_parallelism().map_or(1, |x| x.get());
+        let path = self.path.clone();
+        futures::stream::iter(ranges)
+            .map(move |(start, end)| {
+                let path = path.clone();
+                async move {
+                    let bytes = read_range(&path, start, end).await?;
+                    tokio::task::spawn_blocking(move || {
+                        futures::executor::block_on(parse_bytes(&bytes))
+                    })
+                    .await
+                    .map_err(|err| {
+                        MzMLError::IOError(std::io::Error::new(std::io::ErrorKind::Other, err))
+                    })?
+                }
+            })
+            .buffered(workers)
+    }
+
+    /// Read the MS1 and MS2 spectra, decoding chunks of them in parallel.
+    ///
+    /// Only a few chunks are held in memory at once. As with
+    /// [`MzMLReader::stream`], the peaks of MS2 spectra are not decoded.
+    pub fn stream(&self) -> SpectrumStream {
+        let spectra = self.chunks().flat_map(|chunk| {
+            let spectra: Vec<_> = match chunk {
+                Ok(spectra) => spectra.into_iter().map(Ok).collect(),
+                Err(err) => vec![Err(err)],
+            };
+            futures::stream::iter(spectra)
+        });
+        Box::pin(spectra)
+    }
+
+    /// Read all of the MS1 spectra into memory, decoding chunks of them in
+    /// parallel.
+    pub async fn parse(&self) -> Result<MS1Spectra, MzMLError> {
+        let chunks = self.chunks();
+        futures::pin_mut!(chunks);
+        let mut spectra = Vec::new();
+        while let Some(chunk) = chunks.next().await {
+            spectra.extend(chunk?.into_iter().filter(|x| x.ms_level == 1));
+        }
+        Ok(MS1Spectra::new(spectra))
+    }
+}
+
+/// Read the spectrum elements between two byte offsets.
+async fn read_range(path: &Path, start: u64, end: u64) -> Result<Vec<u8>, MzMLError> {
+    let mut file = File::open(path).await?;
+    file.seek(SeekFrom::Start(start)).await?;
+    let mut bytes = Vec::with_capacity((end - start) as usize);
+    file.take(end - start).read_to_end(&mut bytes).await?;
+
+    // Drop the closing tags that follow the last spectrum:
+    if let Some(pos) = rfind(&bytes, b"</spectrum>") {
+        bytes.truncate(pos + b"</spectrum>".len());
+    }
+    Ok(bytes)
+}
+
+/// Parse the spectrum elements read by [`read_range`].
+async fn parse_bytes(bytes: &[u8]) -> Result<Vec<Spectrum>, MzMLError> {
+    let mut parser = SpectrumParser::new(bytes);
+    let mut spectra = Vec::new();
+    while let Some(spectrum) = parser.next_spectrum().await? {
+        spectra.push(spectrum);
+    }
+    Ok(spectra)
+}
+
+/// The position of the first occurrence of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|x| x == needle)
+}
+
+/// The position of the last occurrence of `needle` in `haystack`.
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).rposition(|x| x == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IndexedMzML;
+    use crate::input::open_spectra;
+    use crate::mzml::tests::{mzml, spectrum_xml};
+    use crate::mzml::MzMLReader;
+    use crate::testing::TempDir;
+    use futures::StreamExt;
+
+    /// Create an indexed mzML file from spectrum elements, with its
+    /// offsets off by `shift` bytes.
+    fn indexed_mzml(spectra: &[String], shift: usize) -> String {
+        let mut xml = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <indexedmzML><mzML><run><spectrumList count=\"{}\">\n",
+            spectra.len()
+        );
+        let mut offsets = Vec::new();
+        for spectrum in spectra {
+            offsets.push(xml.len());
+            xml += spectrum;
+            xml += "\n";
+        }
+        xml += "</spectrumList></run></mzML>\n";
+        let index_offset = xml.len();
+        xml += "<indexList count=\"1\"><index name=\"spectrum\">";
+        for (idx, offset) in offsets.iter().enumerate() {
+            xml += &format!(
+                "<offset idRef=\"scan={}\">{}</offset>",
+                idx + 1,
+                offset + shift
+            );
+        }
+        xml += &format!(
+            "</index></indexList>\n<indexListOffset>{}</indexListOffset>\n\
+             <fileChecksum>0</fileChecksum></indexedmzML>\n",
+            index_offset
+        );
+        xml
+    }
+
+    fn test_spectra() -> Vec<String> {
+        (0..10)
+            .map(|idx| {
+                let ms_level = if idx % 3 == 1 { 2 } else { 1 };
+                let mz = 100. + idx as f64;
+                spectrum_xml(idx, ms_level, &[mz, mz + 1.], &[1., idx as f64])
+            })
+            .collect()
+    }
+
+    fn write(dir: &TempDir, name: &str, xml: &str) -> std::path::PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, xml).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn random_access() {
+        let dir = TempDir::new("indexed_access");
+        let path = write(&dir, "access.mzML", &indexed_mzml(&test_spectra(), 0));
+        let indexed = IndexedMzML::open(&path).await.unwrap().unwrap();
+        assert_eq!(indexed.index().spectra.len(), 10);
+        assert_eq!(indexed.index().spectra[3].scan(), Some(4));
+
+        let spectrum = indexed.spectrum_by_id("scan=4").await.unwrap().unwrap();
+        assert_eq!(spectrum.id, "scan=4");
+        assert_eq!(spectrum.mz, vec![103., 104.]);
+
+        let last = indexed.spectrum_by_scan(10).await.unwrap().unwrap();
+        assert_eq!(last.intensity, vec![1., 9.]);
+        assert!(indexed.spectrum_by_scan(11).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn parallel_matches_sequential() {
+        let xml = indexed_mzml(&test_spectra(), 0);
+        let dir = TempDir::new("indexed_parallel");
+        let path = write(&dir, "parallel.mzML", &xml);
+        let indexed = IndexedMzML::open(&path)
+            .await
+            .unwrap()
+            .unwrap()
+            .with_chunk_size(3);
+
+        let expected: Vec<_> = MzMLReader::new().stream(xml.as_bytes()).collect().await;
+        let streamed: Vec<_> = indexed.stream().collect().await;
+        let ids = |x: &[Result<_, _>]| -> Vec<String> {
+            x.iter()
+                .map(|x: &Result<crate::mzml::Spectrum, _>| x.as_ref().unwrap().id.clone())
+                .collect()
+        };
+        assert_eq!(streamed.len(), 10);
+        assert_eq!(ids(&streamed), ids(&expected));
+
+        let expected = MzMLReader::new().parse(xml.as_bytes()).await.unwrap();
+        let parsed = indexed.parse().await.unwrap();
+        assert_eq!(parsed.spectra.len(), 7);
+        assert_eq!(parsed.spectra[6].mz, expected.spectra[6].mz);
+    }
+
+    #[tokio::test]
+    async fn fallback() {
+        // Without an index:
+        let dir = TempDir::new("indexed_fallback");
+        let plain = write(&dir, "plain.mzML", &mzml(&test_spectra()));
+        assert!(IndexedMzML::open(&plain).await.unwrap().is_none());
+        let spectra: Vec<_> = open_spectra(&plain).await.unwrap().collect().await;
+        assert_eq!(spectra.len(), 10);
+
+        // With offsets that don't point to spectra:
+        let xml = indexed_mzml(&test_spectra(), 4);
+        let corrupt = write(&dir, "corrupt.mzML", &xml);
+        assert!(IndexedMzML::open(&corrupt).await.unwrap().is_none());
+        let spectra: Vec<_> = open_spectra(&corrupt).await.unwrap().collect().await;
+        assert_eq!(spectra.len(), 10);
+    }
+}
@@ -0,0 +1,170 @@
+use std::path::Path;
+use std::pin::Pin;
+
+use async_compression::tokio::bufread::GzipDecoder;
+use futures::Stream;
+use tokio::fs::File;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, BufReader};
+
+use crate::indexed::IndexedMzML;
+use crate::mzml::{MzMLError, MzMLReader, Spectrum};
+use crate::mzxml::MzXMLReader;
+use crate::tdf::TdfReader;
+
+/// The first two bytes of every gzip file.
+pub(crate) const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The number of bytes at the start of a file to search for its root
+/// element.
+const HEAD_SIZE: u64 = 4096;
+
+/// A buffered reader over a possibly decompressed input.
+pub type InputReader = Box<dyn AsyncBufRead + Unpin + Send>;
+
+/// The spectra of a file, in the order they appear.
+pub type SpectrumStream = Pin<Box<dyn Stream<Item = Result<Spectrum, MzMLError>>>>;
+
+/// The format of a mass spectrometry data file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    MzML,
+    MzXML,
+    /// A Bruker timsTOF `.d` directory.
+    Tdf,
+}
+
+impl Format {
+    /// Identify the format from the start of a file.
+    fn sniff(head: &[u8]) -> Option<Self> {
+        let head = String::from_utf8_lossy(head);
+        if head.contains("<mzXML") {
+            Some(Self::MzXML)
+        } else if head.contains("<mzML") || head.contains("<indexedmzML") {
+            Some(Self::MzML)
+        } else {
+            None
+        }
+    }
+
+    /// Guess the format of a file from its name.
+    pub fn from_name(name: &str) -> Self {
+        let name = name.trim_end_matches('/').to_lowercase();
+        if name.ends_with(".d") {
+            Self::Tdf
+        } else if name.contains(".mzxml") {
+            Self::MzXML
+        } else {
+            Self::MzML
+        }
+    }
+
+    /// Detect the format of a file from its root element, falling back to
+    /// its extension.
+    pub async fn detect<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        if TdfReader::is_tdf(&path) {
+            return Ok(Self::Tdf);
+        }
+
+        let mut head = Vec::new();
+        open(&path)
+            .await?
+            .take(HEAD_SIZE)
+            .read_to_end(&mut head)
+            .await?;
+
+        let name = path.as_ref().to_string_lossy();
+        Ok(Self::sniff(&head).unwrap_or_else(|| Self::from_name(&name)))
+    }
+}
+
+/// Open a file for reading, decompressing it if it is gzipped.
+pub async fn open<P: AsRef<Path>>(path: P) -> std::io::Result<InputReader> {
+    let file = File::open(path).await?;
+    decompress(BufReader::new(file)).await
+}
+
+/// Decompress a reader if it starts with the gzip magic bytes.
+pub async fn decompress<R>(mut reader: R) -> std::io::Result<InputReader>
+where
+    R: AsyncBufRead + Unpin + Send + 'static,
+{
+    let head = reader.fill_buf().await?;
+    if head.starts_with(&GZIP_MAGIC) {
+        let mut decoder = GzipDecoder::new(reader);
+        decoder.multiple_members(true);
+        Ok(Box::new(BufReader::new(decoder)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Read the spectra of an mzML file, mzXML file, or Bruker `.d` directory,
+/// detecting its format.
+///
+/// Indexed mzML files are read in parallel. Other files, including gzipped
+/// files, are read sequentially.
+pub async fn open_spectra<P: AsRef<Path>>(path: P) -> Result<SpectrumStream, MzMLError> {
+    match Format::detect(&path).await? {
+        Format::MzXML => {
+            let buf = open(path).await?;
+            return Ok(Box::pin(MzXMLReader::new().stream(buf)));
+        }
+        Format::Tdf => return Ok(Box::pin(TdfReader::open(path)?.stream())),
+        Format::MzML => {}
+    }
+
+    match IndexedMzML::open(&path).await? {
+        Some(indexed) => Ok(indexed.stream()),
+        None => {
+            let buf = open(path).await?;
+            Ok(Box::pin(MzMLReader::new().stream(buf)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decompress, Format};
+    use async_compression::tokio::bufread::GzipEncoder;
+    use std::io::Cursor;
+    use tokio::io::AsyncReadExt;
+
+    const TEXT: &[u8] = b"<?xml version=\"1.0\" encoding=\"utf-8\"?><mzML></mzML>";
+
+    #[tokio::test]
+    async fn test_plain() {
+        let mut reader = decompress(Cursor::new(TEXT.to_vec())).await.unwrap();
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, TEXT);
+    }
+
+    #[tokio::test]
+    async fn test_gzip() {
+        let mut compressed = Vec::new();
+        GzipEncoder::new(TEXT)
+            .read_to_end(&mut compressed)
+            .await
+            .unwrap();
+        assert_ne!(compressed, TEXT);
+
+        let mut reader = decompress(Cursor::new(compressed)).await.unwrap();
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, TEXT);
+    }
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(Format::sniff(TEXT), Some(Format::MzML));
+        let indexed = b"<?xml version=\"1.0\"?>\n<indexedmzML xmlns=\"\">";
+        assert_eq!(Format::sniff(indexed), Some(Format::MzML));
+        let mzxml = b"<?xml version=\"1.0\"?>\n<mzXML xmlns=\"\">";
+        assert_eq!(Format::sniff(mzxml), Some(Format::MzXML));
+        assert_eq!(Format::sniff(b"BM\x00\x01"), None);
+
+        assert_eq!(Format::from_name("data/run.mzXML.gz"), Format::MzXML);
+        assert_eq!(Format::from_name("data/run.d/"), Format::Tdf);
+        assert_eq!(Format::from_name("data/run.mzML"), Format::MzML);
+    }
+}
@@ -1,5 +1,23 @@
+pub mod baseline;
+pub mod batch;
+pub mod calibration;
+pub mod chromatogram;
 pub mod defaults;
+pub mod discovery;
+pub mod elements;
+pub mod indexed;
+pub mod input;
+pub mod library;
 pub mod mass;
 pub mod mzml;
+pub mod mzqc;
+pub mod mzxml;
+pub mod numpress;
+pub mod output;
 pub mod polymer;
+pub mod qc;
 pub mod search;
+pub mod tdf;
+
+#[cfg(test)]
+mod testing;
@@ -0,0 +1,342 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::defaults::DefaultPolymers;
+use crate::polymer::Polymer;
+
+/// A collection of polymers that can be read from or written to disk.
+///
+/// Libraries may be written as TOML:
+///
+/// ```toml
+/// [[polymers]]
+/// name = "PEG+1H"
+/// core_formula = "H2O"
+/// rep_formula = "C2H4O"
+/// charge = 1
+/// protonate = true
+/// description = "Polyethylene glycol"
+/// ```
+///
+/// Instead of `charge` and `protonate`, polymers may list their adducts:
+///
+/// ```toml
+/// adducts = ["[M+H]+", "[M+Na]+", "[M+NH4]+", "[M+H+Na]2+"]
+/// ```
+///
+/// Copolymers list their additional repeating units, each with an optional
+/// `min` and `max` count:
+///
+/// ```toml
+/// [[polymers.repeat_units]]
+/// formula = "C3H6O"
+/// min = 20
+/// max = 35
+/// ```
+///
+/// Libraries may also be written as JSON, using the same field names.
+/// Polymers may also specify a `max_tic` QC threshold, and the library may
+/// specify a `max_total_tic` threshold for all polymers combined.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PolymerLibrary {
+    /// The maximum allowed %TIC of all polymers combined.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_total_tic: Option<f64>,
+    #[serde(default)]
+    pub polymers: Vec<Polymer>,
+}
+
+impl PolymerLibrary {
+    /// Create a new, empty [`PolymerLibrary`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a library from a TOML or JSON file.
+    ///
+    /// The format is chosen using the file extension.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, LibraryError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+        let ext = path
+            .extension()
+            .and_then(|x| x.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+
+        match ext.as_str() {
+            "toml" => Self::from_toml(&contents),
+            "json" => Self::from_json(&contents),
+            _ => Err(LibraryError::UnknownFormat(path.display().to_string())),
+        }
+    }
+
+    /// Parse a library from a TOML string.
+    pub fn from_toml(contents: &str) -> Result<Self, LibraryError> {
+        let library: Self = toml::from_str(contents)?;
+        library.validate()
+    }
+
+    /// Parse a library from a JSON string.
+    pub fn from_json(contents: &str) -> Result<Self, LibraryError> {
+        let library: Self = serde_json::from_str(contents)?;
+        library.validate()
+    }
+
+    /// Check the polymers for values that their formats can't rule out.
+    ///
+    /// The charge is ignored, and so not checked, when adducts are listed.
+    fn validate(self) -> Result<Self, LibraryError> {
+        if let Some(poly) = self
+            .polymers
+            .iter()
+            .find(|x| !x.has_adducts() && x.charge() < 1)
+        {
+            return Err(LibraryError::InvalidCharge(
+                poly.name.clone(),
+                poly.charge(),
+            ));
+        }
+        Ok(self)
+    }
+
+    /// Write a library to a TOML or JSON file.
+    ///
+    /// The format is chosen using the file extension.
+    pub fn to_path<P: AsRef<Path>>(&self, path: P) -> Result<(), LibraryError> {
+        let path = path.as_ref();
+        let ext = path
+            .extension()
+            .and_then(|x| x.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+
+        let contents = match ext.as_str() {
+            "toml" => self.to_toml()?,
+            "json" => self.to_json()?,
+            _ => return Err(LibraryError::UnknownFormat(path.display().to_string())),
+        };
+        Ok(std::fs::write(path, contents)?)
+    }
+
+    /// Write the library as a TOML string.
+    pub fn to_toml(&self) -> Result<String, LibraryError> {
+        Ok(toml::to_string(self)?)
+    }
+
+    /// Write the library as a JSON string.
+    pub fn to_json(&self) -> Result<String, LibraryError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Add the polymers from another library to this one.
+    ///
+    /// Polymers in `other` replace those with the same name.
+    pub fn merge(mut self, other: PolymerLibrary) -> Self {
+        if other.max_total_tic.is_some() {
+            self.max_total_tic = other.max_total_tic;
+        }
+        for poly in other.polymers.into_iter() {
+            match self.polymers.iter_mut().find(|x| x.name == poly.name) {
+                Some(existing) => *existing = poly,
+                None => self.polymers.push(poly),
+            }
+        }
+        self
+    }
+}
+
+impl From<DefaultPolymers> for PolymerLibrary {
+    fn from(defaults: DefaultPolymers) -> Self {
+        Self {
+            max_total_tic: None,
+            polymers: defaults.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LibraryError {
+    UnknownFormat(String),
+    InvalidCharge(String, i32),
+    IOError(std::io::Error),
+    TomlError(toml::de::Error),
+    TomlWriteError(toml::ser::Error),
+    JsonError(serde_json::Error),
+}
+
+impl std::fmt::Display for LibraryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LibraryError::UnknownFormat(s) => write!(
+                f,
+                "LibraryError: unknown library format for {} (expected .toml or .json)",
+                s
+            ),
+            LibraryError::InvalidCharge(name, charge) => write!(
+                f,
+                "LibraryError: invalid charge {} for {} (expected 1 or more; use adducts for anions)",
+                charge, name
+            ),
+            LibraryError::IOError(s) => write!(f, "LibraryError: IO error {}", s),
+            LibraryError::TomlError(s) => write!(f, "LibraryError: TOML error {}", s),
+            LibraryError::TomlWriteError(s) => write!(f, "LibraryError: TOML error {}", s),
+            LibraryError::JsonError(s) => write!(f, "LibraryError: JSON error {}", s),
+        }
+    }
+}
+
+impl std::error::Error for LibraryError {}
+
+impl From<std::io::Error> for LibraryError {
+    fn from(residual: std::io::Error) -> Self {
+        Self::IOError(residual)
+    }
+}
+
+impl From<toml::de::Error> for LibraryError {
+    fn from(residual: toml::de::Error) -> Self {
+        Self::TomlError(residual)
+    }
+}
+
+impl From<toml::ser::Error> for LibraryError {
+    fn from(residual: toml::ser::Error) -> Self {
+        Self::TomlWriteError(residual)
+    }
+}
+
+impl From<serde_json::Error> for LibraryError {
+    fn from(residual: serde_json::Error) -> Self {
+        Self::JsonError(residual)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PolymerLibrary;
+    use crate::defaults::DefaultPolymers;
+    use crate::polymer::Polymer;
+
+    const TOML_LIB: &str = r#"
+        max_total_tic = 10.0
+
+        [[polymers]]
+        name = "PEG+1H"
+        core_formula = "H2O"
+        rep_formula = "C2H4O"
+        charge = 1
+        protonate = true
+        description = "Our PEG"
+        max_tic = 5.0
+
+        [[polymers]]
+        name = "Mystery detergent"
+        core_formula = "C12H26O"
+        rep_formula = "C2H4O"
+        adducts = ["[M+H]+", "[M+NH4]+", "[M+H+Na]2+"]
+    "#;
+
+    const JSON_LIB: &str = r#"{
+        "polymers": [
+            {"name": "PPG", "core_formula": "H2O", "rep_formula": "C3H6O", "charge": 2}
+        ]
+    }"#;
+
+    #[test]
+    fn test_from_toml() {
+        let lib = PolymerLibrary::from_toml(TOML_LIB).unwrap();
+        assert_eq!(lib.polymers.len(), 2);
+        assert_eq!(lib.polymers[0].description.as_deref(), Some("Our PEG"));
+        assert_eq!(lib.polymers[0].max_tic, Some(5.));
+        assert_eq!(lib.max_total_tic, Some(10.));
+        assert_eq!(lib.polymers[1].name, "Mystery detergent");
+        assert_eq!(lib.polymers[1].adducts()[1].name, "[M+NH4]+");
+        assert_eq!(lib.polymers[1].adducts()[2].charge, 2);
+    }
+
+    #[test]
+    fn test_from_json() {
+        let lib = PolymerLibrary::from_json(JSON_LIB).unwrap();
+        assert_eq!(lib.polymers.len(), 1);
+        assert_eq!(lib.polymers[0].name, "PPG");
+    }
+
+    #[test]
+    fn test_copolymer() {
+        let toml = r#"
+        [[polymers]]
+        name = "Pluronic F-68"
+        core_formula = "H2O"
+        rep_formula = "C2H4O"
+
+        [[polymers.repeat_units]]
+        formula = "C3H6O"
+        min = 20
+        max = 35
+        "#;
+        let lib = PolymerLibrary::from_toml(toml).unwrap();
+        let units = lib.polymers[0].units();
+        assert_eq!(units.len(), 2);
+        assert_eq!(units[1].label(), "C3H6O");
+        assert_eq!((units[1].min, units[1].max), (20, Some(35)));
+
+        let lib = PolymerLibrary::from_toml(&lib.to_toml().unwrap()).unwrap();
+        assert_eq!(lib.polymers[0].units(), units);
+    }
+
+    #[test]
+    fn test_invalid_formula() {
+        let toml = "[[polymers]]\nname = \"Bad\"\ncore_formula = \"H2O\"\nrep_formula = \"C2Xx4O\"";
+        let err = PolymerLibrary::from_toml(toml).unwrap_err();
+        assert!(err.to_string().contains("unknown element 'Xx'"));
+    }
+
+    #[test]
+    fn test_invalid_charge() {
+        for charge in [0, -1] {
+            let toml = format!(
+                "[[polymers]]\nname = \"Bad\"\ncore_formula = \"H2O\"\nrep_formula = \"C2H4O\"\ncharge = {}",
+                charge
+            );
+            let err = PolymerLibrary::from_toml(&toml).unwrap_err();
+            assert!(err.to_string().contains("invalid charge"));
+        }
+
+        let json = r#"{"polymers": [{"name": "Bad", "rep_formula": "C2H4O", "charge": 0}]}"#;
+        assert!(PolymerLibrary::from_json(json).is_err());
+
+        // The charge is ignored when adducts are listed:
+        let toml = "[[polymers]]\nname = \"Anion\"\ncore_formula = \"H2O\"\nrep_formula = \"C2H4O\"\ncharge = -1\nadducts = [\"[M-H]-\", \"[M+Cl]-\"]";
+        let lib = PolymerLibrary::from_toml(toml).unwrap();
+        assert_eq!(lib.polymers[0].adducts()[0].charge, -1);
+    }
+
+    #[test]
+    fn test_merge() {
+        let defaults = PolymerLibrary::from(DefaultPolymers::new());
+        let n_defaults = defaults.polymers.len();
+        let lib = defaults.merge(PolymerLibrary::from_toml(TOML_LIB).unwrap());
+        assert_eq!(lib.polymers.len(), n_defaults + 1);
+
+        let peg = lib.polymers.iter().find(|x| x.name == "PEG+1H").unwrap();
+        assert_eq!(peg.description.as_deref(), Some("Our PEG"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut lib = PolymerLibrary::from_toml(TOML_LIB).unwrap();
+        lib.polymers
+            .push(Polymer::from_masses("Unknown", 18.01, 44.03, 1, true));
+
+        let from_toml = PolymerLibrary::from_toml(&lib.to_toml().unwrap()).unwrap();
+        let from_json = PolymerLibrary::from_json(&lib.to_json().unwrap()).unwrap();
+        for other in [from_toml, from_json] {
+            assert_eq!(other.polymers.len(), 3);
+            assert_eq!(other.max_total_tic, Some(10.));
+            assert_eq!(other.polymers[1].adducts()[1].name, "[M+NH4]+");
+            assert_eq!(other.polymers[2].rep_mass(), 44.03);
+        }
+    }
+}
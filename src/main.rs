@@ -1,15 +1,28 @@
-use std::io::BufWriter;
+use std::fs::File;
+use std::io::{BufWriter, Write};
 use std::path::Path;
+use std::process::ExitCode;
 
 use clap::{Arg, Command};
-use log::{error, info};
-use mzsniffer::mzml::MzMLReader;
-use mzsniffer::search::{search, PolymerResults};
-use tokio::fs::File;
-use tokio::io::BufReader;
+use log::{error, info, warn};
+use mzsniffer::baseline::{Baseline, DEFAULT_MAX_FOLD};
+use mzsniffer::batch::BatchSummary;
+use mzsniffer::calibration::Calibration;
+use mzsniffer::defaults::DefaultPolymers;
+use mzsniffer::discovery::{discover_stream, CandidateSeries, DiscoveryResults, DiscoverySettings};
+use mzsniffer::input::open_spectra;
+use mzsniffer::library::PolymerLibrary;
+use mzsniffer::mzml::Polarity;
+use mzsniffer::mzqc::MzQC;
+use mzsniffer::output::{write_composition_table, write_summary_table, OutputFormat};
+use mzsniffer::qc::{Thresholds, EXIT_CLEAN, EXIT_CONTAMINATED, EXIT_ERROR};
+use mzsniffer::search::{IsotopeSettings, PolymerResults, PolymerSearch};
+
+/// The number of runs to show in the ranking of a batch.
+const N_WORST_RUNS: usize = 10;
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> anyhow::Result<ExitCode> {
     let start = std::time::Instant::now();
     env_logger::builder()
         .format_timestamp(None)
@@ -23,7 +36,10 @@ async fn main() -> anyhow::Result<()> {
         .about("\u{1F9A8} mzsniffer \u{1F443} - Detect polymer conminants in mass spectra.")
         .arg(
             Arg::new("mzml_paths")
-                .help("The mzML file(s) to analyze.")
+                .help(
+                    "The mzML or mzXML file(s), or Bruker timsTOF .d directories, to analyze. \
+                     Files may be gzipped.",
+                )
                 .num_args(1..),
         )
         .arg(
@@ -41,111 +57,619 @@ async fn main() -> anyhow::Result<()> {
                 .help("Use Da instead of ppm as the precursor mass tolerance unit.")
                 .action(clap::ArgAction::SetTrue),
         )
-        .arg(Arg::new("format").short('f').long("format").help(
-            "Specify an output format to be sent to stdout. \
-                     Must be one of 'json' or 'pickle'.",
+        .arg(
+            Arg::new("auto_tolerance")
+                .long("auto-tolerance")
+                .help(
+                    "Estimate the systematic mass offset and spread from polymer \
+                     matches within --tolerance, then search again with a \
+                     data-driven tolerance.",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("rt_min")
+                .long("rt-min")
+                .value_name("RT")
+                .help(
+                    "Only search spectra at or after this retention time, \
+                     in the units of the input file.",
+                )
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("rt_max")
+                .long("rt-max")
+                .value_name("RT")
+                .help(
+                    "Only search spectra at or before this retention time, \
+                     in the units of the input file.",
+                )
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("decoys")
+                .long("decoys")
+                .value_name("N")
+                .help(
+                    "Search N decoys of each polymer, with every ion shifted \
+                     by a fraction of an m/z, to estimate the background intensity.",
+                )
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("isotopes")
+                .long("isotopes")
+                .help(
+                    "Sum the isotope envelope of each polymer ion, \
+                     rather than only the monoisotopic peak.",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("min_isotope_score")
+                .long("min-isotope-score")
+                .value_name("SCORE")
+                .help(
+                    "Only count isotope envelopes whose cosine similarity to the \
+                     theoretical envelope is at least SCORE. Implies --isotopes.",
+                )
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(Arg::new("library").short('l').long("library").help(
+            "A TOML or JSON file of polymers to search for. \
+                     These are added to the default polymers.",
         ))
+        .arg(
+            Arg::new("replace")
+                .long("replace-defaults")
+                .help("Search only the polymers in the --library file.")
+                .requires("library")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("max_tic")
+                .long("max-tic")
+                .value_name("NAME=PCT")
+                .help(
+                    "Fail QC if the polymer NAME exceeds PCT %TIC. \
+                     May be used more than once.",
+                )
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("max_total_tic")
+                .long("max-total-tic")
+                .value_name("PCT")
+                .help("Fail QC if all polymers combined exceed PCT %TIC.")
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("baseline")
+                .long("baseline")
+                .value_name("PATH")
+                .help(
+                    "Compare each run to the median %TIC of each polymer in results \
+                     previously saved with '--format json' or '--format pickle'.",
+                ),
+        )
+        .arg(
+            Arg::new("max_fold_change")
+                .long("max-fold-change")
+                .value_name("FOLD")
+                .help(
+                    "Flag polymers whose %TIC changed by at least FOLD relative \
+                     to the baseline.",
+                )
+                .default_value("2")
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("baseline_report")
+                .long("baseline-report")
+                .value_name("PATH")
+                .help("Write the comparison of each run to the baseline to this JSON file.")
+                .requires("baseline"),
+        )
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .help(
+                    "Specify an output format to be sent to stdout. \
+                     Must be one of 'json', 'pickle', 'tsv', or 'csv'.",
+                )
+                .ignore_case(true)
+                .value_parser(OutputFormat::NAMES),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("Write the --format output to this file instead of stdout.")
+                .requires("format"),
+        )
+        .arg(
+            Arg::new("mzqc")
+                .long("mzqc")
+                .help("Write the results to this file as an mzQC document."),
+        )
+        .arg(Arg::new("summary").long("summary").help(
+            "Write a table of the total intensity of each polymer to this file. \
+             The table is comma-separated if the file ends in '.csv' and \
+             tab-separated otherwise.",
+        ))
+        .arg(
+            Arg::new("compositions")
+                .long("compositions")
+                .value_name("PATH")
+                .help(
+                    "Write a table of the total intensity of each composition of each \
+                     copolymer to this file. The table is comma-separated if the file \
+                     ends in '.csv' and tab-separated otherwise.",
+                ),
+        )
+        .arg(
+            Arg::new("batch_summary")
+                .long("batch-summary")
+                .value_name("PATH")
+                .help(
+                    "Write a JSON comparison of all runs to this file: the %TIC of each \
+                     polymer in each run, the runs ranked by total %TIC, and the median, \
+                     maximum, and outlying runs of each polymer.",
+                ),
+        )
+        .arg(
+            Arg::new("discover")
+                .long("discover")
+                .help(
+                    "Find homologous series of unknown polymers using their \
+                     Kendrick mass defects, instead of searching for known polymers.",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("discover_library")
+                .long("discover-library")
+                .value_name("PATH")
+                .help("Write the discovered series to this TOML or JSON library file.")
+                .requires("discover"),
+        )
         .help_template(
             "{usage-heading} {usage}\n\n\
              {about-with-newline}\n\
              Written by {author-with-newline}Version {version}\n\n\
              {all-args}{after-help}",
         )
-        .get_matches();
+        .after_help(
+            "Exit status is 0 if all runs pass QC, 1 if an error occurs, \
+             and 2 if any run exceeds a --max-tic or --max-total-tic threshold.",
+        )
+        .try_get_matches()
+        .unwrap_or_else(|err| {
+            let _ = err.print();
+            match err.use_stderr() {
+                true => std::process::exit(EXIT_ERROR.into()),
+                false => std::process::exit(EXIT_CLEAN.into()),
+            }
+        });
 
-    let mut mzml_paths: Vec<String> = Vec::new();
-    if let Some(f) = matches.get_many::<String>("mzml_paths") {
-        mzml_paths = f.into_iter().map(|p| p.into()).collect();
-    } else {
-        error!("An mzML file must be provided.")
-    }
+    let mzml_paths: Vec<String> = match matches.get_many::<String>("mzml_paths") {
+        Some(f) => f.into_iter().map(|p| p.into()).collect(),
+        None => {
+            error!("An mzML file, mzXML file, or .d directory must be provided.");
+            return Ok(ExitCode::from(EXIT_ERROR));
+        }
+    };
     let unit = match matches.get_one::<bool>("unit") {
         Some(true) => "da",
         Some(false) => "ppm",
         None => unreachable!("This shouldnt happen."),
     };
     let tol = matches.get_one::<f64>("tol").unwrap();
+    let min_score = matches.get_one::<f64>("min_isotope_score").copied();
+    let isotopes = match matches.get_flag("isotopes") || min_score.is_some() {
+        true => Some(IsotopeSettings {
+            min_score,
+            ..Default::default()
+        }),
+        false => None,
+    };
 
-    let out_format = matches.get_one::<String>("format");
-    if let Some(txt) = out_format {
-        match txt.to_lowercase().as_str() {
-            "json" | "pickle" => {}
-            _ => error!("Unrecognized output format."),
+    let out_format = matches
+        .get_one::<String>("format")
+        .map(|x| x.parse::<OutputFormat>())
+        .transpose()
+        .map_err(anyhow::Error::msg)?;
+
+    if matches.get_flag("discover") {
+        let settings = DiscoverySettings {
+            tol: *tol,
+            unit: unit.to_string(),
+            ..Default::default()
+        };
+        let mut results = Vec::new();
+        let mut discovered = PolymerLibrary::new();
+        for mzml_path in mzml_paths.iter() {
+            let candidates = discover(mzml_path, settings.clone()).await?;
+            for candidate in candidates.iter() {
+                let name = candidate.name();
+                if !discovered.polymers.iter().any(|x| x.name == name) {
+                    discovered.polymers.push(candidate.to_polymer(&name));
+                }
+            }
+            results.push(DiscoveryResults {
+                filename: file_name(mzml_path),
+                candidates,
+            });
         }
+        if let Some(fmt) = out_format {
+            fmt.write_discovered(output_writer(&matches)?, &results)?;
+        }
+        if let Some(path) = matches.get_one::<String>("discover_library") {
+            discovered.to_path(path)?;
+        }
+        return Ok(ExitCode::from(EXIT_CLEAN));
+    }
+
+    let mut library = match matches.get_one::<bool>("replace") {
+        Some(true) => PolymerLibrary::new(),
+        _ => PolymerLibrary::from(DefaultPolymers::new()),
+    };
+    if let Some(lib_path) = matches.get_one::<String>("library") {
+        info!("Reading polymers from {}...", lib_path);
+        library = library.merge(PolymerLibrary::from_path(lib_path)?);
+    }
+
+    let mut thresholds = Thresholds::from(&library);
+    if let Some(max_tics) = matches.get_many::<String>("max_tic") {
+        for max_tic in max_tics {
+            thresholds
+                .add_from_str(max_tic)
+                .map_err(anyhow::Error::msg)?;
+        }
+    }
+    if let Some(max_total) = matches.get_one::<f64>("max_total_tic") {
+        thresholds.total = Some(*max_total);
+    }
+    for name in thresholds.polymers.keys() {
+        if !library.polymers.iter().any(|x| &x.name == name) {
+            warn!(
+                "No polymer named '{}' to check against its threshold.",
+                name
+            );
+        }
+    }
+
+    let baseline = match matches.get_one::<String>("baseline") {
+        Some(path) => {
+            info!("Reading baseline results from {}...", path);
+            Some(Baseline::from_path(path)?)
+        }
+        None => None,
+    };
+    let max_fold = matches
+        .get_one::<f64>("max_fold_change")
+        .copied()
+        .unwrap_or(DEFAULT_MAX_FOLD);
+    if max_fold <= 1. {
+        return Err(anyhow::Error::msg(
+            "--max-fold-change must be greater than 1.",
+        ));
     }
 
     // Actually do stuff:
+    let mut search = PolymerSearch::new(library.polymers.clone(), tol, unit);
+    if let Some(settings) = isotopes {
+        search = search.with_isotopes(settings);
+    }
+    search = search.with_rt_range(
+        matches.get_one::<f64>("rt_min").copied(),
+        matches.get_one::<f64>("rt_max").copied(),
+    );
+    if let Some(n_decoys) = matches.get_one::<usize>("decoys") {
+        search = search.with_decoys(*n_decoys);
+    }
+    let auto_tolerance = matches.get_flag("auto_tolerance");
     let results: Vec<PolymerResults> = futures::future::try_join_all(
         mzml_paths
-            .into_iter()
-            .map(|x| run(x, *tol, unit.to_string())),
+            .iter()
+            .cloned()
+            .map(|x| run(x, search.clone(), auto_tolerance)),
     )
     .await?;
 
-    // Write to stdout if required:
-    if let Some(txt) = out_format {
-        let mut out_writer = BufWriter::new(std::io::stdout());
-        match txt.to_lowercase().as_str() {
-            "json" => serde_json::to_writer_pretty(out_writer, &results)?,
-            "pickle" => serde_pickle::to_writer(
-                &mut out_writer,
-                &results,
-                serde_pickle::ser::SerOptions::new(),
-            )?,
-            _ => unreachable!("This should never happen..."),
-        }
+    // Write to stdout or a file if required:
+    if let Some(fmt) = out_format {
+        fmt.write(output_writer(&matches)?, &results)?;
     };
 
+    if let Some(path) = matches.get_one::<String>("mzqc") {
+        let mut mzqc = MzQC::new();
+        for (run_results, mzml_path) in results.iter().zip(mzml_paths.iter()) {
+            mzqc.add_run(run_results, mzml_path)?;
+        }
+        mzqc.to_writer(BufWriter::new(File::create(path)?))?;
+    }
+
+    if let Some(path) = matches.get_one::<String>("summary") {
+        let delimiter = match path.to_lowercase().ends_with(".csv") {
+            true => b',',
+            false => b'\t',
+        };
+        write_summary_table(BufWriter::new(File::create(path)?), &results, delimiter)?;
+    }
+
+    if let Some(path) = matches.get_one::<String>("compositions") {
+        let delimiter = match path.to_lowercase().ends_with(".csv") {
+            true => b',',
+            false => b'\t',
+        };
+        write_composition_table(BufWriter::new(File::create(path)?), &results, delimiter)?;
+    }
+
+    // Compare the runs:
+    let batch_path = matches.get_one::<String>("batch_summary");
+    if results.len() > 1 || batch_path.is_some() {
+        let batch = BatchSummary::new(&results);
+        if results.len() > 1 {
+            batch.log(N_WORST_RUNS);
+        }
+        if let Some(path) = batch_path {
+            serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &batch)?;
+        }
+    }
+
+    // Compare to the baseline:
+    if let Some(baseline) = &baseline {
+        for name in library.polymers.iter().map(|x| &x.name) {
+            if !baseline.percent_tic.contains_key(name) {
+                warn!(
+                    "No polymer named '{}' in the baseline to compare against.",
+                    name
+                );
+            }
+        }
+        let comparisons: Vec<_> = results
+            .iter()
+            .map(|x| baseline.compare(x, max_fold))
+            .collect();
+        for comparison in comparisons.iter() {
+            comparison.log();
+        }
+        if let Some(path) = matches.get_one::<String>("baseline_report") {
+            serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &comparisons)?;
+        }
+    }
+
+    // Evaluate QC thresholds:
+    let mut exit_code = EXIT_CLEAN;
+    if !thresholds.is_empty() {
+        for run_results in results.iter() {
+            let verdict = thresholds.evaluate(run_results);
+            verdict.log();
+            if verdict.contaminated {
+                exit_code = EXIT_CONTAMINATED;
+            }
+        }
+    }
+
     // Wrap up
     info!("DONE!");
     let total_time = std::time::Instant::now() - start;
     info!("Elapsed time: {:2}s", total_time.as_secs());
-    Ok(())
+    Ok(ExitCode::from(exit_code))
 }
 
-async fn run(mzml_file: String, tol: f64, unit: String) -> anyhow::Result<PolymerResults> {
-    info!("Reading {}...", &mzml_file);
-    let start = std::time::Instant::now();
-    let mzml_buf = File::open(mzml_file.as_str()).await?;
-    let mzml_buf = BufReader::new(mzml_buf);
-    let spectra = MzMLReader::new().parse(mzml_buf).await?;
-    let total_time = std::time::Instant::now() - start;
-    info!(" - Read time: {:2}s", total_time.as_secs());
+/// Write to the '--output' file, or stdout if there isn't one.
+fn output_writer(matches: &clap::ArgMatches) -> std::io::Result<BufWriter<Box<dyn Write>>> {
+    let out_writer: Box<dyn Write> = match matches.get_one::<String>("output") {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(std::io::stdout()),
+    };
+    Ok(BufWriter::new(out_writer))
+}
 
-    let start = std::time::Instant::now();
-    info!("Extracting MS1 signals of polymer contaminants...");
-    let mzml_file = Path::new(mzml_file.as_str())
+/// The name of an input file, without its directory.
+fn file_name(path: &str) -> String {
+    Path::new(path)
         .file_name()
         .unwrap()
         .to_str()
         .unwrap()
-        .to_string();
-    let results = search(mzml_file, spectra, &tol, unit.as_str())?;
+        .to_string()
+}
+
+async fn run(
+    mzml_path: String,
+    search: PolymerSearch,
+    auto_tolerance: bool,
+) -> anyhow::Result<PolymerResults> {
+    info!("Reading {}...", &mzml_path);
+    info!("Extracting MS1 signals of polymer contaminants...");
+    let start = std::time::Instant::now();
+    let unit = search.unit().to_string();
+    let unit_name = match unit.as_str() {
+        "da" => "Da",
+        _ => "ppm",
+    };
+    let spectra = open_spectra(mzml_path.as_str()).await?;
+    let mzml_file = file_name(&mzml_path);
+    let mut results = search
+        .clone()
+        .search_stream(mzml_file.clone(), spectra)
+        .await?;
+
+    if auto_tolerance {
+        match Calibration::estimate(&results, &unit) {
+            Some(calibration) => {
+                info!(
+                    " - Estimated a {:.4} {} offset and a {:.4} {} tolerance from {} matches.",
+                    calibration.offset, unit_name, calibration.tol, unit_name, calibration.n,
+                );
+                info!("Extracting MS1 signals again with the estimated tolerance...");
+                let spectra = open_spectra(mzml_path.as_str()).await?;
+                results = search
+                    .with_calibration(&calibration)
+                    .search_stream(mzml_file, spectra)
+                    .await?;
+            }
+            None => warn!(
+                "Too few polymer matches in {} to estimate a tolerance. Keeping the original.",
+                mzml_file
+            ),
+        }
+    }
+
     let total_time = std::time::Instant::now() - start;
-    info!(" - Extraction time: {:2}s ", total_time.as_secs());
-    info!("{}", "+".repeat(36));
-    info!("Polymer                         %TIC");
-    info!("{}", "+".repeat(36));
+    info!(" - Read and extraction time: {:2}s ", total_time.as_secs());
+    let decoys = results.polymers.iter().any(|x| x.decoys.is_some());
+    let (width, header) = match decoys {
+        true => (
+            70,
+            "Polymer                         %TIC  Mean n  Bkgd %TIC  Enrich.  p-value",
+        ),
+        false => (44, "Polymer                         %TIC  Mean n"),
+    };
+    info!("{}", "+".repeat(width));
+    info!("{}", header);
+    info!("{}", "+".repeat(width));
 
     // Print a brief report to stderr:
-    for poly in results.polymers.clone().into_iter() {
-        let poly_total = 100. * poly.total / results.total;
-        info!("{:26}  {:>8.4}", &poly.name, poly_total);
+    for poly in results.polymers.iter() {
+        let mut line = format!("{:26}  {:>8.4}", &poly.name, results.percent_tic(poly));
+        match poly.mean_chain_length() {
+            Some(n) => line += &format!("  {:>6.1}", n),
+            None => line += &format!("  {:>6}", "-"),
+        };
+        if let (Some(stats), Some(background)) = (&poly.decoys, results.background_tic(poly)) {
+            line += &format!("  {:>9.4}", background);
+            match stats.enrichment {
+                Some(enrichment) => line += &format!("  {:>7.1}", enrichment),
+                None => line += &format!("  {:>7}", "-"),
+            };
+            line += &format!("  {:>7.3}", stats.p_value);
+        }
+        info!("{}", line);
+
+        let detected: Vec<_> = poly.series.iter().filter(|x| x.total > 0.).collect();
+        if detected.len() > 1 {
+            for series in detected.into_iter() {
+                let series_total = 100. * series.total / results.observable_tic(poly);
+                info!("  {:24}  {:>8.4}", &series.name, series_total);
+            }
+        }
+    }
+    info!("{}", "+".repeat(width));
+    info!("");
+
+    // The MS2 spectra spent on each polymer:
+    if results.ms2_scans > 0 {
+        info!("{}", "+".repeat(44));
+        info!("Polymer                    MS2 scans    %MS2");
+        info!("{}", "+".repeat(44));
+        for poly in results.polymers.iter() {
+            info!(
+                "{:26}  {:>9}  {:>6.2}",
+                &poly.name,
+                poly.ms2_scans,
+                results.percent_ms2(poly)
+            );
+        }
+        info!("{}", "+".repeat(44));
+        info!("Total MS2 scans: {}", results.ms2_scans);
+        info!("");
     }
-    info!("{}", "+".repeat(36));
+
+    // The most intense chromatographic peak of each polymer:
+    info!("{}", "+".repeat(56));
+    info!("Polymer                    Peaks  Apex RT     FWHM      Area");
+    info!("{}", "+".repeat(56));
+    for poly in results.polymers.iter() {
+        if let Some(peak) = poly.peaks.first() {
+            info!(
+                "{:26}  {:>5}  {:>7.2}  {:>7.2}  {:>8.2e}",
+                &poly.name,
+                poly.peaks.len(),
+                peak.apex_rt,
+                peak.fwhm,
+                peak.area
+            );
+        }
+    }
+    info!("{}", "+".repeat(56));
+    info!("");
+
+    // The mass error of the matched peaks:
+    info!("{}", "+".repeat(52));
+    info!("Polymer                    Matches  Median     MAD");
+    let unit_label = format!("({})", unit_name);
+    info!("{:35}  {:>6}  {:>6}", "", unit_label, unit_label);
+    info!("{}", "+".repeat(52));
+    for poly in results.polymers.iter() {
+        let summary = match unit.as_str() {
+            "da" => &poly.mass_error_da,
+            _ => &poly.mass_error_ppm,
+        };
+        if let Some(summary) = summary {
+            info!(
+                "{:26}  {:>7}  {:>6.3}  {:>6.3}",
+                &poly.name, summary.n, summary.median, summary.mad
+            );
+        }
+    }
+    info!("{}", "+".repeat(52));
     info!("");
 
     Ok(results)
 }
 
+async fn discover(
+    mzml_file: &str,
+    settings: DiscoverySettings,
+) -> anyhow::Result<Vec<CandidateSeries>> {
+    info!("Reading {}...", mzml_file);
+    info!("Finding homologous series...");
+    let spectra = open_spectra(mzml_file).await?;
+    let candidates = discover_stream(spectra, settings).await?;
+
+    // Print a brief report to stderr:
+    info!("{}", "+".repeat(52));
+    info!("Repeat (Da)  Charge      KMD  Members  First m/z     %TIC");
+    info!("{}", "+".repeat(52));
+    for candidate in candidates.iter() {
+        let sign = match candidate.polarity {
+            Polarity::Negative => "-",
+            _ => "+",
+        };
+        info!(
+            "{:>11.4}  {:>5}{}  {:>7.4}  {:>7}  {:>9.4}  {:>7.4}",
+            candidate.repeat_mass,
+            candidate.charge,
+            sign,
+            candidate.kendrick_mass_defect,
+            candidate.members.len(),
+            candidate.members[0],
+            candidate.percent_tic,
+        );
+    }
+    info!("{}", "+".repeat(52));
+    info!("");
+
+    Ok(candidates)
+}
+
 #[cfg(test)]
 mod tests {
     use super::run;
+    use mzsniffer::defaults::DefaultPolymers;
+    use mzsniffer::search::PolymerSearch;
     const TEST_FILE: &str = "data/MSV000081544.20170728_MS1_17k_plasmaspikedPEG_3.mzML";
 
     #[tokio::test]
     async fn smoke() {
-        let results = run(TEST_FILE.to_string(), 10., "ppm".to_string()).await;
+        let search = PolymerSearch::new(DefaultPolymers::new().0, &10., "ppm");
+        let results = run(TEST_FILE.to_string(), search, false).await;
         for poly in results.unwrap().polymers.into_iter() {
             if poly.name == "PEG+1H" {
                 assert!(
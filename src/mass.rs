@@ -1,36 +1,407 @@
+use std::collections::BTreeMap;
+use std::ops::{Add, Mul, Sub};
+use std::str::FromStr;
+
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 
-// Atoms
-fn atomic_mass(atom: &str) -> f64 {
-    match atom {
-        "C" => 12.0000000000000,
-        "H" => 1.007825032071,
-        "O" => 15.9949146195616,
-        "N" => 14.00307400486,
-        "Na" => 22.989769282019,
-        "Si" => 27.97692653505,
-        _ => unreachable!("BUG: Unknown atom {}.", atom),
-    }
-}
+use crate::elements::{element_by_symbol, Element, ELEMENTS};
+
+const HYDROGEN: &Element = &ELEMENTS[0];
 
 // Useful constants
 pub const PROTON: f64 = 1.00727646681290;
 pub const NEUTRON: f64 = 1.0086649158849;
+pub const ELECTRON: f64 = 0.000548579909065;
+/// The mass difference between the 13C and 12C isotopes.
+pub const ISOTOPE_SPACING: f64 = 1.0033548378;
+
+/// An empirical formula, such as `C2H4O`.
+///
+/// Formulas are parsed from strings and may contain groups with a count,
+/// such as `CH3(CH2)4CH3` or `[Si(CH3)2O]2`, hydrates such as
+/// `CuSO4.5H2O`, and negative counts such as `H-1`. Formulas can be added,
+/// subtracted, and multiplied by an integer.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Formula(BTreeMap<u8, i32>);
+
+impl Formula {
+    /// Create an empty formula.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a formula containing a single repeating group, such as
+    /// `H(C2H4O)nOH`.
+    ///
+    /// Returns the formula of the non-repeating parts and the formula of
+    /// the repeating group.
+    pub fn parse_repeat(s: &str) -> Result<(Self, Self), FormulaError> {
+        let (core, repeat) = FormulaParser::new(s).parse()?;
+        let repeat = repeat.ok_or_else(|| FormulaError::MissingRepeat(s.to_string()))?;
+        Ok((core, repeat))
+    }
+
+    /// Add `count` atoms of an element.
+    fn add_atoms(&mut self, element: &Element, count: i32) {
+        let total = self.0.entry(element.number).or_insert(0);
+        *total += count;
+        if *total == 0 {
+            self.0.remove(&element.number);
+        }
+    }
+
+    /// The number of atoms of an element.
+    pub fn count(&self, symbol: &str) -> i32 {
+        element_by_symbol(symbol)
+            .and_then(|x| self.0.get(&x.number))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Does the formula contain no atoms?
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The elements in the formula and their counts.
+    pub fn elements(&self) -> impl Iterator<Item = (&'static Element, i32)> + '_ {
+        self.0
+            .iter()
+            .map(|(number, count)| (&ELEMENTS[*number as usize - 1], *count))
+    }
+
+    /// The monoisotopic mass, using the lightest isotope of each element.
+    pub fn monoisotopic_mass(&self) -> f64 {
+        self.elements()
+            .map(|(element, count)| count as f64 * element.monoisotopic_mass())
+            .sum()
+    }
+
+    /// The average mass, using the standard atomic weight of each element.
+    pub fn average_mass(&self) -> f64 {
+        self.elements()
+            .map(|(element, count)| count as f64 * element.average_mass)
+            .sum()
+    }
+}
+
+impl FromStr for Formula {
+    type Err = FormulaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match FormulaParser::new(s).parse()? {
+            (formula, None) => Ok(formula),
+            (_, Some(_)) => Err(FormulaError::UnexpectedRepeat(s.to_string())),
+        }
+    }
+}
+
+/// Formulas are written in Hill order: carbon, then hydrogen, then the
+/// other elements alphabetically. Without carbon, all elements are
+/// written alphabetically.
+impl std::fmt::Display for Formula {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let has_carbon = self.count("C") != 0;
+        let mut elements: Vec<_> = self.elements().collect();
+        elements.sort_by_key(|(element, _)| match (has_carbon, element.symbol) {
+            (true, "C") => (0, element.symbol),
+            (true, "H") => (1, element.symbol),
+            _ => (2, element.symbol),
+        });
+
+        for (element, count) in elements {
+            match count {
+                1 => write!(f, "{}", element.symbol)?,
+                _ => write!(f, "{}{}", element.symbol, count)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for Formula {
+    type Error = FormulaError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Formula> for String {
+    fn from(formula: Formula) -> Self {
+        formula.to_string()
+    }
+}
+
+impl Add for Formula {
+    type Output = Self;
+
+    fn add(mut self, other: Self) -> Self {
+        for (element, count) in other.elements() {
+            self.add_atoms(element, count);
+        }
+        self
+    }
+}
+
+impl Sub for Formula {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self + other * -1
+    }
+}
+
+impl Mul<i32> for Formula {
+    type Output = Self;
+
+    fn mul(mut self, n: i32) -> Self {
+        if n == 0 {
+            return Self::new();
+        }
+        self.0.values_mut().for_each(|count| *count *= n);
+        self
+    }
+}
+
+/// A recursive descent parser for empirical formulas.
+struct FormulaParser<'a> {
+    /// The formula being parsed, for error messages.
+    text: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+    /// How many groups deep the parser is.
+    depth: usize,
+    /// The repeating group, such as the `(C2H4O)n` of `H(C2H4O)nOH`.
+    repeat: Option<Formula>,
+}
+
+impl<'a> FormulaParser<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            chars: text.chars().filter(|c| !c.is_whitespace()).collect(),
+            pos: 0,
+            depth: 0,
+            repeat: None,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    /// Parse the formula and its repeating group, if any.
+    ///
+    /// Hydrates are separated by '.', '·', or '*' and may start with a
+    /// multiplier, such as the '5' in `CuSO4.5H2O`.
+    fn parse(mut self) -> Result<(Formula, Option<Formula>), FormulaError> {
+        let mut formula = Formula::new();
+        loop {
+            let had_repeat = self.repeat.is_some();
+            let multiplier = self.number()?.unwrap_or(1);
+            let component = self.group(None)?;
+            if multiplier != 1 && !had_repeat && self.repeat.is_some() {
+                return Err(FormulaError::UnexpectedRepeat(self.text.to_string()));
+            }
+            formula = formula + component * multiplier;
+
+            match self.peek() {
+                None => return Ok((formula, self.repeat)),
+                Some(_) => self.pos += 1,
+            }
+        }
+    }
+
+    /// Parse atoms and groups until the `close` bracket or, at the top
+    /// level, the end of a hydrate component.
+    fn group(&mut self, close: Option<char>) -> Result<Formula, FormulaError> {
+        let mut formula = Formula::new();
+        loop {
+            match self.peek() {
+                Some(c) if Some(c) == close => {
+                    self.pos += 1;
+                    return Ok(formula);
+                }
+                None | Some('.' | '·' | '*') if close.is_none() => return Ok(formula),
+                None | Some(')' | ']') => {
+                    return Err(FormulaError::UnbalancedParentheses(self.text.to_string()))
+                }
+                Some(open @ ('(' | '[')) => {
+                    self.pos += 1;
+                    self.depth += 1;
+                    let inner = self.group(Some(if open == '(' { ')' } else { ']' }))?;
+                    self.depth -= 1;
+                    if self.peek() == Some('n') {
+                        self.pos += 1;
+                        if self.depth > 0 || self.repeat.is_some() {
+                            return Err(FormulaError::UnexpectedRepeat(self.text.to_string()));
+                        }
+                        self.repeat = Some(inner);
+                    } else {
+                        formula = formula + inner * self.count()?;
+                    }
+                }
+                Some(c) if c.is_ascii_uppercase() => {
+                    let start = self.pos;
+                    self.pos += 1;
+                    while self.peek().map_or(false, |c| c.is_ascii_lowercase()) {
+                        self.pos += 1;
+                    }
+                    let symbol: String = self.chars[start..self.pos].iter().collect();
+                    let element =
+                        element_by_symbol(&symbol).ok_or(FormulaError::UnknownElement(symbol))?;
+                    let count = self.count()?;
+                    formula.add_atoms(element, count);
+                }
+                Some(c) => return Err(FormulaError::UnexpectedCharacter(c, self.text.to_string())),
+            }
+        }
+    }
+
+    /// Parse an optional, possibly negative, count. Counts default to 1.
+    fn count(&mut self) -> Result<i32, FormulaError> {
+        let negative = self.peek() == Some('-');
+        if negative {
+            self.pos += 1;
+        }
+        match (self.number()?, negative) {
+            (Some(n), true) => Ok(-n),
+            (Some(n), false) => Ok(n),
+            (None, true) => Err(FormulaError::InvalidCount(self.text.to_string())),
+            (None, false) => Ok(1),
+        }
+    }
+
+    /// Parse an optional number.
+    fn number(&mut self) -> Result<Option<i32>, FormulaError> {
+        let start = self.pos;
+        while self.peek().map_or(false, |c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Ok(None);
+        }
+        self.chars[start..self.pos]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .map(Some)
+            .map_err(|_| FormulaError::InvalidCount(self.text.to_string()))
+    }
+}
 
-// Compute the mass from an empirical formula.
-pub fn formula_mass(formula: &str) -> f64 {
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r"([A-Z][a-z]?)(\d*)").unwrap();
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormulaError {
+    UnknownElement(String),
+    UnexpectedCharacter(char, String),
+    UnbalancedParentheses(String),
+    InvalidCount(String),
+    UnexpectedRepeat(String),
+    MissingRepeat(String),
+    NegativeCount(String),
+}
+
+impl std::fmt::Display for FormulaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormulaError::UnknownElement(s) => {
+                write!(f, "FormulaError: unknown element '{}'", s)
+            }
+            FormulaError::UnexpectedCharacter(c, s) => {
+                write!(f, "FormulaError: unexpected character '{}' in '{}'", c, s)
+            }
+            FormulaError::UnbalancedParentheses(s) => {
+                write!(f, "FormulaError: unbalanced parentheses in '{}'", s)
+            }
+            FormulaError::InvalidCount(s) => {
+                write!(f, "FormulaError: invalid atom count in '{}'", s)
+            }
+            FormulaError::UnexpectedRepeat(s) => write!(
+                f,
+                "FormulaError: a repeating group is not allowed here in '{}'",
+                s
+            ),
+            FormulaError::MissingRepeat(s) => write!(
+                f,
+                "FormulaError: expected a repeating group, such as '(C2H4O)n', in '{}'",
+                s
+            ),
+            FormulaError::NegativeCount(s) => {
+                write!(f, "FormulaError: negative atom count in '{}'", s)
+            }
+        }
     }
+}
+
+impl std::error::Error for FormulaError {}
+
+// Compute the monoisotopic mass from an empirical formula.
+pub fn formula_mass(formula: &str) -> Result<f64, FormulaError> {
+    Ok(formula.parse::<Formula>()?.monoisotopic_mass())
+}
+
+/// Compute the isotope distribution of an empirical formula.
+///
+/// Isotopes are grouped by their nominal mass, so the distribution is a
+/// list of the mean mass and total abundance of the M+0, M+1, ... peaks,
+/// up to `n_peaks` peaks. The M+0 peak contains the lightest isotope of
+/// every element. Formulas with negative counts have no distribution.
+pub fn isotope_distribution(
+    formula: &Formula,
+    n_peaks: usize,
+) -> Result<Vec<(f64, f64)>, FormulaError> {
+    // Each peak is (abundance, abundance-weighted mass):
+    let mut dist = vec![(1., 0.)];
+    for (element, count) in formula.elements() {
+        if count < 0 {
+            return Err(FormulaError::NegativeCount(formula.to_string()));
+        }
 
-    let mut total_mass = 0.;
-    for cap in RE.captures_iter(formula) {
-        let num = &cap[2].parse::<f64>().unwrap_or(1.);
-        total_mass += num * atomic_mass(&cap[1]);
+        let lightest = element.isotopes[0].0;
+        let atom_dist: Vec<(f64, f64)> = element
+            .isotopes
+            .iter()
+            .map(|(mass, abundance)| ((mass - lightest).round() as usize, (*mass, *abundance)))
+            .fold(Vec::new(), |mut acc, (shift, (mass, abundance))| {
+                if acc.len() <= shift {
+                    acc.resize(shift + 1, (0., 0.));
+                }
+                acc[shift] = (abundance, abundance * mass);
+                acc
+            });
+
+        for _ in 0..count {
+            dist = convolve(&dist, &atom_dist, n_peaks);
+        }
     }
 
-    total_mass
+    Ok(dist
+        .into_iter()
+        .map(|(abundance, mass)| match abundance > 0. {
+            true => (mass / abundance, abundance),
+            false => (0., 0.),
+        })
+        .collect())
+}
+
+fn convolve(a: &[(f64, f64)], b: &[(f64, f64)], n_peaks: usize) -> Vec<(f64, f64)> {
+    let mut out = vec![(0., 0.); (a.len() + b.len() - 1).min(n_peaks)];
+    for (i, (a_abundance, a_mass)) in a.iter().enumerate() {
+        for (j, (b_abundance, b_mass)) in b.iter().enumerate() {
+            if i + j >= n_peaks {
+                break;
+            }
+            let abundance = a_abundance * b_abundance;
+            out[i + j].0 += abundance;
+            // The mean masses add, weighted by the joint abundance:
+            out[i + j].1 += a_mass * b_abundance + b_mass * a_abundance;
+        }
+    }
+    out
 }
 
 pub fn mass_to_mz(mass: f64, charge: i32, protonate: bool) -> f64 {
@@ -41,20 +412,323 @@ pub fn mass_to_mz(mass: f64, charge: i32, protonate: bool) -> f64 {
     mz
 }
 
+/// An ion formed by adding atoms to or removing atoms from a molecule.
+///
+/// Adducts are written in the usual notation, such as `[M+H]+`,
+/// `[M+NH4]+`, or `[M+H+Na]2+`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Adduct {
+    /// The adduct in bracket notation.
+    pub name: String,
+    /// The net mass of the neutral atoms that are added or removed.
+    pub mass: f64,
+    /// The empirical formula of the atoms that are added or removed.
+    /// This is used to calculate isotope distributions.
+    pub formula: Formula,
+    /// The charge of the ion.
+    pub charge: i32,
+}
+
+impl Adduct {
+    pub fn new(name: &str, mass: f64, formula: Formula, charge: i32) -> Self {
+        Self {
+            name: name.to_string(),
+            mass,
+            formula,
+            charge,
+        }
+    }
+
+    /// Protons account for the charge, such as `[M+2H]2+`.
+    pub fn protonated(charge: i32) -> Self {
+        let name = match charge {
+            1 => "[M+H]+".to_string(),
+            _ => format!("[M+{charge}H]{charge}+"),
+        };
+        let mut formula = Formula::new();
+        formula.add_atoms(HYDROGEN, charge);
+        Self::new(&name, formula.monoisotopic_mass(), formula, charge)
+    }
+
+    /// Protons are removed to account for the charge, such as `[M-2H]2-`.
+    ///
+    /// `n_protons` is the number of protons that are removed.
+    pub fn deprotonated(n_protons: i32) -> Self {
+        let name = match n_protons {
+            1 => "[M-H]-".to_string(),
+            _ => format!("[M-{n_protons}H]{n_protons}-"),
+        };
+        let mut formula = Formula::new();
+        formula.add_atoms(HYDROGEN, -n_protons);
+        Self::new(&name, formula.monoisotopic_mass(), formula, -n_protons)
+    }
+
+    /// The charge is carried by atoms that are already part of the molecule.
+    pub fn intrinsic(charge: i32) -> Self {
+        let sign = if charge < 0 { '-' } else { '+' };
+        let name = match charge.abs() {
+            1 => format!("[M]{sign}"),
+            n => format!("[M]{n}{sign}"),
+        };
+        Self::new(&name, 0., Formula::new(), charge)
+    }
+
+    /// The m/z of a molecule with this adduct.
+    pub fn mz(&self, mass: f64) -> f64 {
+        (mass + self.mass - self.charge as f64 * ELECTRON) / self.charge.abs() as f64
+    }
+
+    /// The mass of the molecule that forms an ion with this adduct at `mz`.
+    pub fn neutral_mass(&self, mz: f64) -> f64 {
+        mz * self.charge.abs() as f64 - self.mass + self.charge as f64 * ELECTRON
+    }
+}
+
+impl FromStr for Adduct {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        lazy_static! {
+            static ref ION: Regex =
+                Regex::new(r"^\[M((?:[+-]\d*[A-Z][A-Za-z0-9]*)*)\](\d*)([+-])$").unwrap();
+            static ref TERM: Regex = Regex::new(r"([+-])(\d*)([A-Z][A-Za-z0-9]*)").unwrap();
+        }
+
+        let err = || format!("Invalid adduct '{}'", s);
+        let cap = ION.captures(s.trim()).ok_or_else(err)?;
+
+        let mut mass = 0.;
+        let mut formula = Formula::new();
+        for term in TERM.captures_iter(&cap[1]) {
+            let count = term[2].parse::<i32>().unwrap_or(1);
+            let atoms = term[3]
+                .parse::<Formula>()
+                .map_err(|e| format!("Invalid adduct '{}': {}", s, e))?;
+            match &term[1] {
+                "-" => {
+                    mass -= count as f64 * atoms.monoisotopic_mass();
+                    formula = formula - atoms * count;
+                }
+                _ => {
+                    mass += count as f64 * atoms.monoisotopic_mass();
+                    formula = formula + atoms * count;
+                }
+            }
+        }
+
+        let mut charge = cap[2].parse::<i32>().unwrap_or(1);
+        if &cap[3] == "-" {
+            charge = -charge;
+        }
+        if charge == 0 {
+            return Err(err());
+        }
+
+        Ok(Self::new(s.trim(), mass, formula, charge))
+    }
+}
+
+impl TryFrom<String> for Adduct {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Adduct> for String {
+    fn from(adduct: Adduct) -> Self {
+        adduct.name
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{formula_mass, mass_to_mz};
+    use super::{formula_mass, isotope_distribution, mass_to_mz, Adduct, Formula, FormulaError};
 
     #[test]
     fn test_formulas() {
-        assert_eq!(formula_mass("H2O"), 18.0105646837036);
-        assert_eq!(formula_mass("Si2H"), 56.961678102171);
+        assert_eq!(formula_mass("H2O").unwrap(), 18.0105646837036);
+        assert_eq!(formula_mass("Si2H").unwrap(), 56.961678102171);
+        assert!((formula_mass("H3PO4").unwrap() - 97.976896).abs() < 1e-6);
+        assert!((formula_mass("C8HF15O2").unwrap() - 413.973702).abs() < 1e-6);
+
+        let water: Formula = "H2O".parse().unwrap();
+        assert!((water.average_mass() - 18.015).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_formula_parsing() {
+        let parse = |s: &str| s.parse::<Formula>();
+        assert_eq!(parse("CH3(CH2)4CH3").unwrap(), parse("C6H14").unwrap());
+        assert_eq!(parse("[Si(CH3)2O]2").unwrap(), parse("C4H12O2Si2").unwrap());
+        assert_eq!(parse("CuSO4.5H2O").unwrap(), parse("CuSO9H10").unwrap());
+        assert_eq!(parse("CuSO4·5H2O").unwrap().count("H"), 10);
+        assert_eq!(parse("C2H6O H-1").unwrap().count("H"), 5);
+        assert_eq!(parse("H-1").unwrap().count("H"), -1);
+        assert!(parse("").unwrap().is_empty());
+
+        // Hill order:
+        let formula = parse("OH2(CH2)2").unwrap();
+        assert_eq!(formula.to_string(), "C2H6O");
+        assert_eq!(parse("NaCl").unwrap().to_string(), "ClNa");
+        assert_eq!(parse("H-1").unwrap().to_string(), "H-1");
+
+        assert_eq!(
+            parse("C2Xx"),
+            Err(FormulaError::UnknownElement("Xx".to_string()))
+        );
+        assert!(matches!(
+            parse("C2(H4"),
+            Err(FormulaError::UnbalancedParentheses(_))
+        ));
+        assert!(matches!(
+            parse("C2H4)"),
+            Err(FormulaError::UnbalancedParentheses(_))
+        ));
+        assert!(matches!(
+            parse("C2h4"),
+            Err(FormulaError::UnexpectedCharacter('h', _))
+        ));
+        assert!(matches!(parse("CH-"), Err(FormulaError::InvalidCount(_))));
+        assert!(matches!(
+            parse("H(C2H4O)nOH"),
+            Err(FormulaError::UnexpectedRepeat(_))
+        ));
+    }
+
+    #[test]
+    fn test_repeat() {
+        let (core, rep) = Formula::parse_repeat("H(C2H4O)nOH").unwrap();
+        assert_eq!(core, "H2O".parse().unwrap());
+        assert_eq!(rep, "C2H4O".parse().unwrap());
+
+        let (core, rep) = Formula::parse_repeat("[Si(CH3)2O]n").unwrap();
+        assert!(core.is_empty());
+        assert_eq!(rep.to_string(), "C2H6OSi");
+
+        assert!(matches!(
+            Formula::parse_repeat("H2O"),
+            Err(FormulaError::MissingRepeat(_))
+        ));
+        assert!(matches!(
+            Formula::parse_repeat("(CH2)n(CH2)n"),
+            Err(FormulaError::UnexpectedRepeat(_))
+        ));
+        assert!(matches!(
+            Formula::parse_repeat("((CH2)n)2"),
+            Err(FormulaError::UnexpectedRepeat(_))
+        ));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let water: Formula = "H2O".parse().unwrap();
+        let peg: Formula = "C2H4O".parse().unwrap();
+        let formula = water.clone() + peg.clone() * 3;
+        assert_eq!(formula.to_string(), "C6H14O4");
+        assert_eq!(formula.clone() - peg * 3, water);
+        assert!((formula.clone() * 2 - formula.clone() * 2).is_empty());
+        assert!((formula.monoisotopic_mass() - 150.089209).abs() < 1e-6);
     }
 
     #[test]
     fn test_mass_to_mz() {
-        let mass = formula_mass("H2O");
+        let mass = formula_mass("H2O").unwrap();
         assert_eq!(mass_to_mz(mass, 1, true), 19.0178411505165);
         assert_eq!(mass_to_mz(mass, 2, true), 10.0125588086647);
     }
+
+    #[test]
+    fn test_adducts() {
+        let mass = formula_mass("H2O").unwrap();
+        let proton: Adduct = "[M+H]+".parse().unwrap();
+        assert_eq!(proton, Adduct::protonated(1));
+        assert!((proton.mz(mass) - 19.0178411505165).abs() < 1e-6);
+
+        let two_protons: Adduct = "[M+2H]2+".parse().unwrap();
+        assert!((two_protons.mz(mass) - 10.0125588086647).abs() < 1e-6);
+
+        let sodium: Adduct = "[M+Na]+".parse().unwrap();
+        assert!((sodium.mz(mass) - 40.9997853858).abs() < 1e-6);
+
+        let mixed: Adduct = "[M+H+Na]2+".parse().unwrap();
+        assert_eq!(mixed.charge, 2);
+        assert!((mixed.mz(mass) - 21.0035309190).abs() < 1e-6);
+
+        let ammonium: Adduct = "[M+NH4]+".parse().unwrap();
+        assert!((ammonium.mz(mass) - 36.0443902369).abs() < 1e-6);
+
+        let deprotonated: Adduct = "[M-H]-".parse().unwrap();
+        assert_eq!(deprotonated, Adduct::deprotonated(1));
+        assert!((deprotonated.mz(mass) - 17.0032882).abs() < 1e-6);
+
+        let two_deprotonated: Adduct = "[M-2H]2-".parse().unwrap();
+        assert_eq!(two_deprotonated, Adduct::deprotonated(2));
+        assert!((two_deprotonated.mz(mass) - 7.9980059).abs() < 1e-6);
+
+        let chloride: Adduct = "[M+Cl]-".parse().unwrap();
+        assert!((chloride.mz(mass) - 52.9799659).abs() < 1e-6);
+
+        let formate: Adduct = "[M+HCOO]-".parse().unwrap();
+        assert!((formate.mz(mass) - 63.0087675).abs() < 1e-6);
+        assert!((formate.neutral_mass(formate.mz(mass)) - mass).abs() < 1e-9);
+
+        let phosphate: Adduct = "[M+H2PO4]-".parse().unwrap();
+        assert_eq!(phosphate.formula.count("P"), 1);
+
+        let water_loss: Adduct = "[M+H-H2O]+".parse().unwrap();
+        assert_eq!(water_loss.formula.to_string(), "H-1O-1");
+
+        assert_eq!(Adduct::intrinsic(1).name, "[M]+");
+        assert_eq!(Adduct::intrinsic(2).name, "[M]2+");
+        let anion = Adduct::intrinsic(-1);
+        assert_eq!(anion.name, "[M]-");
+        assert_eq!(anion.charge, -1);
+        assert_eq!(Adduct::intrinsic(-2).name, "[M]2-");
+        assert_eq!("[M]2-".parse::<Adduct>().unwrap(), Adduct::intrinsic(-2));
+
+        assert!("[M+Xx]+".parse::<Adduct>().is_err());
+        assert!("M+H".parse::<Adduct>().is_err());
+        assert!("[M+H]".parse::<Adduct>().is_err());
+    }
+
+    #[test]
+    fn test_isotope_distribution() {
+        let dist = |s: &str, n| isotope_distribution(&s.parse().unwrap(), n);
+
+        let dist_c2h6 = dist("C2H6", 3).unwrap();
+        assert_eq!(dist_c2h6.len(), 3);
+        assert!((dist_c2h6[0].0 - formula_mass("C2H6").unwrap()).abs() < 1e-9);
+        assert!((dist_c2h6[0].1 - 0.9893f64.powi(2) * 0.999885f64.powi(6)).abs() < 1e-9);
+        assert!((dist_c2h6[1].0 - dist_c2h6[0].0 - 1.0033).abs() < 1e-3);
+
+        // Larger molecules have a more abundant M+1 peak.
+        let large = dist("C100H202", 4).unwrap();
+        assert!(large[1].1 > large[0].1);
+        let total: f64 = large.iter().map(|x| x.1).sum();
+        assert!(total < 1. && total > 0.95);
+
+        let chlorine = dist("Cl", 4).unwrap();
+        assert_eq!(chlorine.len(), 3);
+        assert_eq!(chlorine[1].1, 0.);
+        assert!((chlorine[2].1 - 0.2424).abs() < 1e-9);
+
+        // Bromine has two isotopes of nearly equal abundance:
+        let bromine = dist("Br2", 5).unwrap();
+        assert!((bromine[2].1 / bromine[0].1 - 1.945).abs() < 1e-2);
+
+        // The M+0 peak is at the monoisotopic mass, even for elements whose
+        // lightest isotope is not the most abundant:
+        for formula in ["C2H4OFe", "C4H9B", "C2H5Li", "C2H6Se"] {
+            let mono = formula.parse::<Formula>().unwrap().monoisotopic_mass();
+            assert!((dist(formula, 3).unwrap()[0].0 - mono).abs() < 1e-9);
+        }
+
+        assert!(matches!(
+            dist("H-1", 3),
+            Err(FormulaError::NegativeCount(_))
+        ));
+    }
 }
@@ -1,30 +1,79 @@
 // This is almost all directly from Sage (MIT License):
 // https://github.com/lazear/sage/blob/46c3210af1d49fd9b7b5935ebae202bfd905bda1/crates/sage/src/mzml.rs
 use async_compression::tokio::bufread::ZlibDecoder;
+use futures::Stream;
 use quick_xml::events::Event;
 use quick_xml::Reader;
 use tokio::io::{AsyncBufRead, AsyncReadExt};
 
+use crate::numpress::{Numpress, NumpressError};
+
 #[derive(Default, Debug, Clone)]
 pub struct Spectrum {
     pub ms_level: u8,
     pub id: String,
     pub representation: Representation,
+    pub polarity: Polarity,
     pub scan_start_time: f64,
     pub total_ion_current: f64,
     pub mz: Vec<f64>,
     pub intensity: Vec<f64>,
+    /// The selected ions of an MS2 spectrum.
+    pub precursors: Vec<Precursor>,
+    /// The inverse ion mobility (1/K0) of each peak, when it is retained
+    /// from timsTOF data. Otherwise this is empty.
+    pub mobility: Vec<f64>,
+    /// The lower and upper m/z of each scan window. This is empty when the
+    /// scan windows were not reported.
+    pub scan_windows: Vec<(f64, f64)>,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+impl Spectrum {
+    /// Could an ion with this m/z be observed in the spectrum? Every m/z
+    /// could be observed when the scan windows were not reported.
+    pub fn in_scan_window(&self, mz: f64) -> bool {
+        self.scan_windows.is_empty()
+            || self
+                .scan_windows
+                .iter()
+                .any(|(lower, upper)| *lower <= mz && mz <= *upper)
+    }
+}
+
+/// An ion that was selected for fragmentation.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Precursor {
+    /// The m/z of the selected ion.
+    pub mz: f64,
+    /// The charge of the selected ion, if it was determined.
+    pub charge: Option<i32>,
+    /// The intensity of the selected ion, if it was reported.
+    pub intensity: Option<f64>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Representation {
+    #[default]
     Profile,
     Centroid,
 }
 
-impl Default for Representation {
-    fn default() -> Self {
-        Self::Profile
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Polarity {
+    /// The polarity was not reported. This is treated as positive.
+    #[default]
+    Unknown,
+    Positive,
+    Negative,
+}
+
+impl Polarity {
+    /// Could an ion with this charge be observed?
+    pub fn allows(&self, charge: i32) -> bool {
+        match self {
+            Polarity::Unknown | Polarity::Positive => charge > 0,
+            Polarity::Negative => charge < 0,
+        }
     }
 }
 
@@ -36,6 +85,7 @@ enum State {
     BinaryDataArray,
     Binary,
     Precursor,
+    SelectedIon,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -53,6 +103,12 @@ enum Dtype {
 // MUST supply only one of the following
 const ZLIB_COMPRESSION: &str = "MS:1000574";
 const NO_COMPRESSION: &str = "MS:1000576";
+const NUMPRESS_LINEAR: &str = "MS:1002312";
+const NUMPRESS_PIC: &str = "MS:1002313";
+const NUMPRESS_SLOF: &str = "MS:1002314";
+const NUMPRESS_LINEAR_ZLIB: &str = "MS:1002746";
+const NUMPRESS_PIC_ZLIB: &str = "MS:1002747";
+const NUMPRESS_SLOF_ZLIB: &str = "MS:1002748";
 
 // MUST supply only one of the following
 const INTENSITY_ARRAY: &str = "MS:1000515";
@@ -66,17 +122,48 @@ const FLOAT_32: &str = "MS:1000521";
 const MS_LEVEL: &str = "MS:1000511";
 const PROFILE: &str = "MS:1000128";
 const CENTROID: &str = "MS:1000127";
+const POSITIVE_SCAN: &str = "MS:1000130";
+const NEGATIVE_SCAN: &str = "MS:1000129";
 const TOTAL_ION_CURRENT: &str = "MS:1000285";
 const SCAN_START_TIME: &str = "MS:1000016";
 const SCAN_WINDOW_LOWER: &str = "MS:1000501";
 const SCAN_WINDOW_UPPER: &str = "MS:1000500";
 
-#[derive(Debug)]
+// Selected ion cvParams:
+const SELECTED_ION_MZ: &str = "MS:1000744";
+const CHARGE_STATE: &str = "MS:1000041";
+const PEAK_INTENSITY: &str = "MS:1000042";
+
+#[derive(Debug, Clone)]
 pub struct MS1Spectra {
     pub spectra: Vec<Spectrum>,
+    /// The smallest and largest m/z of the scan windows of every spectrum.
+    /// Each bound is 0 if it was never reported.
     pub scan_range: (f64, f64),
 }
 
+impl MS1Spectra {
+    /// Collect spectra, finding the scan range from their scan windows.
+    pub fn new(spectra: Vec<Spectrum>) -> Self {
+        let windows = spectra.iter().flat_map(|x| x.scan_windows.iter());
+        let lower = windows
+            .clone()
+            .map(|x| x.0)
+            .filter(|x| x.is_finite())
+            .fold(f64::INFINITY, f64::min);
+        let upper = windows
+            .map(|x| x.1)
+            .filter(|x| x.is_finite())
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        let or_zero = |x: f64| if x.is_finite() { x } else { 0. };
+        Self {
+            scan_range: (or_zero(lower), or_zero(upper)),
+            spectra,
+        }
+    }
+}
+
 pub struct MzMLReader;
 
 impl MzMLReader {
@@ -85,21 +172,69 @@ impl MzMLReader {
         Self
     }
 
-    /// "Here be dragons" - @lazear
+    /// Read all of the MS1 spectra into memory.
     pub async fn parse<B: AsyncBufRead + Unpin>(&self, b: B) -> Result<MS1Spectra, MzMLError> {
-        let mut reader = Reader::from_reader(b);
-        let mut buf = Vec::new();
+        let mut parser = SpectrumParser::new(b);
+        let mut spectra = Vec::new();
+        while let Some(spectrum) = parser.next_spectrum().await? {
+            if spectrum.ms_level == 1 {
+                spectra.push(spectrum);
+            }
+        }
 
-        let mut state = None;
-        let mut compression = false;
-        let mut output_buffer = Vec::with_capacity(4096);
-        let mut binary_dtype = Dtype::F64;
-        let mut binary_array = None;
+        Ok(MS1Spectra::new(spectra))
+    }
 
-        let mut spectrum = Spectrum::default();
-        let mut spectra = Vec::new();
-        let mut scan_range = (0., 0.);
+    /// Lazily read the MS1 and MS2 spectra, one at a time.
+    ///
+    /// Unlike [`MzMLReader::parse`], only the current spectrum is held in
+    /// memory. MS2 spectra only have their precursors; their peaks are not
+    /// decoded. The stream ends after the first error.
+    pub fn stream<B: AsyncBufRead + Unpin>(
+        &self,
+        b: B,
+    ) -> impl Stream<Item = Result<Spectrum, MzMLError>> {
+        futures::stream::unfold(Some(SpectrumParser::new(b)), |parser| async move {
+            let mut parser = parser?;
+            match parser.next_spectrum().await {
+                Ok(Some(spectrum)) => Some((Ok(spectrum), Some(parser))),
+                Ok(None) => None,
+                Err(err) => Some((Err(err), None)),
+            }
+        })
+    }
+}
+
+/// The state required to resume parsing where the last spectrum ended.
+pub(crate) struct SpectrumParser<B> {
+    reader: Reader<B>,
+    buf: Vec<u8>,
+    state: Option<State>,
+    compression: bool,
+    numpress: Option<Numpress>,
+    output_buffer: Vec<u8>,
+    binary_dtype: Dtype,
+    binary_array: Option<BinaryKind>,
+    spectrum: Spectrum,
+}
+
+impl<B: AsyncBufRead + Unpin> SpectrumParser<B> {
+    pub(crate) fn new(b: B) -> Self {
+        Self {
+            reader: Reader::from_reader(b),
+            buf: Vec::new(),
+            state: None,
+            compression: false,
+            numpress: None,
+            output_buffer: Vec::with_capacity(4096),
+            binary_dtype: Dtype::F64,
+            binary_array: None,
+            spectrum: Spectrum::default(),
+        }
+    }
 
+    /// "Here be dragons" - @lazear
+    pub(crate) async fn next_spectrum(&mut self) -> Result<Option<Spectrum>, MzMLError> {
         macro_rules! extract {
             ($ev:expr, $key:expr) => {
                 $ev.try_get_attribute($key)?
@@ -109,37 +244,69 @@ impl MzMLReader {
         }
 
         loop {
-            match reader.read_event_into_async(&mut buf).await {
+            match self.reader.read_event_into_async(&mut self.buf).await {
                 Ok(Event::Start(ref ev)) => {
                     // State transition into child tag
-                    state = match (ev.name().into_inner(), state) {
+                    self.state = match (ev.name().into_inner(), self.state) {
                         (b"spectrum", _) => Some(State::Spectrum),
                         (b"scan", Some(State::Spectrum)) => Some(State::Scan),
                         (b"binaryDataArray", Some(State::Spectrum)) => Some(State::BinaryDataArray),
                         (b"binary", Some(State::BinaryDataArray)) => Some(State::Binary),
                         (b"precursor", Some(State::Spectrum)) => Some(State::Precursor),
-                        _ => state,
+                        (b"selectedIon", Some(State::Precursor)) => {
+                            self.spectrum.precursors.push(Precursor::default());
+                            Some(State::SelectedIon)
+                        }
+                        _ => self.state,
                     };
-                    if let b"spectrum" = ev.name().into_inner() {
-                        let id = extract!(ev, b"id");
-                        let id = std::str::from_utf8(&id)?;
-                        spectrum.id = id.to_string();
+                    match ev.name().into_inner() {
+                        b"spectrum" => {
+                            let id = extract!(ev, b"id");
+                            let id = std::str::from_utf8(&id)?;
+                            self.spectrum.id = id.to_string();
+                        }
+                        b"binaryDataArray" => {
+                            self.compression = false;
+                            self.numpress = None;
+                        }
+                        b"scanWindow" if self.state == Some(State::Scan) => {
+                            // Unreported bounds are unlimited:
+                            self.spectrum
+                                .scan_windows
+                                .push((f64::NEG_INFINITY, f64::INFINITY));
+                        }
+                        _ => {}
                     }
                 }
-                Ok(Event::Empty(ref ev)) => match (state, ev.name().into_inner()) {
+                Ok(Event::Empty(ref ev)) => match (self.state, ev.name().into_inner()) {
                     (Some(State::BinaryDataArray), b"cvParam") => {
                         let accession = extract!(ev, b"accession");
                         let accession = std::str::from_utf8(&accession)?;
                         match accession {
-                            ZLIB_COMPRESSION => compression = true,
-                            NO_COMPRESSION => compression = false,
-                            FLOAT_64 => binary_dtype = Dtype::F64,
-                            FLOAT_32 => binary_dtype = Dtype::F32,
-                            INTENSITY_ARRAY => binary_array = Some(BinaryKind::Intensity),
-                            MZ_ARRAY => binary_array = Some(BinaryKind::Mz),
+                            ZLIB_COMPRESSION => self.compression = true,
+                            NO_COMPRESSION => self.compression = false,
+                            NUMPRESS_LINEAR => self.numpress = Some(Numpress::Linear),
+                            NUMPRESS_PIC => self.numpress = Some(Numpress::Pic),
+                            NUMPRESS_SLOF => self.numpress = Some(Numpress::Slof),
+                            NUMPRESS_LINEAR_ZLIB => {
+                                self.compression = true;
+                                self.numpress = Some(Numpress::Linear);
+                            }
+                            NUMPRESS_PIC_ZLIB => {
+                                self.compression = true;
+                                self.numpress = Some(Numpress::Pic);
+                            }
+                            NUMPRESS_SLOF_ZLIB => {
+                                self.compression = true;
+                                self.numpress = Some(Numpress::Slof);
+                            }
+                            FLOAT_64 => self.binary_dtype = Dtype::F64,
+                            FLOAT_32 => self.binary_dtype = Dtype::F32,
+                            INTENSITY_ARRAY => self.binary_array = Some(BinaryKind::Intensity),
+                            MZ_ARRAY => self.binary_array = Some(BinaryKind::Mz),
                             _ => {
                                 // Unknown CV - perhaps noise
-                                binary_array = None;
+                                self.binary_array = None;
                             }
                         }
                     }
@@ -150,24 +317,26 @@ impl MzMLReader {
                             MS_LEVEL => {
                                 let level = extract!(ev, b"value");
                                 let level = std::str::from_utf8(&level)?.parse::<u8>()?;
-                                if level != 1 {
-                                    spectrum = Spectrum::default();
-                                    state = None;
+                                if level > 2 {
+                                    self.spectrum = Spectrum::default();
+                                    self.state = None;
                                 } else {
-                                    spectrum.ms_level = level;
+                                    self.spectrum.ms_level = level;
                                 }
                             }
-                            PROFILE => spectrum.representation = Representation::Profile,
-                            CENTROID => spectrum.representation = Representation::Centroid,
+                            PROFILE => self.spectrum.representation = Representation::Profile,
+                            CENTROID => self.spectrum.representation = Representation::Centroid,
+                            POSITIVE_SCAN => self.spectrum.polarity = Polarity::Positive,
+                            NEGATIVE_SCAN => self.spectrum.polarity = Polarity::Negative,
                             TOTAL_ION_CURRENT => {
                                 let value = extract!(ev, b"value");
                                 let value = std::str::from_utf8(&value)?.parse::<f64>()?;
-                                if value == 0.0 {
+                                if value == 0.0 && self.spectrum.ms_level < 2 {
                                     // No ion current, break out of current state
-                                    spectrum = Spectrum::default();
-                                    state = None;
+                                    self.spectrum = Spectrum::default();
+                                    self.state = None;
                                 } else {
-                                    spectrum.total_ion_current = value;
+                                    self.spectrum.total_ion_current = value;
                                 }
                             }
                             _ => {}
@@ -180,48 +349,64 @@ impl MzMLReader {
                         let value = std::str::from_utf8(&value)?;
                         match accession {
                             SCAN_START_TIME => {
-                                spectrum.scan_start_time = value.parse()?;
+                                self.spectrum.scan_start_time = value.parse()?;
                             }
                             SCAN_WINDOW_LOWER => {
-                                let mz = value.parse()?;
-                                if mz < scan_range.0 {
-                                    scan_range.0 = mz;
+                                if let Some(window) = self.spectrum.scan_windows.last_mut() {
+                                    window.0 = value.parse()?;
                                 }
                             }
                             SCAN_WINDOW_UPPER => {
-                                let mz = value.parse()?;
-                                if mz > scan_range.1 {
-                                    scan_range.1 = mz;
+                                if let Some(window) = self.spectrum.scan_windows.last_mut() {
+                                    window.1 = value.parse()?;
                                 }
                             }
                             _ => {}
                         }
                     }
+                    (Some(State::SelectedIon), b"cvParam") => {
+                        let accession = extract!(ev, b"accession");
+                        let accession = std::str::from_utf8(&accession)?;
+                        let value = extract!(ev, b"value");
+                        let value = std::str::from_utf8(&value)?;
+                        let precursor = self
+                            .spectrum
+                            .precursors
+                            .last_mut()
+                            .ok_or(MzMLError::Malformed)?;
+                        match accession {
+                            SELECTED_ION_MZ => precursor.mz = value.parse()?,
+                            CHARGE_STATE => precursor.charge = Some(value.parse()?),
+                            PEAK_INTENSITY => precursor.intensity = Some(value.parse()?),
+                            _ => {}
+                        }
+                    }
 
                     _ => {}
                 },
                 Ok(Event::Text(text)) => {
-                    if let Some(State::Binary) = state {
-                        if spectrum.ms_level != 1 {
+                    if let Some(State::Binary) = self.state {
+                        if self.spectrum.ms_level != 1 {
                             continue;
                         }
                         let raw = text.unescape()?;
                         // There are occasionally empty binary data arrays, or unknown CVs
-                        if raw.is_empty() || binary_array.is_none() {
+                        if raw.is_empty() || self.binary_array.is_none() {
                             continue;
                         }
                         let decoded = base64::decode(raw.as_bytes())?;
-                        let bytes = match compression {
+                        let bytes = match self.compression {
                             false => &decoded,
                             true => {
                                 let mut r = ZlibDecoder::new(decoded.as_slice());
-                                let n = r.read_to_end(&mut output_buffer).await?;
-                                &output_buffer[..n]
+                                let n = r.read_to_end(&mut self.output_buffer).await?;
+                                &self.output_buffer[..n]
                             }
                         };
 
-                        let array = match binary_dtype {
-                            Dtype::F32 => {
+                        let array = match (self.numpress, self.binary_dtype) {
+                            (Some(numpress), _) => numpress.decode(bytes)?,
+                            (None, Dtype::F32) => {
                                 let mut buf: [u8; 4] = [0; 4];
                                 bytes
                                     .chunks(4)
@@ -232,7 +417,7 @@ impl MzMLReader {
                                     })
                                     .collect::<Vec<f64>>()
                             }
-                            Dtype::F64 => {
+                            (None, Dtype::F64) => {
                                 let mut buf: [u8; 8] = [0; 8];
                                 bytes
                                     .chunks(8)
@@ -243,34 +428,37 @@ impl MzMLReader {
                                     .collect::<Vec<f64>>()
                             }
                         };
-                        output_buffer.clear();
+                        self.output_buffer.clear();
 
-                        match binary_array {
+                        match self.binary_array {
                             Some(BinaryKind::Intensity) => {
-                                spectrum.intensity = array;
+                                self.spectrum.intensity = array;
                             }
                             Some(BinaryKind::Mz) => {
-                                spectrum.mz = array;
+                                self.spectrum.mz = array;
                             }
                             None => {}
                         }
 
-                        binary_array = None;
+                        self.binary_array = None;
                     }
                 }
                 Ok(Event::End(ev)) => {
-                    state = match (state, ev.name().into_inner()) {
+                    self.state = match (self.state, ev.name().into_inner()) {
                         (Some(State::Binary), b"binary") => Some(State::BinaryDataArray),
                         (Some(State::BinaryDataArray), b"binaryDataArray") => Some(State::Spectrum),
                         (Some(State::Scan), b"scan") => Some(State::Spectrum),
+                        (Some(State::SelectedIon), b"selectedIon") => Some(State::Precursor),
+                        (Some(State::Precursor), b"precursor") => Some(State::Spectrum),
                         (_, b"spectrum") => {
-                            if spectrum.ms_level == 1 {
-                                spectra.push(spectrum);
+                            let spectrum = std::mem::take(&mut self.spectrum);
+                            self.buf.clear();
+                            if spectrum.ms_level == 1 || spectrum.ms_level == 2 {
+                                return Ok(Some(spectrum));
                             }
-                            spectrum = Spectrum::default();
                             None
                         }
-                        _ => state,
+                        _ => self.state,
                     };
                 }
                 Ok(Event::Eof) => break,
@@ -279,14 +467,10 @@ impl MzMLReader {
                     log::error!("unhandled XML error while parsing mzML: {}", err)
                 }
             }
-            buf.clear();
+            self.buf.clear();
         }
 
-        let out = MS1Spectra {
-            spectra,
-            scan_range,
-        };
-        Ok(out)
+        Ok(None)
     }
 }
 
@@ -347,6 +531,12 @@ impl From<std::num::ParseIntError> for MzMLError {
     }
 }
 
+impl From<NumpressError> for MzMLError {
+    fn from(_: NumpressError) -> Self {
+        Self::Malformed
+    }
+}
+
 impl From<base64::DecodeError> for MzMLError {
     fn from(_: base64::DecodeError) -> Self {
         Self::Malformed
@@ -354,9 +544,12 @@ impl From<base64::DecodeError> for MzMLError {
 }
 
 #[cfg(test)]
-mod tests {
-    use super::MzMLReader;
+pub(crate) mod tests {
+    use super::{MzMLReader, Polarity, Precursor};
+    use async_compression::tokio::bufread::ZlibEncoder;
+    use futures::StreamExt;
     use tokio::fs::File;
+    use tokio::io::AsyncReadExt;
     use tokio::io::BufReader;
     const TEST_FILE: &str = "data/MSV000081544.20170728_MS1_17k_plasmaspikedPEG_3.mzML";
 
@@ -366,6 +559,200 @@ mod tests {
         let mzml_file = BufReader::new(mzml_file);
         let res = MzMLReader::new().parse(mzml_file).await.unwrap();
         assert_eq!(res.spectra[0].mz.len(), 435);
-        assert_eq!(res.scan_range, (0.0, 1500.0));
+        assert_eq!(res.scan_range, (350.0, 1500.0));
+    }
+
+    /// Create a binaryDataArray from raw bytes and its cvParams.
+    fn binary_array(accessions: &[&str], bytes: &[u8]) -> String {
+        let params: String = accessions
+            .iter()
+            .map(|x| format!("<cvParam cvRef=\"MS\" accession=\"{}\" name=\"\"/>", x))
+            .collect();
+        format!(
+            "<binaryDataArray encodedLength=\"0\">{}<binary>{}</binary></binaryDataArray>",
+            params,
+            base64::encode(bytes),
+        )
+    }
+
+    /// Encode an array as an uncompressed, 64-bit binaryDataArray.
+    fn f64_array(accession: &str, values: &[f64]) -> String {
+        let bytes: Vec<u8> = values.iter().flat_map(|x| x.to_le_bytes()).collect();
+        binary_array(&["MS:1000523", "MS:1000576", accession], &bytes)
+    }
+
+    /// Create a minimal mzML spectrum element.
+    pub(crate) fn spectrum_xml(idx: usize, ms_level: u8, mz: &[f64], intensity: &[f64]) -> String {
+        let arrays = f64_array("MS:1000514", mz) + &f64_array("MS:1000515", intensity);
+        spectrum_with_arrays(idx, ms_level, intensity.iter().sum(), &arrays)
+    }
+
+    fn spectrum_with_arrays(idx: usize, ms_level: u8, tic: f64, arrays: &str) -> String {
+        format!(
+            "<spectrum index=\"{idx}\" id=\"scan={scan}\">\
+             <cvParam cvRef=\"MS\" accession=\"MS:1000511\" name=\"ms level\" value=\"{ms_level}\"/>\
+             <cvParam cvRef=\"MS\" accession=\"MS:1000285\" name=\"total ion current\" value=\"{tic}\"/>\
+             <scanList count=\"1\"><scan>\
+             <cvParam cvRef=\"MS\" accession=\"MS:1000016\" name=\"scan start time\" value=\"{rt}\"/>\
+             </scan></scanList>\
+             <binaryDataArrayList count=\"2\">{arrays}</binaryDataArrayList>\
+             </spectrum>",
+            scan = idx + 1,
+            rt = idx as f64,
+        )
+    }
+
+    pub(crate) fn mzml(spectra: &[String]) -> String {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+             <mzML><run><spectrumList count=\"{}\">{}</spectrumList></run></mzML>",
+            spectra.len(),
+            spectra.join(""),
+        )
+    }
+
+    #[tokio::test]
+    async fn stream() {
+        let xml = mzml(&[
+            spectrum_xml(0, 1, &[100., 200.], &[10., 20.]),
+            spectrum_xml(1, 2, &[150.], &[5.]),
+            spectrum_xml(2, 1, &[300., 400., 500.], &[1., 2., 3.]),
+        ]);
+        let spectra = MzMLReader::new().stream(xml.as_bytes());
+        let spectra: Vec<_> = spectra.collect().await;
+        assert_eq!(spectra.len(), 3);
+
+        // The peaks of MS2 spectra are not decoded:
+        let ms2 = spectra[1].as_ref().unwrap();
+        assert_eq!(ms2.ms_level, 2);
+        assert!(ms2.mz.is_empty());
+
+        let last = spectra[2].as_ref().unwrap();
+        assert_eq!(last.id, "scan=3");
+        assert_eq!(last.mz, vec![300., 400., 500.]);
+        assert_eq!(last.intensity, vec![1., 2., 3.]);
+        assert_eq!(last.total_ion_current, 6.);
+    }
+
+    #[tokio::test]
+    async fn numpress() {
+        // Linear encoded m/z: 100, 101, 102
+        let mut mz = 100f64.to_be_bytes().to_vec();
+        mz.extend(10_000u32.to_le_bytes());
+        mz.extend(10_100u32.to_le_bytes());
+        mz.push(0x80);
+
+        // Pic encoded intensities: 1, 2, 3, zlib compressed
+        let mut intensity = Vec::new();
+        ZlibEncoder::new(&[0x71u8, 0x72, 0x73][..])
+            .read_to_end(&mut intensity)
+            .await
+            .unwrap();
+
+        let arrays = binary_array(&["MS:1000523", "MS:1002312", "MS:1000514"], &mz)
+            + &binary_array(&["MS:1000523", "MS:1002747", "MS:1000515"], &intensity);
+        let xml = spectrum_with_arrays(0, 1, 6., &arrays);
+        let res = MzMLReader::new()
+            .parse(mzml(&[xml]).as_bytes())
+            .await
+            .unwrap();
+        assert_eq!(res.spectra[0].mz, vec![100., 101., 102.]);
+        assert_eq!(res.spectra[0].intensity, vec![1., 2., 3.]);
+    }
+
+    #[tokio::test]
+    async fn polarity() {
+        let positive = spectrum_xml(0, 1, &[100.], &[1.]);
+        let negative = spectrum_xml(1, 1, &[100.], &[1.]).replace(
+            "<scanList",
+            "<cvParam cvRef=\"MS\" accession=\"MS:1000129\" name=\"negative scan\"/><scanList",
+        );
+        let res = MzMLReader::new()
+            .parse(mzml(&[positive, negative]).as_bytes())
+            .await
+            .unwrap();
+        assert_eq!(res.spectra[0].polarity, Polarity::Unknown);
+        assert_eq!(res.spectra[1].polarity, Polarity::Negative);
+        assert!(res.spectra[0].polarity.allows(1));
+        assert!(!res.spectra[1].polarity.allows(1));
+    }
+
+    /// Add scan windows to the scan of a spectrum.
+    fn with_windows(spectrum: String, windows: &[(f64, f64)]) -> String {
+        let windows: String = windows
+            .iter()
+            .map(|(lower, upper)| {
+                format!(
+                    "<scanWindow>\
+                     <cvParam cvRef=\"MS\" accession=\"MS:1000501\" name=\"scan window lower limit\" value=\"{lower}\"/>\
+                     <cvParam cvRef=\"MS\" accession=\"MS:1000500\" name=\"scan window upper limit\" value=\"{upper}\"/>\
+                     </scanWindow>"
+                )
+            })
+            .collect();
+        spectrum.replace(
+            "</scan>",
+            &format!("<scanWindowList count=\"1\">{windows}</scanWindowList></scan>"),
+        )
+    }
+
+    #[tokio::test]
+    async fn scan_windows() {
+        let boxcar = with_windows(
+            spectrum_xml(0, 1, &[450.], &[1.]),
+            &[(400., 500.), (600., 700.)],
+        );
+        let full = with_windows(spectrum_xml(1, 1, &[450.], &[1.]), &[(350., 1500.)]);
+        let unknown = spectrum_xml(2, 1, &[450.], &[1.]);
+        let res = MzMLReader::new()
+            .parse(mzml(&[boxcar, full, unknown]).as_bytes())
+            .await
+            .unwrap();
+
+        assert_eq!(res.scan_range, (350., 1500.));
+        assert_eq!(
+            res.spectra[0].scan_windows,
+            vec![(400., 500.), (600., 700.)]
+        );
+        assert!(res.spectra[0].in_scan_window(650.));
+        assert!(!res.spectra[0].in_scan_window(550.));
+        assert!(res.spectra[2].scan_windows.is_empty());
+        assert!(res.spectra[2].in_scan_window(550.));
+    }
+
+    #[tokio::test]
+    async fn precursors() {
+        let precursor = "<precursorList count=\"1\"><precursor>\
+             <isolationWindow>\
+             <cvParam cvRef=\"MS\" accession=\"MS:1000827\" name=\"isolation window target m/z\" value=\"195.12\"/>\
+             </isolationWindow>\
+             <selectedIonList count=\"1\"><selectedIon>\
+             <cvParam cvRef=\"MS\" accession=\"MS:1000744\" name=\"selected ion m/z\" value=\"195.1227\"/>\
+             <cvParam cvRef=\"MS\" accession=\"MS:1000041\" name=\"charge state\" value=\"1\"/>\
+             <cvParam cvRef=\"MS\" accession=\"MS:1000042\" name=\"peak intensity\" value=\"1000\"/>\
+             </selectedIon></selectedIonList>\
+             <activation>\
+             <cvParam cvRef=\"MS\" accession=\"MS:1000422\" name=\"beam-type collision-induced dissociation\"/>\
+             </activation>\
+             </precursor></precursorList><binaryDataArrayList";
+        let ms2 = spectrum_xml(1, 2, &[150.], &[5.]).replace("<binaryDataArrayList", precursor);
+        let xml = mzml(&[spectrum_xml(0, 1, &[100.], &[1.]), ms2]);
+        let spectra: Vec<_> = MzMLReader::new().stream(xml.as_bytes()).collect().await;
+
+        let ms2 = spectra[1].as_ref().unwrap();
+        assert_eq!(ms2.ms_level, 2);
+        assert_eq!(
+            ms2.precursors,
+            vec![Precursor {
+                mz: 195.1227,
+                charge: Some(1),
+                intensity: Some(1000.),
+            }]
+        );
+        assert!(spectra[0].as_ref().unwrap().precursors.is_empty());
+
+        // MS2 spectra are not kept in memory:
+        let res = MzMLReader::new().parse(xml.as_bytes()).await.unwrap();
+        assert_eq!(res.spectra.len(), 1);
     }
 }
@@ -0,0 +1,323 @@
+//! Write search results as HUPO-PSI mzQC documents.
+//!
+//! See https://github.com/HUPO-PSI/mzQC for the specification. There are
+//! no PSI-MS terms for polymer contamination, so these metrics are defined
+//! in a small mzsniffer vocabulary that is listed in each document.
+use std::io::{Read, Write};
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+
+use crate::input::Format;
+use crate::search::PolymerResults;
+
+const MZQC_VERSION: &str = "1.0.0";
+
+const PSI_MS_NAME: &str = "Proteomics Standards Initiative Mass Spectrometry Ontology";
+const PSI_MS_URI: &str =
+    "https://github.com/HUPO-PSI/psi-ms-CV/releases/download/v4.1.130/psi-ms.obo";
+const PSI_MS_VERSION: &str = "4.1.130";
+
+const MZSNIFFER_NAME: &str = "mzsniffer polymer contamination metrics";
+const MZSNIFFER_URI: &str = "https://github.com/wfondrie/mzsniffer";
+
+/// A term from a controlled vocabulary.
+#[derive(Serialize, Debug, Clone)]
+pub struct CvParam {
+    pub accession: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<Value>,
+}
+
+impl CvParam {
+    fn new(accession: &str, name: &str, value: Option<Value>) -> Self {
+        Self {
+            accession: accession.to_string(),
+            name: name.to_string(),
+            value,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InputFile {
+    pub location: String,
+    pub name: String,
+    pub file_format: CvParam,
+    pub file_properties: Vec<CvParam>,
+}
+
+impl InputFile {
+    /// Describe an input file, including its SHA-1 checksum.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let path = path.as_ref().canonicalize()?;
+        let checksum = sha1_checksum(&path)?;
+        let name = path
+            .file_name()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let file_format = match Format::from_name(&name) {
+            Format::MzML => CvParam::new("MS:1000584", "mzML format", None),
+            Format::MzXML => CvParam::new("MS:1000566", "ISB mzXML format", None),
+            Format::Tdf => CvParam::new("MS:1002817", "Bruker TDF format", None),
+        };
+
+        Ok(Self {
+            location: format!("file://{}", path.display()),
+            name,
+            file_format,
+            file_properties: vec![CvParam::new("MS:1000569", "SHA-1", Some(json!(checksum)))],
+        })
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AnalysisSoftware {
+    pub accession: String,
+    pub name: String,
+    pub version: String,
+    pub uri: String,
+}
+
+impl Default for AnalysisSoftware {
+    fn default() -> Self {
+        Self {
+            accession: "MS:1000799".to_string(),
+            name: "mzsniffer".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            uri: MZSNIFFER_URI.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Metadata {
+    pub label: String,
+    pub input_files: Vec<InputFile>,
+    pub analysis_software: Vec<AnalysisSoftware>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RunQuality {
+    pub metadata: Metadata,
+    pub quality_metrics: Vec<CvParam>,
+}
+
+impl RunQuality {
+    /// Describe the quality of a run from its search results.
+    pub fn new(results: &PolymerResults, input_file: InputFile) -> Self {
+        let metadata = Metadata {
+            label: results.filename.clone(),
+            input_files: vec![input_file],
+            analysis_software: vec![AnalysisSoftware::default()],
+        };
+
+        let pct_tic = json!({
+            "polymer": results.polymers.iter().map(|x| &x.name).collect::<Vec<_>>(),
+            "percent of total ion current": results
+                .polymers
+                .iter()
+                .map(|x| results.percent_tic(x))
+                .collect::<Vec<_>>(),
+        });
+
+        let ms2 = json!({
+            "polymer": results.polymers.iter().map(|x| &x.name).collect::<Vec<_>>(),
+            "number of MS2 spectra": results
+                .polymers
+                .iter()
+                .map(|x| x.ms2_scans)
+                .collect::<Vec<_>>(),
+        });
+
+        // Polymers are listed rather than used as keys, so their names
+        // can't collide with each other or with the other columns:
+        let xic = json!({
+            "scan start time": results.ret_times,
+            "total ion current": results.tic,
+            "polymer": results.polymers.iter().map(|x| &x.name).collect::<Vec<_>>(),
+            "extracted ion chromatogram": results
+                .polymers
+                .iter()
+                .map(|x| &x.xic)
+                .collect::<Vec<_>>(),
+        });
+
+        let quality_metrics = vec![
+            CvParam::new(
+                "MS:4000059",
+                "number of MS1 spectra",
+                Some(json!(results.ret_times.len())),
+            ),
+            CvParam::new(
+                "MZSNIFFER:0000001",
+                "polymer percent of total ion current",
+                Some(pct_tic),
+            ),
+            CvParam::new(
+                "MZSNIFFER:0000002",
+                "polymer extracted ion chromatograms",
+                Some(xic),
+            ),
+            CvParam::new(
+                "MS:4000060",
+                "number of MS2 spectra",
+                Some(json!(results.ms2_scans)),
+            ),
+            CvParam::new(
+                "MZSNIFFER:0000003",
+                "polymer precursor MS2 spectra",
+                Some(ms2),
+            ),
+        ];
+
+        Self {
+            metadata,
+            quality_metrics,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ControlledVocabulary {
+    pub name: String,
+    pub uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MzQC {
+    pub version: String,
+    pub creation_date: String,
+    pub description: String,
+    pub run_qualities: Vec<RunQuality>,
+    pub controlled_vocabularies: Vec<ControlledVocabulary>,
+}
+
+impl MzQC {
+    /// Create an mzQC document without any runs.
+    pub fn new() -> Self {
+        Self {
+            version: MZQC_VERSION.to_string(),
+            creation_date: chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            description: "Polymer contaminants detected by mzsniffer".to_string(),
+            run_qualities: Vec::new(),
+            controlled_vocabularies: vec![
+                ControlledVocabulary {
+                    name: PSI_MS_NAME.to_string(),
+                    uri: PSI_MS_URI.to_string(),
+                    version: Some(PSI_MS_VERSION.to_string()),
+                },
+                ControlledVocabulary {
+                    name: MZSNIFFER_NAME.to_string(),
+                    uri: MZSNIFFER_URI.to_string(),
+                    version: Some(env!("CARGO_PKG_VERSION").to_string()),
+                },
+            ],
+        }
+    }
+
+    /// Add a run from its search results and the path to its input file.
+    pub fn add_run<P: AsRef<Path>>(
+        &mut self,
+        results: &PolymerResults,
+        path: P,
+    ) -> std::io::Result<()> {
+        let input_file = InputFile::from_path(path)?;
+        self.run_qualities
+            .push(RunQuality::new(results, input_file));
+        Ok(())
+    }
+
+    /// Write the document as JSON.
+    pub fn to_writer<W: Write>(&self, writer: W) -> serde_json::Result<()> {
+        serde_json::to_writer_pretty(writer, &Document { mzqc: self })
+    }
+}
+
+/// The top-level mzQC object.
+#[derive(Serialize)]
+struct Document<'a> {
+    #[serde(rename = "mzQC")]
+    mzqc: &'a MzQC,
+}
+
+impl Default for MzQC {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Calculate the SHA-1 checksum of a file.
+///
+/// The checksum of a Bruker `.d` directory is that of its `analysis.tdf`.
+fn sha1_checksum(path: &Path) -> std::io::Result<String> {
+    let mut file = match path.is_dir() {
+        true => std::fs::File::open(path.join("analysis.tdf"))?,
+        false => std::fs::File::open(path)?,
+    };
+    let mut hasher = Sha1::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InputFile, MzQC};
+    use crate::search::PolymerResults;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_mzqc() {
+        let dir = TempDir::new("mzqc");
+        let path = dir.join("run.mzML");
+        std::fs::write(&path, b"abc").unwrap();
+
+        let input = InputFile::from_path(&path).unwrap();
+        let checksum = input.file_properties[0].value.as_ref().unwrap();
+        assert_eq!(checksum, "a9993e364706816aba3e25717850c26c9cd0d89d");
+
+        // A polymer named like another column must not replace it:
+        let mut results = PolymerResults::from_totals(
+            "run.mzML",
+            &[("PEG+1H", 4.), ("total ion current", 3.)],
+            &[10., 30.],
+        );
+        results.polymers[0].xic = vec![1., 3.];
+        results.polymers[1].xic = vec![0., 3.];
+
+        let mut mzqc = MzQC::new();
+        mzqc.add_run(&results, &path).unwrap();
+        let mut out = Vec::new();
+        mzqc.to_writer(&mut out).unwrap();
+
+        let doc: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let run = &doc["mzQC"]["runQualities"][0];
+        assert_eq!(run["metadata"]["label"], "run.mzML");
+        assert_eq!(run["qualityMetrics"][0]["value"], 2);
+        assert_eq!(
+            run["qualityMetrics"][1]["value"]["percent of total ion current"][0],
+            10.0
+        );
+        let xic = &run["qualityMetrics"][2]["value"];
+        assert_eq!(xic["polymer"][1], "total ion current");
+        assert_eq!(xic["extracted ion chromatogram"][1][1], 3.0);
+        assert_eq!(xic["total ion current"][1], 30.0);
+    }
+}
@@ -0,0 +1,459 @@
+//! Read spectra from legacy mzXML files.
+//!
+//! Spectra are read into the same types as mzML files. Retention times are
+//! converted from the `xs:duration` of mzXML into minutes, which is what
+//! most mzML files use.
+use async_compression::tokio::bufread::ZlibDecoder;
+use futures::Stream;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use tokio::io::{AsyncBufRead, AsyncReadExt};
+
+use crate::mzml::{MS1Spectra, MzMLError, Polarity, Precursor, Representation, Spectrum};
+
+/// Which tag are we inside?
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    Scan,
+    PrecursorMz,
+    Peaks,
+}
+
+/// How the peaks of a scan are encoded.
+#[derive(Copy, Clone, Debug)]
+struct PeaksEncoding {
+    precision: usize,
+    big_endian: bool,
+    compression: bool,
+}
+
+impl Default for PeaksEncoding {
+    fn default() -> Self {
+        Self {
+            precision: 32,
+            big_endian: true,
+            compression: false,
+        }
+    }
+}
+
+pub struct MzXMLReader;
+
+impl MzXMLReader {
+    /// Create a new [`MzXMLReader`].
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Read all of the MS1 spectra into memory.
+    pub async fn parse<B: AsyncBufRead + Unpin>(&self, b: B) -> Result<MS1Spectra, MzMLError> {
+        let mut parser = ScanParser::new(b);
+        let mut spectra = Vec::new();
+        while let Some(spectrum) = parser.next_spectrum().await? {
+            if spectrum.ms_level == 1 {
+                spectra.push(spectrum);
+            }
+        }
+
+        Ok(MS1Spectra::new(spectra))
+    }
+
+    /// Lazily read the MS1 and MS2 spectra, one at a time.
+    ///
+    /// As with [`crate::mzml::MzMLReader::stream`], MS2 spectra only have
+    /// their precursors and the stream ends after the first error.
+    pub fn stream<B: AsyncBufRead + Unpin>(
+        &self,
+        b: B,
+    ) -> impl Stream<Item = Result<Spectrum, MzMLError>> {
+        futures::stream::unfold(Some(ScanParser::new(b)), |parser| async move {
+            let mut parser = parser?;
+            match parser.next_spectrum().await {
+                Ok(Some(spectrum)) => Some((Ok(spectrum), Some(parser))),
+                Ok(None) => None,
+                Err(err) => Some((Err(err), None)),
+            }
+        })
+    }
+}
+
+impl Default for MzXMLReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The state required to resume parsing where the last scan ended.
+struct ScanParser<B> {
+    reader: Reader<B>,
+    buf: Vec<u8>,
+    state: Option<State>,
+    /// The scan being read, if it is one that we keep.
+    spectrum: Option<Spectrum>,
+    encoding: PeaksEncoding,
+    output_buffer: Vec<u8>,
+}
+
+impl<B: AsyncBufRead + Unpin> ScanParser<B> {
+    fn new(b: B) -> Self {
+        Self {
+            reader: Reader::from_reader(b),
+            buf: Vec::new(),
+            state: None,
+            spectrum: None,
+            encoding: PeaksEncoding::default(),
+            output_buffer: Vec::with_capacity(4096),
+        }
+    }
+
+    /// Start a new scan from the attributes of its `<scan>` element.
+    ///
+    /// Returns `None` for the scans that are not kept: those above MS2 and
+    /// MS1 scans without any ion current.
+    fn start_scan(ev: &BytesStart) -> Result<Option<Spectrum>, MzMLError> {
+        let mut spectrum = Spectrum::default();
+        let mut tic = None;
+        let mut window = (None, None);
+        for attr in ev.attributes() {
+            let attr = attr.map_err(quick_xml::Error::from)?;
+            let value = attr.unescape_value()?;
+            match attr.key.into_inner() {
+                b"num" => spectrum.id = format!("scan={}", value),
+                b"msLevel" => spectrum.ms_level = value.parse()?,
+                b"centroided" if value == "1" || value == "true" => {
+                    spectrum.representation = Representation::Centroid
+                }
+                b"polarity" => {
+                    spectrum.polarity = match &value[..] {
+                        "+" => Polarity::Positive,
+                        "-" => Polarity::Negative,
+                        _ => Polarity::Unknown,
+                    }
+                }
+                b"retentionTime" => spectrum.scan_start_time = parse_duration(&value)?,
+                b"totIonCurrent" => tic = Some(value.parse::<f64>()?),
+                b"startMz" => window.0 = Some(value.parse()?),
+                b"endMz" => window.1 = Some(value.parse()?),
+                _ => {}
+            }
+        }
+
+        if window.0.is_some() || window.1.is_some() {
+            spectrum.scan_windows.push((
+                window.0.unwrap_or(f64::NEG_INFINITY),
+                window.1.unwrap_or(f64::INFINITY),
+            ));
+        }
+
+        // mzXML files usually report the TIC, but it is optional:
+        spectrum.total_ion_current = tic.unwrap_or(f64::NAN);
+        let keep = match spectrum.ms_level {
+            1 => spectrum.total_ion_current != 0.,
+            2 => true,
+            _ => false,
+        };
+        Ok(keep.then_some(spectrum))
+    }
+
+    /// Finish a scan, calculating its TIC from its peaks if it wasn't
+    /// reported. MS1 scans without any ion current are dropped.
+    fn finish_scan(current: &mut Option<Spectrum>) -> Option<Spectrum> {
+        let mut spectrum = current.take()?;
+        if spectrum.total_ion_current.is_nan() {
+            spectrum.total_ion_current = spectrum.intensity.iter().sum();
+        }
+        match spectrum.ms_level == 1 && spectrum.total_ion_current == 0. {
+            true => None,
+            false => Some(spectrum),
+        }
+    }
+
+    async fn next_spectrum(&mut self) -> Result<Option<Spectrum>, MzMLError> {
+        loop {
+            let event = self.reader.read_event_into_async(&mut self.buf).await;
+            let mut finished = None;
+            match event {
+                Ok(Event::Start(ref ev)) => match ev.name().into_inner() {
+                    b"scan" => {
+                        // Scans may be nested within their parent scan, whose
+                        // peaks always precede its children:
+                        finished = Self::finish_scan(&mut self.spectrum);
+                        self.spectrum = Self::start_scan(ev)?;
+                        self.state = Some(State::Scan);
+                    }
+                    b"precursorMz" if self.spectrum.is_some() => {
+                        let mut precursor = Precursor::default();
+                        for attr in ev.attributes() {
+                            let attr = attr.map_err(quick_xml::Error::from)?;
+                            let value = attr.unescape_value()?;
+                            match attr.key.into_inner() {
+                                b"precursorCharge" => precursor.charge = Some(value.parse()?),
+                                b"precursorIntensity" => precursor.intensity = Some(value.parse()?),
+                                _ => {}
+                            }
+                        }
+                        if let Some(spectrum) = self.spectrum.as_mut() {
+                            spectrum.precursors.push(precursor);
+                        }
+                        self.state = Some(State::PrecursorMz);
+                    }
+                    b"peaks" if self.spectrum.is_some() => {
+                        self.encoding = PeaksEncoding::default();
+                        for attr in ev.attributes() {
+                            let attr = attr.map_err(quick_xml::Error::from)?;
+                            let value = attr.unescape_value()?;
+                            match attr.key.into_inner() {
+                                b"precision" => self.encoding.precision = value.parse()?,
+                                b"byteOrder" => self.encoding.big_endian = value != "little",
+                                b"compressionType" => self.encoding.compression = value == "zlib",
+                                b"pairOrder" | b"contentType" if value != "m/z-int" => {
+                                    return Err(MzMLError::UnsupportedCV(value.to_string()));
+                                }
+                                _ => {}
+                            }
+                        }
+                        self.state = Some(State::Peaks);
+                    }
+                    _ => {}
+                },
+                Ok(Event::Empty(ref ev)) if ev.name().into_inner() == b"scan" => {
+                    finished = Self::finish_scan(&mut self.spectrum);
+                    self.spectrum = Self::start_scan(ev)?;
+                    if finished.is_none() {
+                        finished = Self::finish_scan(&mut self.spectrum);
+                    }
+                }
+                Ok(Event::Text(text)) => match (self.state, self.spectrum.as_mut()) {
+                    (Some(State::PrecursorMz), Some(spectrum)) => {
+                        if let Some(precursor) = spectrum.precursors.last_mut() {
+                            precursor.mz = text.unescape()?.trim().parse()?;
+                        }
+                    }
+                    (Some(State::Peaks), Some(spectrum)) if spectrum.ms_level == 1 => {
+                        let raw = text.unescape()?;
+                        let raw = raw.trim();
+                        if !raw.is_empty() {
+                            let decoded = base64::decode(raw.as_bytes())?;
+                            (spectrum.mz, spectrum.intensity) =
+                                decode_peaks(&decoded, self.encoding, &mut self.output_buffer)
+                                    .await?;
+                        }
+                    }
+                    _ => {}
+                },
+                Ok(Event::End(ev)) => match ev.name().into_inner() {
+                    b"scan" => {
+                        finished = Self::finish_scan(&mut self.spectrum);
+                        self.state = None;
+                    }
+                    b"precursorMz" | b"peaks" => self.state = Some(State::Scan),
+                    _ => {}
+                },
+                Ok(Event::Eof) => break,
+                Ok(_) => {}
+                Err(err) => {
+                    log::error!("unhandled XML error while parsing mzXML: {}", err)
+                }
+            }
+            self.buf.clear();
+            if finished.is_some() {
+                return Ok(finished);
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Decode interleaved m/z and intensity pairs.
+async fn decode_peaks(
+    decoded: &[u8],
+    encoding: PeaksEncoding,
+    output_buffer: &mut Vec<u8>,
+) -> Result<(Vec<f64>, Vec<f64>), MzMLError> {
+    let bytes = match encoding.compression {
+        false => decoded,
+        true => {
+            output_buffer.clear();
+            let mut r = ZlibDecoder::new(decoded);
+            let n = r.read_to_end(output_buffer).await?;
+            &output_buffer[..n]
+        }
+    };
+
+    let values: Vec<f64> = match (encoding.precision, encoding.big_endian) {
+        (32, big_endian) => bytes
+            .chunks_exact(4)
+            .map(|chunk| {
+                let buf = [chunk[0], chunk[1], chunk[2], chunk[3]];
+                match big_endian {
+                    true => f32::from_be_bytes(buf) as f64,
+                    false => f32::from_le_bytes(buf) as f64,
+                }
+            })
+            .collect(),
+        (64, big_endian) => bytes
+            .chunks_exact(8)
+            .map(|chunk| {
+                let mut buf: [u8; 8] = [0; 8];
+                buf.copy_from_slice(chunk);
+                match big_endian {
+                    true => f64::from_be_bytes(buf),
+                    false => f64::from_le_bytes(buf),
+                }
+            })
+            .collect(),
+        (precision, _) => return Err(MzMLError::UnsupportedCV(format!("precision {}", precision))),
+    };
+
+    Ok(values
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .unzip())
+}
+
+/// Parse an `xs:duration`, such as `PT1M30.5S`, into minutes.
+///
+/// Plain numbers are assumed to be seconds.
+fn parse_duration(value: &str) -> Result<f64, MzMLError> {
+    let value = value.trim();
+    let time = match value.strip_prefix("PT") {
+        Some(time) => time,
+        None => return Ok(value.parse::<f64>()? / 60.),
+    };
+
+    let mut seconds = 0.;
+    let mut number = String::new();
+    for c in time.chars() {
+        let scale = match c {
+            'H' => 3600.,
+            'M' => 60.,
+            'S' => 1.,
+            _ => {
+                number.push(c);
+                continue;
+            }
+        };
+        seconds += number.parse::<f64>()? * scale;
+        number.clear();
+    }
+    match number.is_empty() {
+        true => Ok(seconds / 60.),
+        false => Err(MzMLError::Malformed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_duration, MzXMLReader};
+    use crate::mzml::{Polarity, Precursor, Representation};
+    use async_compression::tokio::bufread::ZlibEncoder;
+    use futures::StreamExt;
+    use tokio::io::AsyncReadExt;
+
+    /// Encode peaks as big-endian pairs.
+    fn peaks(mz: &[f64], intensity: &[f64], precision: usize) -> Vec<u8> {
+        mz.iter()
+            .zip(intensity)
+            .flat_map(|(mz, int)| match precision {
+                32 => [(*mz as f32).to_be_bytes(), (*int as f32).to_be_bytes()].concat(),
+                _ => [mz.to_be_bytes(), int.to_be_bytes()].concat(),
+            })
+            .collect()
+    }
+
+    fn scan_xml(
+        num: usize,
+        attrs: &str,
+        peaks_attrs: &str,
+        bytes: &[u8],
+        children: &str,
+    ) -> String {
+        format!(
+            "<scan num=\"{num}\" {attrs} retentionTime=\"PT{rt}S\" startMz=\"100\" endMz=\"1500\">\
+             <peaks byteOrder=\"network\" pairOrder=\"m/z-int\" {peaks_attrs}>{peaks}</peaks>\
+             {children}</scan>",
+            rt = num * 30,
+            peaks = base64::encode(bytes),
+        )
+    }
+
+    fn mzxml(scans: &str) -> String {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?>\
+             <mzXML xmlns=\"http://sashimi.sourceforge.net/schema_revision/mzXML_3.2\">\
+             <msRun scanCount=\"3\">{}</msRun></mzXML>",
+            scans
+        )
+    }
+
+    #[tokio::test]
+    async fn test_mzxml() {
+        let mut compressed = Vec::new();
+        let raw = peaks(&[300., 400.], &[5., 6.], 64);
+        ZlibEncoder::new(&raw[..])
+            .read_to_end(&mut compressed)
+            .await
+            .unwrap();
+
+        let ms2 = "<scan num=\"2\" msLevel=\"2\" retentionTime=\"PT61S\">\
+                   <precursorMz precursorIntensity=\"1000\" precursorCharge=\"1\">195.1227</precursorMz>\
+                   <peaks precision=\"32\" byteOrder=\"network\" pairOrder=\"m/z-int\"></peaks></scan>";
+        let xml = mzxml(
+            &(scan_xml(
+                1,
+                "msLevel=\"1\" polarity=\"+\" centroided=\"1\" totIonCurrent=\"30\"",
+                "precision=\"32\"",
+                &peaks(&[100., 200.], &[10., 20.], 32),
+                ms2,
+            ) + &scan_xml(
+                3,
+                "msLevel=\"1\" polarity=\"-\"",
+                "precision=\"64\" compressionType=\"zlib\"",
+                &compressed,
+                "",
+            )),
+        );
+
+        let spectra: Vec<_> = MzXMLReader::new().stream(xml.as_bytes()).collect().await;
+        assert_eq!(spectra.len(), 3);
+
+        let first = spectra[0].as_ref().unwrap();
+        assert_eq!(first.id, "scan=1");
+        assert_eq!(first.mz, vec![100., 200.]);
+        assert_eq!(first.intensity, vec![10., 20.]);
+        assert_eq!(first.total_ion_current, 30.);
+        assert_eq!(first.scan_start_time, 0.5);
+        assert_eq!(first.polarity, Polarity::Positive);
+        assert_eq!(first.representation, Representation::Centroid);
+
+        // The nested MS2 scan follows its parent:
+        let ms2 = spectra[1].as_ref().unwrap();
+        assert_eq!(ms2.ms_level, 2);
+        assert_eq!(
+            ms2.precursors,
+            vec![Precursor {
+                mz: 195.1227,
+                charge: Some(1),
+                intensity: Some(1000.),
+            }]
+        );
+
+        let last = spectra[2].as_ref().unwrap();
+        assert_eq!(last.mz, vec![300., 400.]);
+        assert_eq!(last.total_ion_current, 11.);
+        assert_eq!(last.polarity, Polarity::Negative);
+
+        let res = MzXMLReader::new().parse(xml.as_bytes()).await.unwrap();
+        assert_eq!(res.spectra.len(), 2);
+        assert_eq!(res.scan_range.1, 1500.);
+    }
+
+    #[test]
+    fn test_duration() {
+        assert_eq!(parse_duration("PT90S").unwrap(), 1.5);
+        assert_eq!(parse_duration("PT1H1M30S").unwrap(), 61.5);
+        assert_eq!(parse_duration("30").unwrap(), 0.5);
+        assert!(parse_duration("PT1.5").is_err());
+    }
+}
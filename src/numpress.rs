@@ -0,0 +1,208 @@
+//! Decoders for MS-Numpress compressed binary data arrays.
+//!
+//! These are ports of the reference implementation by Johan Teleman:
+//! https://github.com/ms-numpress/ms-numpress
+//!
+//! All three schemes store integers as variable length sequences of
+//! half-bytes. The first half-byte of each integer is a header: values
+//! 0-8 give the number of leading zero half-bytes, while values 9-15 give
+//! 8 plus the number of leading 0xf half-bytes. The remaining half-bytes
+//! follow, least significant first.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Numpress {
+    /// MS-Numpress linear prediction compression, typically for m/z.
+    Linear,
+    /// MS-Numpress positive integer compression, typically for intensity.
+    Pic,
+    /// MS-Numpress short logged float compression, typically for intensity.
+    Slof,
+}
+
+impl Numpress {
+    /// Decode a Numpress compressed byte array.
+    pub fn decode(&self, data: &[u8]) -> Result<Vec<f64>, NumpressError> {
+        match self {
+            Numpress::Linear => decode_linear(data),
+            Numpress::Pic => decode_pic(data),
+            Numpress::Slof => decode_slof(data),
+        }
+    }
+}
+
+/// Decode a linear prediction compressed array.
+pub fn decode_linear(data: &[u8]) -> Result<Vec<f64>, NumpressError> {
+    if data.len() == 8 {
+        return Ok(Vec::new());
+    }
+
+    let fixed_point = decode_fixed_point(data)?;
+    let mut result = Vec::new();
+    if data.len() < 12 {
+        return Err(NumpressError("not enough bytes to read the first value"));
+    }
+
+    let mut ints: [i64; 3] = [0, read_u32(&data[8..12]) as i64, 0];
+    result.push(ints[1] as f64 / fixed_point);
+    if data.len() == 12 {
+        return Ok(result);
+    }
+
+    if data.len() < 16 {
+        return Err(NumpressError("not enough bytes to read the second value"));
+    }
+
+    ints[2] = read_u32(&data[12..16]) as i64;
+    result.push(ints[2] as f64 / fixed_point);
+
+    let mut reader = HalfByteReader::new(&data[16..]);
+    while !reader.is_done() {
+        ints[0] = ints[1];
+        ints[1] = ints[2];
+        let diff = reader.read_int()? as i32 as i64;
+        let extrapolated = ints[1] + (ints[1] - ints[0]);
+        ints[2] = extrapolated + diff;
+        result.push(ints[2] as f64 / fixed_point);
+    }
+
+    Ok(result)
+}
+
+/// Decode a positive integer compressed array.
+pub fn decode_pic(data: &[u8]) -> Result<Vec<f64>, NumpressError> {
+    let mut result = Vec::new();
+    let mut reader = HalfByteReader::new(data);
+    while !reader.is_done() {
+        result.push(reader.read_int()? as f64);
+    }
+
+    Ok(result)
+}
+
+/// Decode a short logged float compressed array.
+pub fn decode_slof(data: &[u8]) -> Result<Vec<f64>, NumpressError> {
+    let fixed_point = decode_fixed_point(data)?;
+    let result = data[8..]
+        .chunks_exact(2)
+        .map(|x| {
+            let value = u16::from_le_bytes([x[0], x[1]]);
+            (value as f64 / fixed_point).exp() - 1.
+        })
+        .collect();
+
+    Ok(result)
+}
+
+/// The fixed point is stored as a big-endian double in the first 8 bytes.
+fn decode_fixed_point(data: &[u8]) -> Result<f64, NumpressError> {
+    let bytes: [u8; 8] = data
+        .get(..8)
+        .ok_or(NumpressError("not enough bytes to read the fixed point"))?
+        .try_into()
+        .unwrap();
+
+    Ok(f64::from_be_bytes(bytes))
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    u32::from_le_bytes([data[0], data[1], data[2], data[3]])
+}
+
+/// Read integers that have been packed into half-bytes.
+struct HalfByteReader<'a> {
+    data: &'a [u8],
+    /// The index of the next half-byte.
+    pos: usize,
+}
+
+impl<'a> HalfByteReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Are there any integers left?
+    ///
+    /// An odd number of half-bytes is padded with a single zero half-byte.
+    fn is_done(&self) -> bool {
+        let remaining = 2 * self.data.len() - self.pos;
+        remaining == 0 || (remaining == 1 && self.data[self.pos / 2] & 0xf == 0)
+    }
+
+    fn next_half_byte(&mut self) -> Result<u32, NumpressError> {
+        let byte = self
+            .data
+            .get(self.pos / 2)
+            .ok_or(NumpressError("unexpected end of data"))?;
+        let half = match self.pos % 2 {
+            0 => byte >> 4,
+            _ => byte & 0xf,
+        };
+        self.pos += 1;
+        Ok(half as u32)
+    }
+
+    fn read_int(&mut self) -> Result<u32, NumpressError> {
+        let head = self.next_half_byte()?;
+        let mut result: u32 = 0;
+        let n_leading = match head {
+            0..=8 => head,
+            _ => {
+                let n = head - 8;
+                for i in 0..n {
+                    result |= 0xf000_0000 >> (4 * i);
+                }
+                n
+            }
+        };
+
+        for i in n_leading..8 {
+            result |= self.next_half_byte()? << ((i - n_leading) * 4);
+        }
+
+        Ok(result)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct NumpressError(&'static str);
+
+impl std::fmt::Display for NumpressError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NumpressError: corrupt input data, {}", self.0)
+    }
+}
+
+impl std::error::Error for NumpressError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_linear, decode_pic, decode_slof};
+
+    #[test]
+    fn test_linear() {
+        let mut data = 100f64.to_be_bytes().to_vec();
+        data.extend(10_000u32.to_le_bytes());
+        data.extend(10_100u32.to_le_bytes());
+        // Differences of 0, 50, -1, then 0 and a padding half-byte:
+        data.extend([0x86, 0x23, 0xff, 0x80]);
+        let decoded = decode_linear(&data).unwrap();
+        assert_eq!(decoded, vec![100., 101., 102., 103.5, 104.99, 106.48]);
+    }
+
+    #[test]
+    fn test_pic() {
+        // 1, 2, 3, then 0x1234:
+        let data = [0x71, 0x72, 0x73, 0x44, 0x32, 0x10];
+        let decoded = decode_pic(&data).unwrap();
+        assert_eq!(decoded, vec![1., 2., 3., 4660.]);
+        assert!(decode_pic(&[0x04, 0x32]).is_err());
+    }
+
+    #[test]
+    fn test_slof() {
+        let mut data = 100f64.to_be_bytes().to_vec();
+        data.extend([0x00, 0x00, 0x64, 0x00]);
+        let decoded = decode_slof(&data).unwrap();
+        assert_eq!(decoded, vec![0., 1f64.exp() - 1.]);
+    }
+}
@@ -0,0 +1,350 @@
+use std::io::Write;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::discovery::DiscoveryResults;
+use crate::search::PolymerResults;
+
+/// The supported formats for detailed results.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Json,
+    Pickle,
+    Tsv,
+    Csv,
+}
+
+impl OutputFormat {
+    pub const NAMES: [&'static str; 4] = ["json", "pickle", "tsv", "csv"];
+
+    /// Write the results in this format.
+    pub fn write<W: Write>(&self, mut writer: W, results: &[PolymerResults]) -> anyhow::Result<()> {
+        match self {
+            OutputFormat::Json => serde_json::to_writer_pretty(writer, results)?,
+            OutputFormat::Pickle => serde_pickle::to_writer(
+                &mut writer,
+                &results,
+                serde_pickle::ser::SerOptions::new(),
+            )?,
+            OutputFormat::Tsv => write_xic_table(writer, results, b'\t')?,
+            OutputFormat::Csv => write_xic_table(writer, results, b',')?,
+        };
+        Ok(())
+    }
+
+    /// Write the series found by `--discover` in this format.
+    pub fn write_discovered<W: Write>(
+        &self,
+        mut writer: W,
+        results: &[DiscoveryResults],
+    ) -> anyhow::Result<()> {
+        match self {
+            OutputFormat::Json => serde_json::to_writer_pretty(writer, results)?,
+            OutputFormat::Pickle => serde_pickle::to_writer(
+                &mut writer,
+                &results,
+                serde_pickle::ser::SerOptions::new(),
+            )?,
+            OutputFormat::Tsv => write_candidate_table(writer, results, b'\t')?,
+            OutputFormat::Csv => write_candidate_table(writer, results, b',')?,
+        };
+        Ok(())
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "pickle" => Ok(Self::Pickle),
+            "tsv" => Ok(Self::Tsv),
+            "csv" => Ok(Self::Csv),
+            _ => Err(format!("Unrecognized output format '{}'", s)),
+        }
+    }
+}
+
+/// A row of the long-form XIC table.
+#[derive(Serialize)]
+struct XicRow<'a> {
+    file: &'a str,
+    polymer: &'a str,
+    scan_index: usize,
+    ret_time: f64,
+    intensity: f64,
+    pct_tic: f64,
+}
+
+/// A row of the per-polymer summary table.
+#[derive(Serialize)]
+struct SummaryRow<'a> {
+    file: &'a str,
+    polymer: &'a str,
+    intensity: f64,
+    pct_tic: f64,
+}
+
+/// Write the intensity of every polymer in every scan as a long-form table.
+///
+/// The `pct_tic` column is the percentage of the scan's total ion current.
+pub fn write_xic_table<W: Write>(
+    writer: W,
+    results: &[PolymerResults],
+    delimiter: u8,
+) -> Result<(), csv::Error> {
+    let mut wtr = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(writer);
+
+    for run in results.iter() {
+        for poly in run.polymers.iter() {
+            let scans = run
+                .ret_times
+                .iter()
+                .zip(run.tic.iter())
+                .zip(poly.xic.iter());
+            for (scan_index, ((ret_time, tic), intensity)) in scans.enumerate() {
+                wtr.serialize(XicRow {
+                    file: &run.filename,
+                    polymer: &poly.name,
+                    scan_index,
+                    ret_time: *ret_time,
+                    intensity: *intensity,
+                    pct_tic: percent(*intensity, *tic),
+                })?;
+            }
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Write the total intensity of every polymer as a table.
+///
+/// The `pct_tic` column is the percentage of the total ion current of the
+/// spectra that could observe the polymer.
+pub fn write_summary_table<W: Write>(
+    writer: W,
+    results: &[PolymerResults],
+    delimiter: u8,
+) -> Result<(), csv::Error> {
+    let mut wtr = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(writer);
+
+    for run in results.iter() {
+        for poly in run.polymers.iter() {
+            wtr.serialize(SummaryRow {
+                file: &run.filename,
+                polymer: &poly.name,
+                intensity: poly.total,
+                pct_tic: run.percent_tic(poly),
+            })?;
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// A row of the copolymer composition table.
+#[derive(Serialize)]
+struct CompositionRow<'a> {
+    file: &'a str,
+    polymer: &'a str,
+    composition: String,
+    repeat_units: usize,
+    intensity: f64,
+    pct_tic: f64,
+}
+
+/// Write the intensity of every composition of every copolymer as a table.
+///
+/// Compositions are written like `(C2H4O)12(C3H6O)3` and only those that
+/// were observed are included. The `pct_tic` column is the percentage of
+/// the total ion current of the spectra that could observe the polymer.
+pub fn write_composition_table<W: Write>(
+    writer: W,
+    results: &[PolymerResults],
+    delimiter: u8,
+) -> Result<(), csv::Error> {
+    let mut wtr = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(writer);
+
+    for run in results.iter() {
+        for poly in run.polymers.iter() {
+            for comp in poly.compositions.iter() {
+                let composition: String = poly
+                    .repeat_units
+                    .iter()
+                    .zip(comp.counts.iter())
+                    .map(|(unit, count)| format!("({}){}", unit, count))
+                    .collect();
+                wtr.serialize(CompositionRow {
+                    file: &run.filename,
+                    polymer: &poly.name,
+                    composition,
+                    repeat_units: comp.counts.iter().sum(),
+                    intensity: comp.intensity,
+                    pct_tic: percent(comp.intensity, run.observable_tic(poly)),
+                })?;
+            }
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// A row of the discovered series table.
+#[derive(Serialize)]
+struct CandidateRow<'a> {
+    file: &'a str,
+    name: String,
+    repeat_mass: f64,
+    charge: i32,
+    kendrick_mass_defect: f64,
+    n_members: usize,
+    members: String,
+    intensity: f64,
+    pct_tic: f64,
+}
+
+/// Write every series found by `--discover` as a table.
+///
+/// The `members` column lists the m/z of each member, separated by
+/// semicolons.
+pub fn write_candidate_table<W: Write>(
+    writer: W,
+    results: &[DiscoveryResults],
+    delimiter: u8,
+) -> Result<(), csv::Error> {
+    let mut wtr = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(writer);
+
+    for run in results.iter() {
+        for candidate in run.candidates.iter() {
+            let members: Vec<String> = candidate
+                .members
+                .iter()
+                .map(|x| format!("{:.4}", x))
+                .collect();
+            wtr.serialize(CandidateRow {
+                file: &run.filename,
+                name: candidate.name(),
+                repeat_mass: candidate.repeat_mass,
+                charge: candidate.charge,
+                kendrick_mass_defect: candidate.kendrick_mass_defect,
+                n_members: candidate.members.len(),
+                members: members.join(";"),
+                intensity: candidate.intensity,
+                pct_tic: candidate.percent_tic,
+            })?;
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+fn percent(intensity: f64, total: f64) -> f64 {
+    if total > 0. {
+        100. * intensity / total
+    } else {
+        0.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        write_candidate_table, write_composition_table, write_summary_table, write_xic_table,
+        OutputFormat,
+    };
+    use crate::discovery::{CandidateSeries, DiscoveryResults};
+    use crate::mzml::Polarity;
+    use crate::search::{Composition, PolymerResults};
+
+    fn results() -> Vec<PolymerResults> {
+        let mut results = PolymerResults::from_totals(
+            "run.mzML",
+            &[("Triton X-100 (Reduced, Na)", 4.)],
+            &[10., 30.],
+        );
+        results.polymers[0].xic = vec![1., 3.];
+        vec![results]
+    }
+
+    #[test]
+    fn test_xic_table() {
+        let mut out = Vec::new();
+        write_xic_table(&mut out, &results(), b',').unwrap();
+        let expected = "file,polymer,scan_index,ret_time,intensity,pct_tic\n\
+                        run.mzML,\"Triton X-100 (Reduced, Na)\",0,0.5,1.0,10.0\n\
+                        run.mzML,\"Triton X-100 (Reduced, Na)\",1,1.0,3.0,10.0\n";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_summary_table() {
+        let mut out = Vec::new();
+        write_summary_table(&mut out, &results(), b'\t').unwrap();
+        let expected = "file\tpolymer\tintensity\tpct_tic\n\
+                        run.mzML\tTriton X-100 (Reduced, Na)\t4.0\t10.0\n";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_composition_table() {
+        let mut results = results();
+        let poly = &mut results[0].polymers[0];
+        poly.repeat_units = vec!["C2H4O".to_string(), "C3H6O".to_string()];
+        poly.compositions = vec![Composition {
+            counts: vec![12, 3],
+            intensity: 2.,
+        }];
+
+        let mut out = Vec::new();
+        write_composition_table(&mut out, &results, b'\t').unwrap();
+        let expected = "file\tpolymer\tcomposition\trepeat_units\tintensity\tpct_tic\n\
+                        run.mzML\tTriton X-100 (Reduced, Na)\t(C2H4O)12(C3H6O)3\t15\t2.0\t5.0\n";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_candidate_table() {
+        let results = vec![DiscoveryResults {
+            filename: "run.mzML".to_string(),
+            candidates: vec![CandidateSeries {
+                repeat_mass: 44.0262,
+                charge: 1,
+                polarity: Polarity::Positive,
+                kendrick_mass_defect: 0.01,
+                members: vec![195.12309, 239.14939],
+                intensity: 10.,
+                percent_tic: 1.,
+            }],
+        }];
+
+        let mut out = Vec::new();
+        write_candidate_table(&mut out, &results, b'\t').unwrap();
+        let expected = "file\tname\trepeat_mass\tcharge\tkendrick_mass_defect\tn_members\tmembers\tintensity\tpct_tic\n\
+                        run.mzML\tUnknown 44.0262 (1+)\t44.0262\t1\t0.01\t2\t195.1231;239.1494\t10.0\t1.0\n";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+        let mut out = Vec::new();
+        OutputFormat::Json
+            .write_discovered(&mut out, &results)
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json[0]["candidates"][0]["charge"], 1);
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!("TSV".parse::<OutputFormat>(), Ok(OutputFormat::Tsv));
+        assert!("xml".parse::<OutputFormat>().is_err());
+    }
+}
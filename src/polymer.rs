@@ -1,23 +1,159 @@
-use crate::mass::{formula_mass, mass_to_mz};
+use std::collections::HashMap;
 
-#[derive(Debug)]
+use serde::{Deserialize, Serialize};
+
+use crate::mass::{isotope_distribution, Adduct, Formula, FormulaError};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Polymer {
     /// The name of the polymer.
     pub name: String,
     /// The empirical formula for the non-repeating parts of the
     /// molecule.
-    core_formula: String,
+    #[serde(default)]
+    core_formula: Formula,
     /// The empirical formula for the repeating part of the molecule.
-    rep_formula: String,
-    /// The charge of the molecule.
+    #[serde(default)]
+    rep_formula: Formula,
+    /// The mass of the non-repeating parts of the molecule, for polymers
+    /// of unknown composition. This overrides the 'core_formula'.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    core_mass: Option<f64>,
+    /// The mass of the repeating part of the molecule, for polymers of
+    /// unknown composition. This overrides the 'rep_formula'.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rep_mass: Option<f64>,
+    /// Additional repeating units of a copolymer, each with its own range
+    /// of counts. The 'rep_formula', if any, is the first repeating unit.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    repeat_units: Vec<RepeatUnit>,
+    /// The charge of the molecule. Ignored if adducts are provided.
+    #[serde(default = "default_charge")]
     charge: i32,
     /// Add protons to account for the charge? If false, you should
-    /// add the charged-atoms to your 'core_formula'.
+    /// add the charged-atoms to your 'core_formula'. Ignored if adducts
+    /// are provided.
+    #[serde(default = "default_protonate")]
     protonate: bool,
-    /// The precursor m/z values.
-    pub precursors: Option<Vec<f64>>,
+    /// The adducts to search for, such as `[M+Na]+` or `[M-H]-`. Each
+    /// adduct is a separate ion series.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    adducts: Vec<Adduct>,
+    /// A free-text description of the polymer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Where the polymer definition came from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    /// The maximum allowed percentage of the total ion current.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tic: Option<f64>,
+    /// A shift applied to the m/z of every ion. This is used to create
+    /// decoys.
+    #[serde(skip)]
+    mz_shift: f64,
+    /// The ion series for each adduct.
+    #[serde(skip)]
+    pub series: Option<Vec<IonSeries>>,
+}
+
+/// A repeating unit of a copolymer.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RepeatUnit {
+    /// The empirical formula of the repeating unit.
+    #[serde(default)]
+    pub formula: Formula,
+    /// The mass of the repeating unit, for units of unknown composition.
+    /// This overrides the 'formula'.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mass: Option<f64>,
+    /// The smallest number of this unit in the molecule.
+    #[serde(default)]
+    pub min: usize,
+    /// The largest number of this unit in the molecule. Otherwise, the
+    /// count is only limited by the largest m/z in the spectra.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<usize>,
+}
+
+impl RepeatUnit {
+    pub fn new(formula: &str) -> Result<Self, FormulaError> {
+        Ok(Self {
+            formula: formula.parse()?,
+            mass: None,
+            min: 0,
+            max: None,
+        })
+    }
+
+    /// A repeating unit of unknown composition.
+    pub fn from_mass(mass: f64) -> Self {
+        Self {
+            formula: Formula::new(),
+            mass: Some(mass),
+            min: 0,
+            max: None,
+        }
+    }
+
+    /// Only consider molecules with `min` to `max` of this unit.
+    pub fn with_range(mut self, min: usize, max: Option<usize>) -> Self {
+        self.min = min;
+        self.max = max;
+        self
+    }
+
+    /// The mass of the repeating unit.
+    pub fn mass(&self) -> f64 {
+        self.mass
+            .unwrap_or_else(|| self.formula.monoisotopic_mass())
+    }
+
+    /// A short name for the unit: its formula or, for units of unknown
+    /// composition, its mass.
+    pub fn label(&self) -> String {
+        match self.mass {
+            Some(mass) => format!("{:.4}", mass),
+            None => self.formula.to_string(),
+        }
+    }
+}
+
+/// The precursors of a polymer with a single adduct.
+#[derive(Debug, Clone)]
+pub struct IonSeries {
+    /// The adduct.
+    pub adduct: Adduct,
+    /// The precursor m/z values, in ascending order.
+    pub precursors: Vec<f64>,
+    /// The number of each repeating unit of each precursor.
+    pub compositions: Vec<Vec<usize>>,
+    /// The precursors whose composition differs from each precursor by a
+    /// single repeating unit.
+    pub neighbors: Vec<Vec<usize>>,
     /// The tolerance around each m/z value.
-    pub tols: Option<Vec<f64>>,
+    pub tols: Vec<f64>,
+    /// The isotope envelope of each precursor.
+    pub envelopes: Option<Vec<Envelope>>,
+}
+
+/// The significant isotope peaks of a single precursor.
+#[derive(Debug, Clone, Default)]
+pub struct Envelope {
+    /// The m/z of each isotope peak.
+    pub mz: Vec<f64>,
+    /// The abundance of each isotope peak, relative to the most abundant.
+    pub abundance: Vec<f64>,
+    /// The tolerance around each m/z value.
+    pub tols: Vec<f64>,
+}
+
+fn default_charge() -> i32 {
+    1
+}
+
+fn default_protonate() -> bool {
+    true
 }
 
 impl Polymer {
@@ -27,68 +163,466 @@ impl Polymer {
         rep_formula: &str,
         charge: i32,
         protonate: bool,
+    ) -> Result<Self, FormulaError> {
+        Ok(Self::from_formulas(
+            name,
+            core_formula.parse()?,
+            rep_formula.parse()?,
+            charge,
+            protonate,
+        ))
+    }
+
+    /// Create a polymer from a formula with a repeating group, such as
+    /// `H(C2H4O)nOH`.
+    pub fn from_formula(
+        name: &str,
+        formula: &str,
+        charge: i32,
+        protonate: bool,
+    ) -> Result<Self, FormulaError> {
+        let (core_formula, rep_formula) = Formula::parse_repeat(formula)?;
+        Ok(Self::from_formulas(
+            name,
+            core_formula,
+            rep_formula,
+            charge,
+            protonate,
+        ))
+    }
+
+    /// Create a polymer of unknown composition from its masses. Isotope
+    /// envelopes cannot be calculated for these.
+    pub fn from_masses(
+        name: &str,
+        core_mass: f64,
+        rep_mass: f64,
+        charge: i32,
+        protonate: bool,
+    ) -> Self {
+        Self::from_formulas(name, Formula::new(), Formula::new(), charge, protonate)
+            .with_masses(core_mass, rep_mass)
+    }
+
+    fn from_formulas(
+        name: &str,
+        core_formula: Formula,
+        rep_formula: Formula,
+        charge: i32,
+        protonate: bool,
     ) -> Self {
         Self {
             name: name.to_string(),
-            core_formula: core_formula.to_string(),
-            rep_formula: rep_formula.to_string(),
+            core_formula,
+            rep_formula,
+            core_mass: None,
+            rep_mass: None,
+            repeat_units: Vec::new(),
             charge,
-            precursors: None,
-            tols: None,
             protonate,
+            adducts: Vec::new(),
+            description: None,
+            reference: None,
+            max_tic: None,
+            mz_shift: 0.,
+            series: None,
         }
     }
 
-    fn mz_array(&self, max_mz: &f64) -> Vec<f64> {
-        let core_mass = formula_mass(&self.core_formula);
-        let rep_mass = formula_mass(&self.rep_formula);
-        let mut mz_vec: Vec<f64> = Vec::new();
-        for rep in 0.. {
-            let poly_mass = mass_to_mz(
-                core_mass + rep as f64 * rep_mass,
-                self.charge,
-                self.protonate,
-            );
+    /// Search for these adducts instead of using the charge.
+    pub fn with_adducts(mut self, adducts: Vec<Adduct>) -> Self {
+        self.adducts = adducts;
+        self
+    }
 
-            if poly_mass > *max_mz {
-                break;
-            }
+    /// Use these masses instead of the formulas, for polymers of unknown
+    /// composition. Isotope envelopes cannot be calculated for these.
+    pub fn with_masses(mut self, core_mass: f64, rep_mass: f64) -> Self {
+        self.core_mass = Some(core_mass);
+        self.rep_mass = Some(rep_mass);
+        self
+    }
 
-            mz_vec.push(poly_mass);
+    /// Add a repeating unit, making this a copolymer.
+    pub fn with_repeat_unit(mut self, unit: RepeatUnit) -> Self {
+        self.repeat_units.push(unit);
+        self
+    }
 
-            // In the case that it isn't actually a polymer:
-            if rep_mass == 0. {
-                break;
-            }
+    /// The repeating units of the molecule.
+    ///
+    /// The 'rep_formula' or 'rep_mass' is the first unit, with any count,
+    /// unless it is empty and other units are provided.
+    pub fn units(&self) -> Vec<RepeatUnit> {
+        let mut units = Vec::with_capacity(self.repeat_units.len() + 1);
+        if self.repeat_units.is_empty() || self.rep_mass.is_some() || !self.rep_formula.is_empty() {
+            units.push(RepeatUnit {
+                formula: self.rep_formula.clone(),
+                mass: self.rep_mass,
+                min: 0,
+                max: None,
+            });
         }
-        mz_vec
+        units.extend(self.repeat_units.iter().cloned());
+        units
+    }
+
+    /// Does the molecule have more than one repeating unit?
+    pub fn is_copolymer(&self) -> bool {
+        self.units().len() > 1
+    }
+
+    /// The mass of the non-repeating parts of the molecule.
+    pub fn core_mass(&self) -> f64 {
+        self.core_mass
+            .unwrap_or_else(|| self.core_formula.monoisotopic_mass())
+    }
+
+    /// The mass of the repeating part of the molecule.
+    pub fn rep_mass(&self) -> f64 {
+        self.rep_mass
+            .unwrap_or_else(|| self.rep_formula.monoisotopic_mass())
+    }
+
+    /// The charge of the molecule, when no adducts are provided.
+    pub fn charge(&self) -> i32 {
+        self.charge
+    }
+
+    /// Were the adducts provided, rather than derived from the charge?
+    pub fn has_adducts(&self) -> bool {
+        !self.adducts.is_empty()
+    }
+
+    /// A decoy of this polymer, with the m/z of every ion shifted by
+    /// `shift`, whatever its charge.
+    pub fn decoy(&self, shift: f64) -> Self {
+        let mut decoy = self.clone();
+        decoy.name = format!("DECOY_{}_{:+.3}", self.name, shift);
+        decoy.mz_shift += shift;
+        decoy.series = None;
+        decoy
+    }
+
+    /// The adducts that form the ion series of this polymer.
+    ///
+    /// Without explicit adducts, protonated polymers are also searched for
+    /// as deprotonated anions and, when singly charged, as chloride and
+    /// formate adducts.
+    pub fn adducts(&self) -> Vec<Adduct> {
+        if !self.adducts.is_empty() {
+            return self.adducts.clone();
+        }
+
+        if !self.protonate {
+            return vec![Adduct::intrinsic(self.charge)];
+        }
+
+        let mut adducts = vec![
+            Adduct::protonated(self.charge),
+            Adduct::deprotonated(self.charge),
+        ];
+        if self.charge == 1 {
+            adducts.push("[M+Cl]-".parse().unwrap());
+            adducts.push("[M+HCOO]-".parse().unwrap());
+        }
+        adducts
+    }
+
+    /// The m/z and composition of every precursor up to `max_mz`, in
+    /// order of m/z.
+    ///
+    /// Each repeating unit contributes from its minimum to its maximum
+    /// count, forming a grid of compositions for copolymers. Units without
+    /// a positive mass only contribute their minimum count.
+    fn precursors(&self, adduct: &Adduct, max_mz: &f64) -> Vec<(f64, Vec<usize>)> {
+        let units = self.units();
+        let min_mass: f64 = units.iter().map(|x| x.min as f64 * x.mass()).sum();
+        let mut counts: Vec<usize> = units.iter().map(|x| x.min).collect();
+        let mut precursors = Vec::new();
+        add_compositions(
+            &units,
+            0,
+            self.core_mass() + self.mz_shift * adduct.charge.abs() as f64 + min_mass,
+            &mut counts,
+            adduct,
+            *max_mz,
+            &mut precursors,
+        );
+
+        // Sorting by m/z means that precursors are only appended when the
+        // bounds are extended to a larger m/z.
+        precursors.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+        precursors
     }
 
     pub fn calculate_bounds(&mut self, max_mz: &f64, tol: &f64, unit: &str) {
-        let mz_array = self.mz_array(max_mz);
-        let n_vals = mz_array.len();
-        let tol_vals: Vec<f64> = match &unit.to_lowercase()[..] {
-            "da" => vec![*tol; n_vals],
-            "ppm" => mz_array
-                .clone()
-                .into_iter()
-                .map(|x| *tol * x / 1_000_000.0)
-                .collect(),
-            _ => unreachable!("Invalid unit {}", unit),
-        };
-
-        self.precursors = Some(mz_array);
-        self.tols = Some(tol_vals);
+        let series = self
+            .adducts()
+            .into_iter()
+            .map(|adduct| {
+                let (mz_array, compositions): (Vec<f64>, Vec<Vec<usize>>) =
+                    self.precursors(&adduct, max_mz).into_iter().unzip();
+                let tol_vals = tolerances(&mz_array, tol, unit);
+                IonSeries {
+                    adduct,
+                    neighbors: neighbors(&compositions),
+                    precursors: mz_array,
+                    compositions,
+                    tols: tol_vals,
+                    envelopes: None,
+                }
+            })
+            .collect();
+
+        self.series = Some(series);
+    }
+
+    /// Calculate the isotope envelope of each precursor.
+    ///
+    /// Only isotope peaks with at least `min_abundance` relative to the
+    /// most abundant peak are kept. This must be called after
+    /// [`Polymer::calculate_bounds`]. Polymers defined by their masses
+    /// rather than formulas have no envelopes.
+    pub fn calculate_envelopes(&mut self, min_abundance: f64, tol: &f64, unit: &str) {
+        let units = self.units();
+        if self.core_mass.is_some() || units.iter().any(|x| x.mass.is_some()) {
+            return;
+        }
+        let mut series = self
+            .series
+            .take()
+            .expect("Bounds must be calculated first.");
+        for ions in series.iter_mut() {
+            let envelopes = (0..ions.precursors.len())
+                .map(|idx| {
+                    let formula = units
+                        .iter()
+                        .zip(ions.compositions[idx].iter())
+                        .fold(self.core_formula.clone(), |formula, (unit, count)| {
+                            formula + unit.formula.clone() * *count as i32
+                        })
+                        + ions.adduct.formula.clone();
+                    let mono_mass = formula.monoisotopic_mass();
+
+                    // Ions that lose atoms the molecule does not have, such
+                    // as a deprotonated oligomer without hydrogens, only
+                    // have their monoisotopic peak.
+                    let dist = isotope_distribution(&formula, MAX_ISOTOPES)
+                        .unwrap_or_else(|_| vec![(mono_mass, 1.)]);
+                    let max_abundance = dist.iter().map(|x| x.1).fold(0., f64::max);
+                    let (mz, abundance): (Vec<f64>, Vec<f64>) = dist
+                        .into_iter()
+                        .filter(|(_, abundance)| *abundance >= min_abundance * max_abundance)
+                        .map(|(mass, abundance)| {
+                            let offset = (mass - mono_mass) / ions.adduct.charge.abs() as f64;
+                            (ions.precursors[idx] + offset, abundance / max_abundance)
+                        })
+                        .unzip();
+
+                    Envelope {
+                        tols: tolerances(&mz, tol, unit),
+                        mz,
+                        abundance,
+                    }
+                })
+                .collect();
+            ions.envelopes = Some(envelopes);
+        }
+        self.series = Some(series);
+    }
+}
+
+/// Add every composition up to `max_mz` that varies the units from `idx`
+/// onward, which are at their minimum counts in `mass`.
+fn add_compositions(
+    units: &[RepeatUnit],
+    idx: usize,
+    mass: f64,
+    counts: &mut Vec<usize>,
+    adduct: &Adduct,
+    max_mz: f64,
+    precursors: &mut Vec<(f64, Vec<usize>)>,
+) {
+    let unit = match units.get(idx) {
+        Some(unit) => unit,
+        None => {
+            if adduct.mz(mass) <= max_mz {
+                precursors.push((adduct.mz(mass), counts.clone()));
+            }
+            return;
+        }
+    };
+
+    let unit_mass = unit.mass();
+    let max = match unit_mass > 0. {
+        true => unit.max.unwrap_or(usize::MAX),
+        false => unit.min,
+    };
+    for count in unit.min..=max {
+        let total = mass + (count - unit.min) as f64 * unit_mass;
+        if adduct.mz(total) > max_mz {
+            break;
+        }
+        counts[idx] = count;
+        add_compositions(units, idx + 1, total, counts, adduct, max_mz, precursors);
+    }
+    counts[idx] = unit.min;
+}
+
+/// The indices of the compositions that differ from each composition by
+/// a single repeating unit.
+fn neighbors(compositions: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let index: HashMap<&[usize], usize> = compositions
+        .iter()
+        .enumerate()
+        .map(|(idx, counts)| (counts.as_slice(), idx))
+        .collect();
+
+    compositions
+        .iter()
+        .map(|counts| {
+            let mut adjacent = Vec::new();
+            let mut other = counts.clone();
+            for unit in 0..counts.len() {
+                let steps = [counts[unit].checked_sub(1), Some(counts[unit] + 1)];
+                for count in steps.into_iter().flatten() {
+                    other[unit] = count;
+                    if let Some(idx) = index.get(other.as_slice()) {
+                        adjacent.push(*idx);
+                    }
+                }
+                other[unit] = counts[unit];
+            }
+            adjacent
+        })
+        .collect()
+}
+
+/// The maximum number of isotope peaks to consider.
+const MAX_ISOTOPES: usize = 10;
+
+/// The tolerance around each m/z value.
+fn tolerances(mz_array: &[f64], tol: &f64, unit: &str) -> Vec<f64> {
+    match &unit.to_lowercase()[..] {
+        "da" => vec![*tol; mz_array.len()],
+        "ppm" => mz_array.iter().map(|x| *tol * x / 1_000_000.0).collect(),
+        _ => unreachable!("Invalid unit {}", unit),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Polymer;
+    use super::{Polymer, RepeatUnit};
+    use crate::mass::Adduct;
 
     #[test]
     fn smoke() {
-        let mut poly = Polymer::new("test", "CH3", "OH", 3, true);
+        let mut poly = Polymer::new("test", "CH3", "OH", 3, true).unwrap();
         poly.calculate_bounds(&100., &10., "ppm");
     }
+
+    #[test]
+    fn test_adducts() {
+        let adducts = vec!["[M+H]+", "[M+Na]+", "[M+H+Na]2+"]
+            .into_iter()
+            .map(|x| x.parse::<Adduct>().unwrap())
+            .collect();
+        let mut poly = Polymer::new("PEG", "H2O", "C2H4O", 1, true)
+            .unwrap()
+            .with_adducts(adducts);
+        poly.calculate_bounds(&300., &10., "ppm");
+
+        let series = poly.series.unwrap();
+        assert_eq!(series.len(), 3);
+        assert_eq!(series[2].adduct.charge, 2);
+        assert!((series[0].precursors[4] - 195.1227).abs() < 1e-4);
+        assert!((series[1].precursors[4] - 217.1046).abs() < 1e-4);
+        assert!((series[2].precursors[4] - 109.0560).abs() < 1e-4);
+        assert!(series[2].precursors.len() > series[0].precursors.len());
+    }
+
+    #[test]
+    fn test_default_adducts() {
+        let names =
+            |poly: Polymer| -> Vec<String> { poly.adducts().into_iter().map(|x| x.name).collect() };
+
+        let peg = Polymer::new("PEG", "H2O", "C2H4O", 1, true).unwrap();
+        assert_eq!(names(peg), vec!["[M+H]+", "[M-H]-", "[M+Cl]-", "[M+HCOO]-"]);
+
+        let peg = Polymer::new("PEG", "H2O", "C2H4O", 2, true).unwrap();
+        assert_eq!(names(peg), vec!["[M+2H]2+", "[M-2H]2-"]);
+
+        let tween = Polymer::new("Tween", "C18H34O6Na", "C2H4O", 1, false).unwrap();
+        assert_eq!(names(tween), vec!["[M]+"]);
+    }
+
+    #[test]
+    fn test_from_formula() {
+        let peg = Polymer::from_formula("PEG", "H(C2H4O)nOH", 1, true).unwrap();
+        assert!((peg.core_mass() - 18.010565).abs() < 1e-6);
+        assert!((peg.rep_mass() - 44.026215).abs() < 1e-6);
+
+        // Perfluorinated and sulfonated polymers are supported:
+        let pfpe = Polymer::from_formula("PFPE", "CF3O(C3F6O)nCF2CF3", 1, false).unwrap();
+        assert!((pfpe.rep_mass() - 165.985334).abs() < 1e-6);
+        assert!(Polymer::new("PSS", "H2", "C8H7SO3Na", 1, true).is_ok());
+
+        assert!(Polymer::new("Bad", "H2O", "C2Xx4O", 1, true).is_err());
+        assert!(Polymer::from_formula("Bad", "C2H4O", 1, true).is_err());
+    }
+
+    #[test]
+    fn test_copolymer() {
+        let ppg = RepeatUnit::new("C3H6O").unwrap().with_range(1, Some(3));
+        let mut poly = Polymer::new("PEG-PPG", "H2O", "C2H4O", 1, true)
+            .unwrap()
+            .with_repeat_unit(ppg)
+            .with_adducts(vec![Adduct::protonated(1)]);
+        assert!(poly.is_copolymer());
+        poly.calculate_bounds(&400., &10., "ppm");
+        poly.calculate_envelopes(0.05, &10., "ppm");
+
+        let series = &poly.series.as_ref().unwrap()[0];
+        assert!(series.precursors.windows(2).all(|x| x[0] <= x[1]));
+        assert!(series.compositions.iter().all(|x| (1..=3).contains(&x[1])));
+        assert!(series.compositions.contains(&vec![0, 3]));
+        assert!(series.compositions.contains(&vec![7, 1]));
+        assert!(!series.compositions.contains(&vec![8, 1]));
+
+        // Extending the bounds only adds larger precursors:
+        let before = series.compositions.clone();
+        poly.calculate_bounds(&800., &10., "ppm");
+        let series = &poly.series.as_ref().unwrap()[0];
+        assert_eq!(series.compositions[..before.len()], before);
+
+        let idx = series.compositions.iter().position(|x| x == &vec![2, 2]);
+        let neighbors: Vec<&Vec<usize>> = series.neighbors[idx.unwrap()]
+            .iter()
+            .map(|x| &series.compositions[*x])
+            .collect();
+        assert_eq!(neighbors.len(), 4);
+        assert!(neighbors.contains(&&vec![2, 1]) && neighbors.contains(&&vec![3, 2]));
+    }
+
+    #[test]
+    fn test_envelopes() {
+        let mut poly = Polymer::new("PEG", "H2O", "C2H4O", 2, true).unwrap();
+        poly.calculate_bounds(&1500., &10., "ppm");
+        poly.calculate_envelopes(0.05, &10., "ppm");
+
+        let series = &poly.series.unwrap()[0];
+        let envelopes = series.envelopes.as_ref().unwrap();
+        assert_eq!(envelopes.len(), series.precursors.len());
+
+        // The smallest oligomer is basically just the monoisotopic peak:
+        assert_eq!(envelopes[0].mz, vec![series.precursors[0]]);
+
+        // Isotope peaks are spaced by ~1/z and the M+1 peak eventually
+        // becomes the most abundant.
+        let last = envelopes.last().unwrap();
+        assert!((last.mz[1] - last.mz[0] - 0.5017).abs() < 1e-3);
+        assert_eq!(last.abundance[1], 1.);
+        assert!(last.abundance[0] < 1.);
+    }
 }
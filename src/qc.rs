@@ -0,0 +1,169 @@
+//! Pass/fail quality control using %TIC thresholds.
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::library::PolymerLibrary;
+use crate::search::PolymerResults;
+
+/// The exit code when no thresholds are exceeded.
+pub const EXIT_CLEAN: u8 = 0;
+/// The exit code when something went wrong.
+pub const EXIT_ERROR: u8 = 1;
+/// The exit code when at least one threshold is exceeded.
+pub const EXIT_CONTAMINATED: u8 = 2;
+
+/// The maximum allowed %TIC for polymers.
+#[derive(Debug, Clone, Default)]
+pub struct Thresholds {
+    /// The maximum %TIC of individual polymers, by name.
+    pub polymers: BTreeMap<String, f64>,
+    /// The maximum %TIC of all polymers combined.
+    pub total: Option<f64>,
+}
+
+impl Thresholds {
+    /// Are there any thresholds to check?
+    pub fn is_empty(&self) -> bool {
+        self.polymers.is_empty() && self.total.is_none()
+    }
+
+    /// Parse a polymer threshold of the form 'NAME=PCT'.
+    pub fn add_from_str(&mut self, threshold: &str) -> Result<(), String> {
+        let (name, pct) = threshold
+            .rsplit_once('=')
+            .ok_or_else(|| format!("Expected NAME=PCT, but got '{}'", threshold))?;
+        let pct = pct
+            .trim()
+            .parse::<f64>()
+            .map_err(|_| format!("Invalid %TIC threshold '{}'", pct))?;
+        self.polymers.insert(name.trim().to_string(), pct);
+        Ok(())
+    }
+
+    /// Check a run against the thresholds.
+    pub fn evaluate(&self, results: &PolymerResults) -> Verdict {
+        let mut checks: Vec<Check> = results
+            .polymers
+            .iter()
+            .filter_map(|poly| {
+                let max = self.polymers.get(&poly.name)?;
+                Some(Check::new(&poly.name, results.percent_tic(poly), *max))
+            })
+            .collect();
+
+        if let Some(max) = self.total {
+            let total = results
+                .polymers
+                .iter()
+                .map(|x| results.percent_tic(x))
+                .sum();
+            checks.push(Check::new("Total", total, max));
+        }
+
+        Verdict {
+            filename: results.filename.clone(),
+            contaminated: checks.iter().any(|x| !x.passed),
+            checks,
+        }
+    }
+}
+
+impl From<&PolymerLibrary> for Thresholds {
+    fn from(library: &PolymerLibrary) -> Self {
+        Self {
+            polymers: library
+                .polymers
+                .iter()
+                .filter_map(|x| Some((x.name.clone(), x.max_tic?)))
+                .collect(),
+            total: library.max_total_tic,
+        }
+    }
+}
+
+/// The comparison of one polymer, or the total, to its threshold.
+#[derive(Serialize, Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    pub percent_tic: f64,
+    pub max_percent_tic: f64,
+    pub passed: bool,
+}
+
+impl Check {
+    fn new(name: &str, percent_tic: f64, max_percent_tic: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            percent_tic,
+            max_percent_tic,
+            passed: percent_tic <= max_percent_tic,
+        }
+    }
+}
+
+/// The outcome of all checks for a run.
+#[derive(Serialize, Debug, Clone)]
+pub struct Verdict {
+    pub filename: String,
+    pub contaminated: bool,
+    pub checks: Vec<Check>,
+}
+
+impl Verdict {
+    /// Log a table of the checks to stderr.
+    pub fn log(&self) {
+        let status = match self.contaminated {
+            true => "CONTAMINATED",
+            false => "CLEAN",
+        };
+        log::info!("QC verdict for {}: {}", self.filename, status);
+        log::info!("{}", "+".repeat(52));
+        log::info!("Polymer                         %TIC      Max  Result");
+        log::info!("{}", "+".repeat(52));
+        for check in self.checks.iter() {
+            let result = match check.passed {
+                true => "PASS",
+                false => "FAIL",
+            };
+            log::info!(
+                "{:26}  {:>8.4} {:>8.4}  {}",
+                check.name,
+                check.percent_tic,
+                check.max_percent_tic,
+                result
+            );
+        }
+        log::info!("{}", "+".repeat(52));
+        log::info!("");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Thresholds;
+    use crate::search::PolymerResults;
+
+    fn results() -> PolymerResults {
+        PolymerResults::from_totals("run.mzML", &[("PEG+1H", 6.), ("PPG", 1.)], &[100.])
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let mut thresholds = Thresholds::default();
+        assert!(thresholds.is_empty());
+        assert!(thresholds.add_from_str("PEG+1H").is_err());
+
+        thresholds.add_from_str("PPG=2").unwrap();
+        thresholds.total = Some(10.);
+        let verdict = thresholds.evaluate(&results());
+        assert!(!verdict.contaminated);
+        assert_eq!(verdict.checks.len(), 2);
+        assert_eq!(verdict.checks[1].percent_tic, 7.);
+
+        thresholds.add_from_str("PEG+1H=5").unwrap();
+        let verdict = thresholds.evaluate(&results());
+        assert!(verdict.contaminated);
+        assert!(!verdict.checks[0].passed);
+    }
+}
@@ -1,26 +1,221 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 
+use futures::{Stream, StreamExt};
 use rayon::prelude::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use crate::defaults::DefaultPolymers;
-use crate::mzml::MS1Spectra;
-use crate::polymer::Polymer;
+use crate::calibration::{Calibration, ErrorSummary, MassError, MassErrors};
+use crate::chromatogram::{pick_peaks, ChromPeak, PeakSettings};
+use crate::mass::ISOTOPE_SPACING;
+use crate::mzml::{MS1Spectra, MzMLError, Spectrum};
+use crate::polymer::{IonSeries, Polymer};
 
-#[derive(Serialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct PolymerResults {
     pub filename: String,
     pub polymers: Vec<PolymerResult>,
     pub ret_times: Vec<f64>,
     pub tic: Vec<f64>,
     pub total: f64,
+    /// The number of MS2 spectra.
+    #[serde(default)]
+    pub ms2_scans: usize,
 }
 
-#[derive(Serialize, Clone, Debug)]
+#[cfg(test)]
+impl PolymerResults {
+    /// Results with the total intensity of each polymer, for tests.
+    ///
+    /// Each spectrum has the given TIC, and they are half a minute apart.
+    pub(crate) fn from_totals(filename: &str, polymers: &[(&str, f64)], tic: &[f64]) -> Self {
+        Self {
+            filename: filename.to_string(),
+            polymers: polymers
+                .iter()
+                .map(|(name, total)| PolymerResult {
+                    total: *total,
+                    ..PolymerResult::new(name)
+                })
+                .collect(),
+            ret_times: (1..=tic.len()).map(|x| x as f64 / 2.).collect(),
+            tic: tic.to_vec(),
+            total: tic.iter().sum(),
+            ms2_scans: 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct PolymerResult {
     pub name: String,
     pub total: f64,
     pub xic: Vec<f64>,
+    /// The total intensity of each oligomer, indexed by the number of
+    /// repeat units and summed over every adduct.
+    #[serde(default)]
+    pub oligomers: Vec<f64>,
+    /// The number of MS2 spectra whose precursor matched the polymer.
+    #[serde(default)]
+    pub ms2_scans: usize,
+    /// The total ion current of the spectra whose scan windows exclude
+    /// every precursor of the polymer. These spectra are not included in
+    /// its %TIC.
+    #[serde(default)]
+    pub excluded_tic: f64,
+    /// The chromatographic peaks of the XIC, most intense first.
+    #[serde(default)]
+    pub peaks: Vec<ChromPeak>,
+    /// The background intensity estimated from decoy series.
+    pub decoys: Option<DecoyStats>,
+    /// The distribution of the mass errors of the matched peaks, in ppm.
+    pub mass_error_ppm: Option<ErrorSummary>,
+    /// The distribution of the mass errors of the matched peaks, in Da.
+    pub mass_error_da: Option<ErrorSummary>,
+    /// The errors of the matched peaks. Only the most intense peak of
+    /// each oligomer is included.
+    #[serde(skip)]
+    pub mass_errors: MassErrors,
+    /// The results for each adduct of the polymer.
+    #[serde(default)]
+    pub series: Vec<SeriesResult>,
+    /// The repeating units of a copolymer.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub repeat_units: Vec<String>,
+    /// The total intensity of each composition of a copolymer, summed
+    /// over every adduct.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compositions: Vec<Composition>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SeriesResult {
+    /// The adduct of this ion series.
+    pub name: String,
+    /// The charge of the adduct.
+    pub charge: i32,
+    pub total: f64,
+    pub xic: Vec<f64>,
+    /// The total intensity of each oligomer, indexed by the number of
+    /// repeat units.
+    pub oligomers: Vec<f64>,
+    /// The total intensity of each precursor, in order of m/z.
+    #[serde(skip)]
+    pub precursors: Vec<f64>,
+}
+
+/// The intensity of a single composition of a copolymer.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Composition {
+    /// The number of each repeating unit.
+    pub counts: Vec<usize>,
+    /// The total intensity.
+    pub intensity: f64,
+}
+
+impl PolymerResults {
+    /// Create empty results for the provided polymers.
+    pub fn new(filename: String, polymers: &[Polymer]) -> Self {
+        Self {
+            filename,
+            polymers: polymers.iter().map(PolymerResult::from).collect(),
+            ret_times: Vec::new(),
+            tic: Vec::new(),
+            total: 0.,
+            ms2_scans: 0,
+        }
+    }
+
+    /// The total ion current of the spectra that could observe a polymer.
+    pub fn observable_tic(&self, poly: &PolymerResult) -> f64 {
+        self.total - poly.excluded_tic
+    }
+
+    /// The percentage of the total ion current explained by a polymer, in
+    /// the spectra that could observe it.
+    pub fn percent_tic(&self, poly: &PolymerResult) -> f64 {
+        let total = self.observable_tic(poly);
+        if total > 0. {
+            100. * poly.total / total
+        } else {
+            0.
+        }
+    }
+
+    /// Add the polymer intensities from a single spectrum.
+    fn push(&mut self, spectrum: &Spectrum, matches: Vec<Vec<SeriesMatch>>) {
+        self.ret_times.push(spectrum.scan_start_time);
+        self.tic.push(spectrum.total_ion_current);
+        self.total += spectrum.total_ion_current;
+        for (poly, poly_matches) in self.polymers.iter_mut().zip(matches) {
+            poly.push(poly_matches, spectrum.total_ion_current);
+        }
+    }
+
+    /// The percentage of MS2 spectra whose precursor matched a polymer.
+    pub fn percent_ms2(&self, poly: &PolymerResult) -> f64 {
+        if self.ms2_scans > 0 {
+            100. * poly.ms2_scans as f64 / self.ms2_scans as f64
+        } else {
+            0.
+        }
+    }
+
+    /// Count an MS2 spectrum and the polymers its precursors matched.
+    fn push_ms2(&mut self, matched: Vec<bool>) {
+        self.ms2_scans += 1;
+        for (poly, is_match) in self.polymers.iter_mut().zip(matched) {
+            if is_match {
+                poly.ms2_scans += 1;
+            }
+        }
+    }
+
+    /// The percentage of the total ion current explained by the decoys of
+    /// a polymer, on average.
+    pub fn background_tic(&self, poly: &PolymerResult) -> Option<f64> {
+        let decoys = poly.decoys.as_ref()?;
+        let total = self.observable_tic(poly);
+        match total > 0. {
+            true => Some(100. * decoys.background / total),
+            false => Some(0.),
+        }
+    }
+
+    /// Find the chromatographic peaks of each polymer.
+    pub fn pick_peaks(&mut self, settings: &PeakSettings) {
+        for poly in self.polymers.iter_mut() {
+            poly.peaks = pick_peaks(&self.ret_times, &poly.xic, settings);
+        }
+    }
+
+    /// Summarize the oligomers and mass errors and find the
+    /// chromatographic peaks of each polymer, and compare each polymer to
+    /// its decoys.
+    ///
+    /// The decoys of each polymer follow all of the polymers, in order.
+    /// They are removed from the results.
+    fn finish(mut self, polymers: &[Polymer], n_decoys: usize) -> Self {
+        let n_targets = self.polymers.len() / (n_decoys + 1);
+        let decoys = self.polymers.split_off(n_targets);
+        for (idx, poly) in self.polymers.iter_mut().enumerate() {
+            poly.add_compositions(&polymers[idx]);
+
+            if n_decoys > 0 {
+                let decoy_totals: Vec<f64> = decoys[idx * n_decoys..(idx + 1) * n_decoys]
+                    .iter()
+                    .map(|x| x.total)
+                    .collect();
+                poly.decoys = Some(DecoyStats::new(poly.total, &decoy_totals));
+            }
+
+            poly.mass_error_ppm = poly.mass_errors.errors("ppm").summary();
+            poly.mass_error_da = poly.mass_errors.errors("da").summary();
+        }
+        self.pick_peaks(&PeakSettings::default());
+        self
+    }
 }
 
 impl PolymerResult {
@@ -29,87 +224,759 @@ impl PolymerResult {
             name: name.to_string(),
             total: 0.,
             xic: Vec::new(),
+            oligomers: Vec::new(),
+            ms2_scans: 0,
+            excluded_tic: 0.,
+            peaks: Vec::new(),
+            decoys: None,
+            mass_error_ppm: None,
+            mass_error_da: None,
+            mass_errors: MassErrors::default(),
+            series: Vec::new(),
+            repeat_units: Vec::new(),
+            compositions: Vec::new(),
+        }
+    }
+
+    /// Add the matches of each ion series from a single spectrum, with
+    /// its total ion current.
+    fn push(&mut self, matches: Vec<SeriesMatch>, tic: f64) {
+        if !matches.iter().any(|x| x.observable) {
+            self.excluded_tic += tic;
+        }
+        let mut total = 0.;
+        for (series, matched) in self.series.iter_mut().zip(matches) {
+            add_intensities(&mut series.precursors, &matched.intensities);
+            for error in matched.errors.iter() {
+                self.mass_errors.push(error);
+            }
+            let intensity: f64 = matched.intensities.iter().sum();
+            series.xic.push(intensity);
+            series.total += intensity;
+            total += intensity;
+        }
+        self.xic.push(total);
+        self.total += total;
+    }
+
+    /// Sum the intensity of each precursor by its number of repeat units
+    /// and, for copolymers, by its composition.
+    fn add_compositions(&mut self, poly: &Polymer) {
+        let ion_series = match &poly.series {
+            Some(ion_series) => ion_series,
+            None => return,
+        };
+
+        let mut compositions: BTreeMap<&[usize], f64> = BTreeMap::new();
+        for (series, ions) in self.series.iter_mut().zip(ion_series) {
+            for (counts, intensity) in ions.compositions.iter().zip(series.precursors.iter()) {
+                let n = counts.iter().sum::<usize>();
+                for oligomers in [&mut self.oligomers, &mut series.oligomers] {
+                    if oligomers.len() <= n {
+                        oligomers.resize(n + 1, 0.);
+                    }
+                    oligomers[n] += intensity;
+                }
+                if !self.repeat_units.is_empty() && *intensity > 0. {
+                    *compositions.entry(counts).or_insert(0.) += intensity;
+                }
+            }
+        }
+
+        self.compositions = compositions
+            .into_iter()
+            .map(|(counts, intensity)| Composition {
+                counts: counts.to_vec(),
+                intensity,
+            })
+            .collect();
+    }
+
+    /// The intensity-weighted mean number of repeat units.
+    pub fn mean_chain_length(&self) -> Option<f64> {
+        let total: f64 = self.oligomers.iter().sum();
+        let weighted: f64 = self
+            .oligomers
+            .iter()
+            .enumerate()
+            .map(|(n, intensity)| n as f64 * intensity)
+            .sum();
+        match total > 0. {
+            true => Some(weighted / total),
+            false => None,
+        }
+    }
+}
+
+/// Add the intensity of each precursor to a running total.
+fn add_intensities(totals: &mut Vec<f64>, intensities: &[f64]) {
+    if totals.len() < intensities.len() {
+        totals.resize(intensities.len(), 0.);
+    }
+    for (total, intensity) in totals.iter_mut().zip(intensities) {
+        *total += intensity;
+    }
+}
+
+impl From<&Polymer> for PolymerResult {
+    fn from(poly: &Polymer) -> Self {
+        let mut result = Self::new(&poly.name);
+        result.series = poly
+            .adducts()
+            .into_iter()
+            .map(|x| SeriesResult::new(&x.name, x.charge))
+            .collect();
+        if poly.is_copolymer() {
+            result.repeat_units = poly.units().iter().map(|x| x.label()).collect();
+        }
+        result
+    }
+}
+
+impl SeriesResult {
+    pub fn new(name: &str, charge: i32) -> Self {
+        Self {
+            name: name.to_string(),
+            charge,
+            total: 0.,
+            xic: Vec::new(),
+            oligomers: Vec::new(),
+            precursors: Vec::new(),
+        }
+    }
+}
+
+/// A comparison of a polymer to its decoys.
+///
+/// Decoys are copies of the polymer with every ion shifted by a
+/// fraction of an m/z, so they should only match unrelated peaks.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DecoyStats {
+    /// The number of decoys.
+    pub n: usize,
+    /// The mean total intensity of the decoys.
+    pub background: f64,
+    /// The total intensity of the polymer divided by the background.
+    /// This is undefined when the background is zero.
+    pub enrichment: Option<f64>,
+    /// The fraction of decoys, with a pseudocount, that are at least as
+    /// intense as the polymer.
+    pub p_value: f64,
+}
+
+impl DecoyStats {
+    pub fn new(total: f64, decoy_totals: &[f64]) -> Self {
+        let n = decoy_totals.len();
+        let background = decoy_totals.iter().sum::<f64>() / n.max(1) as f64;
+        let n_greater = decoy_totals.iter().filter(|x| **x >= total).count();
+        Self {
+            n,
+            background,
+            enrichment: match background > 0. {
+                true => Some(total / background),
+                false => None,
+            },
+            p_value: (n_greater + 1) as f64 / (n + 1) as f64,
+        }
+    }
+}
+
+/// The largest charge of the ions whose isotope peaks decoys avoid.
+const DECOY_MAX_CHARGE: i32 = 3;
+
+/// The smallest distance, in m/z, between a decoy shift and the offset of
+/// an isotope peak.
+const DECOY_MARGIN: f64 = 0.05;
+
+/// The ranges of m/z shifts that avoid the isotope peaks of ions with
+/// charges up to 3, which are offset by multiples of 1/3 and 1/2 m/z.
+fn decoy_ranges() -> Vec<(f64, f64)> {
+    let mut offsets: Vec<f64> = (1..=DECOY_MAX_CHARGE)
+        .flat_map(|z| (0..=z).map(move |k| k as f64 * ISOTOPE_SPACING / z as f64))
+        .collect();
+    offsets.sort_by(|a, b| a.total_cmp(b));
+    offsets
+        .windows(2)
+        .map(|x| (x[0] + DECOY_MARGIN, x[1] - DECOY_MARGIN))
+        .filter(|(lower, upper)| lower < upper)
+        .collect()
+}
+
+/// The m/z shifts of `n` decoys, alternating in sign.
+///
+/// The magnitudes are spread evenly over the ranges from
+/// [`decoy_ranges`].
+fn decoy_shifts(n: usize) -> Vec<f64> {
+    let ranges = decoy_ranges();
+    let width: f64 = ranges.iter().map(|(lower, upper)| upper - lower).sum();
+    let n_pairs = ((n + 1) / 2) as f64;
+    (0..n)
+        .map(|idx| {
+            let mut pos = width * ((idx / 2) as f64 + 0.5) / n_pairs;
+            let mut shift = 0.;
+            for (lower, upper) in ranges.iter() {
+                if pos <= upper - lower {
+                    shift = lower + pos;
+                    break;
+                }
+                pos -= upper - lower;
+            }
+            match idx % 2 {
+                0 => shift,
+                _ => -shift,
+            }
+        })
+        .collect()
+}
+
+/// The peaks of an ion series that were matched in a single spectrum.
+#[derive(Debug, Default)]
+struct SeriesMatch {
+    /// The intensity of each oligomer.
+    intensities: Vec<f64>,
+    /// The errors of the matched oligomers.
+    errors: Vec<MassError>,
+    /// Is any precursor within the scan windows of the spectrum?
+    observable: bool,
+}
+
+/// How to match the isotope envelope of each precursor.
+#[derive(Debug, Clone, Copy)]
+pub struct IsotopeSettings {
+    /// The minimum abundance of an isotope peak to extract, relative to
+    /// the most abundant isotope peak.
+    pub min_abundance: f64,
+    /// The minimum cosine similarity between the observed and theoretical
+    /// isotope envelopes for the envelope to be counted.
+    pub min_score: Option<f64>,
+}
+
+impl Default for IsotopeSettings {
+    fn default() -> Self {
+        Self {
+            min_abundance: 0.05,
+            min_score: None,
         }
     }
 }
 
+/// Extract the polymer intensities from spectra.
+#[derive(Clone)]
+pub struct PolymerSearch {
+    polymers: Vec<Polymer>,
+    tol: f64,
+    unit: String,
+    /// The systematic offset of observed m/z values, in `unit`.
+    offset: f64,
+    /// The number of decoys of each polymer, which follow the polymers.
+    n_decoys: usize,
+    /// Only search spectra within this retention time range.
+    rt_range: (f64, f64),
+    isotopes: Option<IsotopeSettings>,
+    /// The largest m/z for which precursors have been calculated.
+    max_mz: f64,
+}
+
+impl PolymerSearch {
+    pub fn new(polymers: Vec<Polymer>, tol: &f64, unit: &str) -> Self {
+        Self {
+            polymers,
+            tol: *tol,
+            unit: unit.to_string(),
+            offset: 0.,
+            n_decoys: 0,
+            rt_range: (f64::NEG_INFINITY, f64::INFINITY),
+            isotopes: None,
+            max_mz: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Search `n` decoys of each polymer to estimate the background.
+    pub fn with_decoys(mut self, n: usize) -> Self {
+        let n_targets = self.polymers.len() / (self.n_decoys + 1);
+        self.polymers.truncate(n_targets);
+        let shifts = decoy_shifts(n);
+        let decoys: Vec<Polymer> = self
+            .polymers
+            .iter()
+            .flat_map(|poly| shifts.iter().map(|shift| poly.decoy(*shift)))
+            .collect();
+        self.polymers.extend(decoys);
+        self.n_decoys = n;
+        self.max_mz = f64::NEG_INFINITY;
+        self
+    }
+
+    /// Only search spectra within a retention time range. Retention times
+    /// are in the units of the mzML file, which are usually minutes.
+    pub fn with_rt_range(mut self, min: Option<f64>, max: Option<f64>) -> Self {
+        self.rt_range = (
+            min.unwrap_or(f64::NEG_INFINITY),
+            max.unwrap_or(f64::INFINITY),
+        );
+        self
+    }
+
+    /// Is the spectrum within the retention time range?
+    fn in_rt_range(&self, spectrum: &Spectrum) -> bool {
+        let rt = spectrum.scan_start_time;
+        self.rt_range.0 <= rt && rt <= self.rt_range.1
+    }
+
+    /// The unit of the tolerance, either "ppm" or "da".
+    pub fn unit(&self) -> &str {
+        &self.unit
+    }
+
+    /// Search around offset m/z values with a calibrated tolerance.
+    pub fn with_calibration(mut self, calibration: &Calibration) -> Self {
+        self.offset = calibration.offset;
+        self.tol = calibration.tol;
+        self.unit = calibration.unit.clone();
+        // The precursor tolerances need to be recalculated:
+        self.max_mz = f64::NEG_INFINITY;
+        self
+    }
+
+    /// Sum the isotope envelope of each precursor, rather than only the
+    /// monoisotopic peak.
+    pub fn with_isotopes(mut self, settings: IsotopeSettings) -> Self {
+        self.isotopes = Some(settings);
+        self
+    }
+
+    /// Search spectra that have been read into memory.
+    pub fn search(
+        mut self,
+        filename: String,
+        mut spec: MS1Spectra,
+    ) -> Result<PolymerResults, SearchError> {
+        let mut results = PolymerResults::new(filename, &self.polymers);
+        spec.spectra.retain(|x| self.in_rt_range(x));
+        let upper = spec
+            .spectra
+            .iter()
+            .map(max_mz)
+            .fold(spec.scan_range.1, f64::max);
+
+        self.extend_bounds(upper);
+        let matches: Vec<Vec<Vec<SeriesMatch>>> = spec
+            .spectra
+            .par_iter()
+            .map(|x| self.match_spectrum(x))
+            .collect();
+
+        for (spectrum, spec_matches) in spec.spectra.iter().zip(matches) {
+            results.push(spectrum, spec_matches);
+        }
+
+        Ok(results.finish(&self.polymers, self.n_decoys))
+    }
+
+    /// Search spectra as they are read, keeping memory use constant.
+    pub async fn search_stream<S>(
+        mut self,
+        filename: String,
+        spectra: S,
+    ) -> Result<PolymerResults, SearchError>
+    where
+        S: Stream<Item = Result<Spectrum, MzMLError>>,
+    {
+        let mut results = PolymerResults::new(filename, &self.polymers);
+
+        futures::pin_mut!(spectra);
+        while let Some(spectrum) = spectra.next().await {
+            let spectrum = spectrum?;
+            if !self.in_rt_range(&spectrum) {
+                continue;
+            }
+            if spectrum.ms_level == 2 {
+                let max_precursor = spectrum.precursors.iter().map(|x| x.mz).fold(0., f64::max);
+                self.extend_bounds(max_precursor);
+                results.push_ms2(self.match_precursors(&spectrum));
+                continue;
+            }
+            self.extend_bounds(max_mz(&spectrum));
+            let matches = self.match_spectrum(&spectrum);
+            results.push(&spectrum, matches);
+        }
+
+        Ok(results.finish(&self.polymers, self.n_decoys))
+    }
+
+    /// Make sure precursors have been calculated up to `max_mz`.
+    fn extend_bounds(&mut self, max_mz: f64) {
+        if max_mz <= self.max_mz {
+            return;
+        }
+        // Round up so that we don't recalculate for every spectrum.
+        let max_mz = (max_mz / 100.).ceil() * 100.;
+        self.max_mz = max_mz;
+        let isotopes = self.isotopes;
+        let (tol, unit) = (self.tol, self.unit.as_str());
+        self.polymers.par_iter_mut().for_each(|poly| {
+            poly.calculate_bounds(&max_mz, &tol, unit);
+            if let Some(settings) = isotopes {
+                poly.calculate_envelopes(settings.min_abundance, &tol, unit);
+            }
+        });
+    }
+
+    /// The matches of each ion series of each polymer in a spectrum.
+    fn match_spectrum(&self, spectrum: &Spectrum) -> Vec<Vec<SeriesMatch>> {
+        let (mz, intensity) = sorted_peaks(spectrum);
+        self.polymers
+            .par_iter()
+            .map(|poly| self.match_polymer(poly, spectrum, &mz, &intensity))
+            .collect()
+    }
+
+    /// The matches of each ion series of a polymer in a spectrum.
+    ///
+    /// Each peak is claimed at most once per polymer. When the queries of
+    /// several oligomers match the same peak, such as an isotope peak of
+    /// one copolymer and the monoisotopic peak of another, only the query
+    /// closest to the peak keeps it.
+    fn match_polymer(
+        &self,
+        poly: &Polymer,
+        spectrum: &Spectrum,
+        mz: &[f64],
+        intensity: &[f64],
+    ) -> Vec<SeriesMatch> {
+        let all_series = poly.series.as_ref().unwrap();
+        let observable: Vec<bool> = all_series
+            .iter()
+            .map(|series| {
+                series
+                    .precursors
+                    .iter()
+                    .any(|x| spectrum.in_scan_window(*x))
+            })
+            .collect();
+
+        // The queries of each oligomer, for the ion series that are searched:
+        let queries: Vec<Vec<OligomerQueries>> = all_series
+            .iter()
+            .zip(observable.iter())
+            .map(|(series, observable)| {
+                match *observable && spectrum.polarity.allows(series.adduct.charge) {
+                    true => oligomer_queries(series),
+                    false => Vec::new(),
+                }
+            })
+            .collect();
+        let mut matches: Vec<Vec<Vec<Option<usize>>>> = queries
+            .iter()
+            .map(|oligomers| {
+                oligomers
+                    .iter()
+                    .map(|x| self.match_peaks(x.mz, x.tols, spectrum, mz, intensity))
+                    .collect()
+            })
+            .collect();
+
+        // Find the closest query to each matched peak, then drop the rest:
+        let mut closest: HashMap<usize, (f64, (usize, usize, usize))> = HashMap::new();
+        for (s, oligomers) in matches.iter().enumerate() {
+            for (n, peaks) in oligomers.iter().enumerate() {
+                for (k, idx) in peaks.iter().enumerate() {
+                    if let Some(idx) = idx {
+                        let query_mz = queries[s][n].mz[k];
+                        let dist = (mz[*idx] - query_mz - self.shift(query_mz)).abs();
+                        let entry = closest.entry(*idx).or_insert((dist, (s, n, k)));
+                        if dist < entry.0 {
+                            *entry = (dist, (s, n, k));
+                        }
+                    }
+                }
+            }
+        }
+        for (s, oligomers) in matches.iter_mut().enumerate() {
+            for (n, peaks) in oligomers.iter_mut().enumerate() {
+                for (k, idx) in peaks.iter_mut().enumerate() {
+                    if idx.map_or(false, |x| closest[&x].1 != (s, n, k)) {
+                        *idx = None;
+                    }
+                }
+            }
+        }
+
+        all_series
+            .iter()
+            .enumerate()
+            .map(|(s, series)| {
+                let oligomers: Vec<(f64, Option<MassError>)> = queries[s]
+                    .iter()
+                    .zip(matches[s].iter())
+                    .map(|(query, peaks)| self.score_peaks(query, peaks, mz, intensity))
+                    .collect();
+
+                let is_match = |n: &usize| oligomers.get(*n).map_or(false, |x| x.1.is_some());
+                let mut matched = SeriesMatch {
+                    observable: observable[s],
+                    ..Default::default()
+                };
+                for (n, (total, error)) in oligomers.iter().enumerate() {
+                    matched.intensities.push(*total);
+                    if let Some(mut error) = *error {
+                        error.supported = series.neighbors[n].iter().any(is_match);
+                        matched.errors.push(error);
+                    }
+                }
+                matched
+            })
+            .collect()
+    }
+
+    /// Do the precursors of an MS2 spectrum match each polymer?
+    ///
+    /// A precursor matches if it is within the tolerance of an oligomer,
+    /// or one of its isotope peaks, and its charge is unknown or the same
+    /// as the ion series.
+    fn match_precursors(&self, spectrum: &Spectrum) -> Vec<bool> {
+        self.polymers
+            .iter()
+            .map(|poly| {
+                poly.series.as_ref().unwrap().iter().any(|series| {
+                    if !spectrum.polarity.allows(series.adduct.charge) {
+                        return false;
+                    }
+                    spectrum.precursors.iter().any(|precursor| {
+                        let charge = series.adduct.charge.abs();
+                        if precursor.charge.map_or(false, |x| x.abs() != charge) {
+                            return false;
+                        }
+                        let is_match = |queries: &[f64], tols: &[f64]| {
+                            queries.iter().zip(tols.iter()).any(|(query_mz, tol)| {
+                                (query_mz + self.shift(*query_mz) - precursor.mz).abs() <= *tol
+                            })
+                        };
+                        match &series.envelopes {
+                            Some(envelopes) => envelopes.iter().any(|x| is_match(&x.mz, &x.tols)),
+                            None => is_match(&series.precursors, &series.tols),
+                        }
+                    })
+                })
+            })
+            .collect()
+    }
+
+    /// The most intense peak within the tolerance of each query.
+    ///
+    /// Peaks outside of the scan windows of the spectrum are never matched.
+    fn match_peaks(
+        &self,
+        queries: &[f64],
+        tols: &[f64],
+        spectrum: &Spectrum,
+        mz_vec: &[f64],
+        intensity_vec: &[f64],
+    ) -> Vec<Option<usize>> {
+        queries
+            .iter()
+            .zip(tols.iter())
+            .map(|(query_mz, tol)| {
+                let query_mz = query_mz + self.shift(*query_mz);
+                match spectrum.in_scan_window(query_mz) {
+                    true => best_match(query_mz, *tol, mz_vec, intensity_vec),
+                    false => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Sum the intensity of the matched peaks of a single oligomer.
+    ///
+    /// The queries are either the monoisotopic m/z or the isotope envelope,
+    /// with the theoretical abundance of each peak. If a minimum isotope
+    /// score is set, the envelope only counts if the cosine similarity
+    /// between the observed and theoretical abundances is at least that
+    /// score. The error of the most intense matched peak is returned.
+    fn score_peaks(
+        &self,
+        query: &OligomerQueries,
+        matches: &[Option<usize>],
+        mz_vec: &[f64],
+        intensity_vec: &[f64],
+    ) -> (f64, Option<MassError>) {
+        let observed: Vec<f64> = matches
+            .iter()
+            .map(|x| x.map_or(0., |idx| intensity_vec[idx]))
+            .collect();
+
+        let min_score = self.isotopes.and_then(|x| x.min_score);
+        if let (Some(min_score), Some(abundance)) = (min_score, query.abundance) {
+            if cosine(&observed, abundance) < min_score {
+                return (0., None);
+            }
+        }
+
+        let error = matches
+            .iter()
+            .zip(query.mz.iter())
+            .filter_map(|(idx, query_mz)| idx.map(|idx| (idx, query_mz)))
+            .max_by(|a, b| intensity_vec[a.0].total_cmp(&intensity_vec[b.0]))
+            .map(|(idx, query_mz)| MassError::new(*query_mz, mz_vec[idx], intensity_vec[idx]));
+
+        (observed.iter().sum(), error)
+    }
+
+    /// The systematic offset at an m/z value, in Da.
+    fn shift(&self, mz: f64) -> f64 {
+        match &self.unit.to_lowercase()[..] {
+            "da" => self.offset,
+            "ppm" => self.offset * mz / 1_000_000.0,
+            _ => unreachable!("Invalid unit {}", self.unit),
+        }
+    }
+}
+
+/// The m/z values to match for a single oligomer.
+struct OligomerQueries<'a> {
+    mz: &'a [f64],
+    tols: &'a [f64],
+    /// The theoretical abundance of each isotope peak, if the queries are
+    /// an isotope envelope.
+    abundance: Option<&'a [f64]>,
+}
+
+/// The queries of each oligomer in an ion series.
+fn oligomer_queries(series: &IonSeries) -> Vec<OligomerQueries<'_>> {
+    match &series.envelopes {
+        Some(envelopes) => envelopes
+            .iter()
+            .map(|env| OligomerQueries {
+                mz: &env.mz,
+                tols: &env.tols,
+                abundance: Some(&env.abundance),
+            })
+            .collect(),
+        None => series
+            .precursors
+            .iter()
+            .zip(series.tols.iter())
+            .map(|(mz, tol)| OligomerQueries {
+                mz: std::slice::from_ref(mz),
+                tols: std::slice::from_ref(tol),
+                abundance: None,
+            })
+            .collect(),
+    }
+}
+
+/// The largest m/z that could be observed in a spectrum.
+///
+/// This is the upper limit of its scan windows, or its largest peak when
+/// the scan windows were not reported.
+fn max_mz(spectrum: &Spectrum) -> f64 {
+    spectrum
+        .scan_windows
+        .iter()
+        .map(|x| x.1)
+        .filter(|x| x.is_finite())
+        .chain(spectrum.mz.iter().copied())
+        .fold(0., f64::max)
+}
+
 pub fn search(
     filename: String,
     spec: MS1Spectra,
+    polymers: Vec<Polymer>,
     tol: &f64,
     unit: &str,
 ) -> Result<PolymerResults, SearchError> {
-    let polymers = DefaultPolymers::new();
-    let poly_results: Vec<PolymerResult> = polymers
-        .0
-        .into_par_iter()
-        .map(|mut x| search_for_polymer(&mut x, &spec, tol, unit))
-        .collect::<Result<Vec<PolymerResult>, SearchError>>()?;
-
-    let mut results = PolymerResults {
-        filename,
-        polymers: poly_results,
-        ret_times: Vec::new(),
-        tic: Vec::new(),
-        total: 0.,
-    };
-
-    let _ = spec
-        .spectra
-        .into_iter()
-        .map(|x| {
-            results.ret_times.push(x.scan_start_time);
-            results.tic.push(x.total_ion_current);
-            results.total += x.total_ion_current;
-        })
-        .count();
-
-    Ok(results)
+    PolymerSearch::new(polymers, tol, unit).search(filename, spec)
 }
 
-fn search_for_polymer(
-    poly: &mut Polymer,
-    spec: &MS1Spectra,
+/// Search spectra as they are read, keeping memory use constant.
+pub async fn search_stream<S>(
+    filename: String,
+    spectra: S,
+    polymers: Vec<Polymer>,
     tol: &f64,
     unit: &str,
-) -> Result<PolymerResult, SearchError> {
-    poly.calculate_bounds(&spec.scan_range.1, tol, unit);
-    let mut results = PolymerResult::new(&poly.name);
-
-    results.xic = spec
-        .spectra
-        .to_vec()
-        .into_par_iter()
-        .map(|x| {
-            find_peaks(
-                poly.precursors.as_ref().unwrap(),
-                poly.tols.as_ref().unwrap(),
-                &x.mz,
-                &x.intensity,
-            )
-        })
-        .collect::<Vec<f64>>();
+) -> Result<PolymerResults, SearchError>
+where
+    S: Stream<Item = Result<Spectrum, MzMLError>>,
+{
+    PolymerSearch::new(polymers, tol, unit)
+        .search_stream(filename, spectra)
+        .await
+}
 
-    results.total = results.xic.clone().into_iter().sum();
-    Ok(results)
+/// Sum the most intense peak within the tolerance of each query m/z.
+///
+/// The m/z values of the spectrum must be sorted in ascending order, which
+/// lets each query be found with a binary search.
+pub fn find_peaks(
+    query_vec: &[f64],
+    tol_vec: &[f64],
+    mz_vec: &[f64],
+    intensity_vec: &[f64],
+) -> f64 {
+    query_vec
+        .iter()
+        .zip(tol_vec.iter())
+        .map(|(query_mz, tol)| most_intense(*query_mz, *tol, mz_vec, intensity_vec))
+        .sum()
 }
 
-fn find_peaks(query_vec: &[f64], tol_vec: &[f64], mz_vec: &[f64], intensity_vec: &[f64]) -> f64 {
-    let mut total_intensity = 0.;
-    let query_iter = query_vec.iter().zip(tol_vec.iter());
+/// The intensity of the most intense peak within the tolerance of a query.
+fn most_intense(query_mz: f64, tol: f64, mz_vec: &[f64], intensity_vec: &[f64]) -> f64 {
+    best_match(query_mz, tol, mz_vec, intensity_vec).map_or(0., |idx| intensity_vec[idx])
+}
 
-    for (query_mz, tol) in query_iter {
-        let mut biggest = 0.;
-        let spec_iter = mz_vec.iter().zip(intensity_vec.iter());
+/// The index of the most intense peak within the tolerance of a query.
+fn best_match(query_mz: f64, tol: f64, mz_vec: &[f64], intensity_vec: &[f64]) -> Option<usize> {
+    let mut biggest = 0.;
+    let mut best = None;
+    let start = mz_vec.partition_point(|mz| *mz < query_mz - tol);
+    let spec_iter = mz_vec[start..]
+        .iter()
+        .zip(intensity_vec.get(start..).unwrap_or_default());
 
-        for (mz, intensity) in spec_iter {
-            if (mz - query_mz).abs() <= *tol && intensity > &biggest {
-                biggest = *intensity;
-            }
+    for (idx, (mz, intensity)) in spec_iter.enumerate() {
+        if *mz > query_mz + tol {
+            break;
         }
-        total_intensity += biggest;
+        if (mz - query_mz).abs() <= tol && *intensity > biggest {
+            biggest = *intensity;
+            best = Some(start + idx);
+        }
+    }
+    best
+}
+
+/// The cosine similarity between two vectors.
+fn cosine(a: &[f64], b: &[f64]) -> f64 {
+    let dot: f64 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f64>().sqrt();
+    match norm_a * norm_b > 0. {
+        true => dot / (norm_a * norm_b),
+        false => 0.,
     }
-    total_intensity
+}
+
+/// Sort the peaks of a spectrum by m/z, if they aren't already.
+fn sorted_peaks(spectrum: &Spectrum) -> (Cow<'_, [f64]>, Cow<'_, [f64]>) {
+    if spectrum.mz.windows(2).all(|x| x[0] <= x[1]) {
+        return (
+            Cow::Borrowed(&spectrum.mz),
+            Cow::Borrowed(&spectrum.intensity),
+        );
+    }
+
+    let mut peaks: Vec<(f64, f64)> = spectrum
+        .mz
+        .iter()
+        .copied()
+        .zip(spectrum.intensity.iter().copied())
+        .collect();
+    peaks.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let (mz, intensity) = peaks.into_iter().unzip();
+    (Cow::Owned(mz), Cow::Owned(intensity))
 }
 
 #[derive(Debug)]
@@ -129,10 +996,25 @@ impl Error for SearchError {
     }
 }
 
+impl From<MzMLError> for SearchError {
+    fn from(residual: MzMLError) -> Self {
+        Self {
+            details: residual.to_string(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::search;
-    use crate::mzml::MzMLReader;
+    use super::{
+        decoy_shifts, find_peaks, search, search_stream, DecoyStats, IsotopeSettings,
+        PolymerSearch, DECOY_MARGIN,
+    };
+    use crate::calibration::Calibration;
+    use crate::defaults::DefaultPolymers;
+    use crate::mass::{Adduct, ISOTOPE_SPACING};
+    use crate::mzml::{MS1Spectra, MzMLReader, Polarity, Precursor, Spectrum};
+    use crate::polymer::{Polymer, RepeatUnit};
     use tokio::fs::File;
     use tokio::io::BufReader;
     const TEST_FILE: &str = "data/MSV000081544.20170728_MS1_17k_plasmaspikedPEG_3.mzML";
@@ -142,6 +1024,449 @@ mod tests {
         let mzml_file = File::open(TEST_FILE).await.unwrap();
         let mzml_file = BufReader::new(mzml_file);
         let spectra = MzMLReader::new().parse(mzml_file).await.unwrap();
-        search(TEST_FILE.to_string(), spectra, &10., "ppm").unwrap();
+        search(
+            TEST_FILE.to_string(),
+            spectra,
+            DefaultPolymers::new().0,
+            &10.,
+            "ppm",
+        )
+        .unwrap();
+    }
+
+    fn peg_spectra() -> Vec<Spectrum> {
+        // [M+H]+ of PEG with 4 and 5 repeats.
+        let mz = vec![195.1227, 200.0, 239.1489];
+        (0..3)
+            .map(|i| Spectrum {
+                ms_level: 1,
+                scan_start_time: i as f64,
+                total_ion_current: 60.,
+                mz: mz.clone(),
+                intensity: vec![10., 20., 30.],
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn stream_matches_memory() {
+        let polymers = vec![Polymer::new("PEG", "H2O", "C2H4O", 1, true).unwrap()];
+        let spectra = peg_spectra();
+        let in_memory = MS1Spectra {
+            spectra: spectra.clone(),
+            scan_range: (0., 0.),
+        };
+        let expected =
+            search("test".to_string(), in_memory, polymers.clone(), &10., "ppm").unwrap();
+
+        let stream = futures::stream::iter(spectra.into_iter().map(Ok));
+        let streamed = search_stream("test".to_string(), stream, polymers, &10., "ppm")
+            .await
+            .unwrap();
+
+        assert_eq!(streamed.polymers[0].xic, vec![40., 40., 40.]);
+        assert_eq!(streamed.polymers[0].total, expected.polymers[0].total);
+        assert_eq!(streamed.ret_times, expected.ret_times);
+        assert_eq!(streamed.total, 180.);
+        assert_eq!(
+            streamed.polymers[0].oligomers,
+            expected.polymers[0].oligomers
+        );
+    }
+
+    #[test]
+    fn oligomers() {
+        let polymers = vec![Polymer::new("PEG", "H2O", "C2H4O", 1, true).unwrap()];
+        let spectra = MS1Spectra {
+            spectra: peg_spectra(),
+            scan_range: (0., 0.),
+        };
+        let results = search("test".to_string(), spectra, polymers, &10., "ppm").unwrap();
+        let peg = &results.polymers[0];
+
+        assert_eq!(peg.oligomers[..6], [0., 0., 0., 0., 30., 90.]);
+        assert_eq!(peg.series[0].charge, 1);
+        assert_eq!(peg.series[0].oligomers, peg.oligomers);
+        assert_eq!(peg.series[1].charge, -1);
+        assert!(peg.series[1].oligomers.is_empty());
+        assert_eq!(peg.mean_chain_length(), Some(4.75));
+    }
+
+    #[test]
+    fn copolymers() {
+        let ppg = RepeatUnit::new("C3H6O").unwrap().with_range(1, Some(2));
+        let polymers = vec![Polymer::new("PEG-PPG", "H2O", "C2H4O", 1, true)
+            .unwrap()
+            .with_repeat_unit(ppg)
+            .with_adducts(vec![Adduct::protonated(1)])];
+
+        // [M+H]+ with (EO, PO) of (2, 1), (1, 2), and (3, 1):
+        let spectra = MS1Spectra {
+            spectra: vec![Spectrum {
+                ms_level: 1,
+                total_ion_current: 100.,
+                mz: vec![165.1121, 179.1278, 209.1384],
+                intensity: vec![10., 20., 30.],
+                ..Default::default()
+            }],
+            scan_range: (0., 0.),
+        };
+        let results = search("test".to_string(), spectra, polymers, &10., "ppm").unwrap();
+        let copolymer = &results.polymers[0];
+
+        assert_eq!(copolymer.total, 60.);
+        assert_eq!(copolymer.repeat_units, vec!["C2H4O", "C3H6O"]);
+        let compositions: Vec<(Vec<usize>, f64)> = copolymer
+            .compositions
+            .iter()
+            .map(|x| (x.counts.clone(), x.intensity))
+            .collect();
+        assert_eq!(
+            compositions,
+            vec![(vec![1, 2], 20.), (vec![2, 1], 10.), (vec![3, 1], 30.)]
+        );
+        assert_eq!(copolymer.oligomers[3..5], [30., 30.]);
+
+        // Only (2, 1) and (3, 1) differ by a single repeating unit:
+        assert_eq!(copolymer.mass_errors.n_supported(), 2);
+    }
+
+    #[test]
+    fn polarity() {
+        let polymers = vec![Polymer::new("PEG", "H2O", "C2H4O", 1, true).unwrap()];
+        let mut spectra = peg_spectra();
+        spectra[0].polarity = Polarity::Positive;
+        spectra[1].polarity = Polarity::Negative;
+        // [M-H]- of PEG with 4 repeats:
+        spectra[2].polarity = Polarity::Negative;
+        spectra[2].mz[0] = 193.1081;
+        let spectra = MS1Spectra {
+            spectra,
+            scan_range: (0., 0.),
+        };
+
+        let results = search("test".to_string(), spectra, polymers, &10., "ppm").unwrap();
+        let peg = &results.polymers[0];
+        assert_eq!(peg.xic, vec![40., 0., 10.]);
+        assert_eq!(peg.series[0].xic, vec![40., 0., 0.]);
+        assert_eq!(peg.series[1].xic, vec![0., 0., 10.]);
+    }
+
+    #[test]
+    fn test_find_peaks() {
+        let mz = vec![99.9, 100.0, 100.05, 100.2, 200.0, 300.0];
+        let intensity = vec![5., 1., 3., 100., 7., 11.];
+        let total = find_peaks(&[100.0, 250.0, 300.0], &[0.1, 0.1, 0.1], &mz, &intensity);
+        assert_eq!(total, 5. + 0. + 11.);
+
+        let total = find_peaks(&[50.0, 400.0], &[0.1, 0.1], &mz, &intensity);
+        assert_eq!(total, 0.);
+    }
+
+    #[test]
+    fn unsorted_spectrum() {
+        let polymers = vec![Polymer::new("PEG", "H2O", "C2H4O", 1, true).unwrap()];
+        let mut spectra = peg_spectra();
+        spectra[0].mz.reverse();
+        spectra[0].intensity.reverse();
+        let spectra = MS1Spectra {
+            spectra,
+            scan_range: (0., 0.),
+        };
+
+        let results = search("test".to_string(), spectra, polymers, &10., "ppm").unwrap();
+        assert_eq!(results.polymers[0].xic, vec![40., 40., 40.]);
+    }
+
+    #[test]
+    fn isotopes() {
+        // The first three isotope peaks of PEG with 20 repeats:
+        let mut poly = Polymer::new("PEG", "H2O", "C2H4O", 1, true).unwrap();
+        poly.calculate_bounds(&1000., &10., "ppm");
+        poly.calculate_envelopes(0.05, &10., "ppm");
+        let envelope = &poly.series.as_ref().unwrap()[0].envelopes.as_ref().unwrap()[20];
+        let spectrum = |intensity: Vec<f64>| MS1Spectra {
+            spectra: vec![Spectrum {
+                ms_level: 1,
+                total_ion_current: 100.,
+                mz: envelope.mz[..3].to_vec(),
+                intensity,
+                ..Default::default()
+            }],
+            scan_range: (0., 1000.),
+        };
+
+        let polymers = vec![Polymer::new("PEG", "H2O", "C2H4O", 1, true).unwrap()];
+        let mono = search(
+            "test".to_string(),
+            spectrum(vec![60., 30., 10.]),
+            polymers.clone(),
+            &10.,
+            "ppm",
+        );
+        assert_eq!(mono.unwrap().polymers[0].total, 60.);
+
+        let search_isotopes = |intensity, settings| {
+            PolymerSearch::new(polymers.clone(), &10., "ppm")
+                .with_isotopes(settings)
+                .search("test".to_string(), spectrum(intensity))
+                .unwrap()
+                .polymers[0]
+                .total
+        };
+
+        let settings = IsotopeSettings::default();
+        assert_eq!(search_isotopes(vec![60., 30., 10.], settings), 100.);
+
+        // A poor match to the theoretical envelope is not counted:
+        let settings = IsotopeSettings {
+            min_score: Some(0.95),
+            ..Default::default()
+        };
+        assert_eq!(search_isotopes(vec![60., 30., 10.], settings), 100.);
+        assert_eq!(search_isotopes(vec![10., 30., 60.], settings), 0.);
+    }
+
+    #[test]
+    fn shared_peaks() {
+        // The windows of PEG with 4 and 5 repeats overlap with such a wide
+        // tolerance, but the peak between them is only counted once, for
+        // the closer 5-mer:
+        let polymers = vec![Polymer::new("PEG", "H2O", "C2H4O", 1, true).unwrap()];
+        let spectra = MS1Spectra {
+            spectra: vec![Spectrum {
+                ms_level: 1,
+                total_ion_current: 10.,
+                mz: vec![220.],
+                intensity: vec![10.],
+                ..Default::default()
+            }],
+            scan_range: (0., 0.),
+        };
+        let results = search("test".to_string(), spectra, polymers, &30., "da").unwrap();
+        let peg = &results.polymers[0];
+        assert_eq!(peg.total, 10.);
+        assert_eq!(peg.oligomers[4..6], [0., 10.]);
+        assert_eq!(peg.mass_errors.len(), 1);
+    }
+
+    #[test]
+    fn mass_errors() {
+        // PEG+1H with 4 to 6 repeats, shifted by 8 ppm:
+        let mut poly = Polymer::new("PEG", "H2O", "C2H4O", 1, true).unwrap();
+        poly.calculate_bounds(&300., &10., "ppm");
+        let mz: Vec<f64> = poly.series.unwrap()[0].precursors[4..7]
+            .iter()
+            .map(|x| x * (1. + 8e-6))
+            .collect();
+        let spectra = MS1Spectra {
+            spectra: vec![Spectrum {
+                ms_level: 1,
+                total_ion_current: 60.,
+                mz,
+                intensity: vec![10., 20., 30.],
+                ..Default::default()
+            }],
+            scan_range: (0., 300.),
+        };
+
+        let polymers = vec![Polymer::new("PEG", "H2O", "C2H4O", 1, true).unwrap()];
+        let results = search(
+            "test".to_string(),
+            spectra.clone(),
+            polymers.clone(),
+            &10.,
+            "ppm",
+        );
+        let peg = &results.unwrap().polymers[0];
+        assert_eq!(peg.mass_errors.len(), 3);
+        assert_eq!(peg.mass_errors.n_supported(), 3);
+        let summary = peg.mass_error_ppm.as_ref().unwrap();
+        assert!((summary.median - 8.).abs() < 1e-6);
+
+        // A narrow tolerance misses the peaks, unless it is centered on the offset:
+        let narrow = search(
+            "test".to_string(),
+            spectra.clone(),
+            polymers.clone(),
+            &2.,
+            "ppm",
+        );
+        assert_eq!(narrow.unwrap().polymers[0].total, 0.);
+
+        let calibration = Calibration {
+            offset: 8.,
+            tol: 2.,
+            unit: "ppm".to_string(),
+            n: 3,
+        };
+        let calibrated = PolymerSearch::new(polymers, &10., "ppm")
+            .with_calibration(&calibration)
+            .search("test".to_string(), spectra)
+            .unwrap();
+        assert_eq!(calibrated.polymers[0].total, 60.);
+        let summary = calibrated.polymers[0].mass_error_ppm.as_ref().unwrap();
+        assert!((summary.median - 8.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn decoy_shifts_avoid_isotopes() {
+        // The isotope peaks of ions with charges up to 3, including the
+        // monoisotopic peak of the target:
+        let offsets: Vec<f64> = (1..=3)
+            .flat_map(|z| (0..=z).map(move |k| k as f64 * ISOTOPE_SPACING / z as f64))
+            .collect();
+        for n in 1..=40 {
+            let shifts = decoy_shifts(n);
+            assert_eq!(shifts.len(), n);
+            for shift in shifts {
+                for offset in offsets.iter() {
+                    assert!(
+                        (shift.abs() - offset).abs() >= DECOY_MARGIN - 1e-9,
+                        "A shift of {} is within {} of {}",
+                        shift,
+                        DECOY_MARGIN,
+                        offset
+                    );
+                }
+            }
+        }
+
+        // Every ion series is shifted by the same m/z, whatever its charge:
+        let mut peg = Polymer::new("PEG", "H2O", "C2H4O", 2, true).unwrap();
+        let mut decoy = peg.decoy(0.2);
+        peg.calculate_bounds(&1000., &10., "ppm");
+        decoy.calculate_bounds(&1000., &10., "ppm");
+        let target = &peg.series.as_ref().unwrap()[0].precursors;
+        let shifted = &decoy.series.as_ref().unwrap()[0].precursors;
+        for (a, b) in target.iter().zip(shifted.iter()) {
+            assert!((b - a - 0.2).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn decoys() {
+        let shifts = decoy_shifts(4);
+        assert!((shifts[0] + shifts[1]).abs() < 1e-9);
+        assert!(shifts[0] > 0. && shifts[2] > shifts[0]);
+
+        let stats = DecoyStats::new(10., &[1., 3., 12.]);
+        assert_eq!(stats.background, 16. / 3.);
+        assert_eq!(stats.enrichment, Some(10. / (16. / 3.)));
+        assert_eq!(stats.p_value, 0.5);
+
+        // A peak that only matches the first decoy of PEG with 4 repeats:
+        let mut spectra = peg_spectra();
+        spectra[0].mz[1] = 195.1227 + shifts[0];
+        let polymers = vec![
+            Polymer::new("PEG", "H2O", "C2H4O", 1, true).unwrap(),
+            Polymer::new("PPG", "H2O", "C3H6O", 1, true).unwrap(),
+        ];
+        let results = PolymerSearch::new(polymers, &10., "ppm")
+            .with_decoys(4)
+            .search(
+                "test".to_string(),
+                MS1Spectra {
+                    spectra,
+                    scan_range: (0., 0.),
+                },
+            )
+            .unwrap();
+
+        assert_eq!(results.polymers.len(), 2);
+        let peg = results.polymers[0].decoys.as_ref().unwrap();
+        assert_eq!(peg.n, 4);
+        assert_eq!(peg.background, 5.);
+        assert_eq!(peg.enrichment, Some(120. / 5.));
+        assert_eq!(peg.p_value, 0.2);
+        assert_eq!(
+            results.background_tic(&results.polymers[0]),
+            Some(100. * 5. / 180.)
+        );
+
+        let ppg = results.polymers[1].decoys.as_ref().unwrap();
+        assert_eq!(ppg.background, 0.);
+        assert_eq!(ppg.enrichment, None);
+        assert_eq!(ppg.p_value, 1.);
+    }
+
+    #[test]
+    fn rt_range() {
+        let polymers = vec![Polymer::new("PEG", "H2O", "C2H4O", 1, true).unwrap()];
+        let spectra = MS1Spectra {
+            spectra: peg_spectra(),
+            scan_range: (0., 0.),
+        };
+        let results = PolymerSearch::new(polymers, &10., "ppm")
+            .with_rt_range(Some(0.5), None)
+            .search("test".to_string(), spectra)
+            .unwrap();
+        assert_eq!(results.ret_times, vec![1., 2.]);
+        assert_eq!(results.polymers[0].xic, vec![40., 40.]);
+        assert_eq!(results.total, 120.);
+
+        // A flat XIC is a single peak:
+        let peaks = &results.polymers[0].peaks;
+        assert_eq!(peaks.len(), 1);
+        assert_eq!(peaks[0].area, 40.);
+    }
+
+    #[test]
+    fn scan_windows() {
+        let polymers = vec![Polymer::new("PEG", "H2O", "C2H4O", 1, true).unwrap()];
+        let mut spectra = peg_spectra();
+        // Only the 5-mer is within the window:
+        spectra[0].scan_windows = vec![(200., 1000.)];
+        // Oligomers above the largest peak could be observed, but weren't:
+        spectra[1].scan_windows = vec![(1000., 2000.)];
+        // No oligomer falls between the 4-mer and 5-mer ions:
+        spectra[2].scan_windows = vec![(200., 210.)];
+        let spectra = MS1Spectra {
+            spectra,
+            scan_range: (0., 0.),
+        };
+
+        let results = search("test".to_string(), spectra, polymers, &10., "ppm").unwrap();
+        let peg = &results.polymers[0];
+        assert_eq!(peg.xic, vec![30., 0., 0.]);
+        assert_eq!(peg.excluded_tic, 60.);
+        assert_eq!(results.total, 180.);
+        assert_eq!(results.observable_tic(peg), 120.);
+        assert!((results.percent_tic(peg) - 100. * 30. / 120.).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn ms2() {
+        let selected = |mz: f64, charge: Option<i32>| Spectrum {
+            ms_level: 2,
+            precursors: vec![Precursor {
+                mz,
+                charge,
+                intensity: None,
+            }],
+            ..Default::default()
+        };
+        let mut spectra = peg_spectra();
+        spectra.push(selected(195.1227, Some(1)));
+        spectra.push(selected(195.1227, Some(2)));
+        spectra.push(selected(239.1489, None));
+        spectra.push(selected(300., Some(1)));
+
+        let polymers = vec![
+            Polymer::new("PEG", "H2O", "C2H4O", 1, true).unwrap(),
+            Polymer::new("PPG", "H2O", "C3H6O", 1, true).unwrap(),
+        ];
+        let stream = futures::stream::iter(spectra.into_iter().map(Ok));
+        let results = search_stream("test".to_string(), stream, polymers, &10., "ppm")
+            .await
+            .unwrap();
+
+        // MS2 spectra don't contribute to the XICs:
+        assert_eq!(results.ret_times.len(), 3);
+        assert_eq!(results.ms2_scans, 4);
+        assert_eq!(results.polymers[0].ms2_scans, 2);
+        assert_eq!(results.percent_ms2(&results.polymers[0]), 50.);
+        assert_eq!(results.polymers[1].ms2_scans, 0);
     }
 }
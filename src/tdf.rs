@@ -0,0 +1,795 @@
+//! Read spectra directly from Bruker timsTOF `.d` directories.
+//!
+//! A `.d` directory holds an `analysis.tdf` SQLite database that describes
+//! each frame and an `analysis.tdf_bin` file with the peaks of every frame.
+//! Each frame is a stack of TIMS scans, one for each ion mobility. The
+//! peaks are stored as time-of-flight (TOF) indices, which are converted to
+//! m/z using the calibration of each frame in the `MzCalibration` table.
+//! Runs without this table fall back to interpolating between the bounds
+//! of the acquisition range, which is only approximate.
+//!
+//! Frame times are converted from seconds to minutes, which is what most
+//! mzML files use.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use futures::{Stream, StreamExt};
+use log::warn;
+use rayon::prelude::*;
+use rusqlite::{Connection, OpenFlags};
+
+use crate::mzml::{MS1Spectra, MzMLError, Polarity, Precursor, Spectrum};
+
+/// The frame metadata database in a `.d` directory.
+const TDF_FILE: &str = "analysis.tdf";
+
+/// The frame peaks in a `.d` directory.
+const TDF_BIN_FILE: &str = "analysis.tdf_bin";
+
+/// The only supported `TimsCompressionType`.
+const ZSTD_COMPRESSION: i64 = 2;
+
+/// The number of frames decoded by each parallel task.
+const CHUNK_SIZE: usize = 16;
+
+/// What to do with the ion mobility of each peak.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mobility {
+    /// Sum the peaks with the same m/z across all of the scans in a frame,
+    /// which is what mzsniffer searches.
+    #[default]
+    Collapse,
+    /// Keep every peak from every scan and record its ion mobility.
+    Retain,
+}
+
+/// A frame of the run.
+#[derive(Debug, Clone)]
+struct Frame {
+    id: usize,
+    /// The retention time, in minutes.
+    time: f64,
+    polarity: Polarity,
+    ms_level: u8,
+    /// The byte offset of the frame in the binary file.
+    offset: u64,
+    /// The id of the m/z calibration of the frame.
+    calibration: Option<usize>,
+}
+
+/// A linear conversion, used for TOF indices and scan numbers.
+#[derive(Debug, Clone, Copy)]
+struct Converter {
+    intercept: f64,
+    slope: f64,
+}
+
+/// The conversion of TOF indices to m/z from the `MzCalibration` table.
+///
+/// The flight time of a TOF index, in ns, is `delay + timebase * index`,
+/// and it is related to m/z by
+///
+/// `t = C0 + sqrt(1e12 / C1) * sqrt(mz) + C2 * mz + C3 * mz^1.5 + C4 * mz^2`.
+///
+/// The temperature compensation of the calibration is not applied. It
+/// scales all flight times by nearly the same factor, which is a constant
+/// ppm offset that `--auto-tolerance` removes.
+#[derive(Debug, Clone, Copy)]
+struct MzCalibration {
+    timebase: f64,
+    delay: f64,
+    coefficients: [f64; 5],
+}
+
+impl MzCalibration {
+    /// Convert a TOF index to m/z.
+    fn mz(&self, tof: u32) -> f64 {
+        let [c0, c1, c2, c3, c4] = self.coefficients;
+        let time = self.delay + self.timebase * tof as f64 - c0;
+        let a = (1e12 / c1).sqrt();
+
+        // Solve the quadratic in sqrt(m/z), then refine it with Newton's
+        // method when the higher order terms are used:
+        let mut root = 2. * time / (a + (a * a + 4. * c2 * time).max(0.).sqrt());
+        if c3 != 0. || c4 != 0. {
+            for _ in 0..NEWTON_STEPS {
+                let residual = root * (a + root * (c2 + root * (c3 + root * c4))) - time;
+                let slope = a + root * (2. * c2 + root * (3. * c3 + root * 4. * c4));
+                root -= residual / slope;
+            }
+        }
+        root * root
+    }
+}
+
+/// The number of Newton steps used to invert the m/z calibration. The
+/// higher order terms are small, so it converges in far fewer.
+const NEWTON_STEPS: usize = 8;
+
+/// A reader for Bruker TDF data.
+#[derive(Debug, Clone)]
+pub struct TdfReader {
+    /// The binary file, which is shared by the tasks that read frames.
+    bin: Arc<Mutex<File>>,
+    frames: Vec<Frame>,
+    /// The precursors of PASEF MS2 spectra, by the first frame that selected
+    /// them.
+    precursors: HashMap<usize, Vec<(usize, Precursor)>>,
+    /// The m/z calibrations, by their id.
+    calibrations: HashMap<usize, MzCalibration>,
+    /// Converts the square root of m/z from TOF indices for frames without
+    /// a calibration.
+    tof: Converter,
+    /// Converts 1/K0 from scan numbers.
+    scan: Converter,
+    mz_range: (f64, f64),
+    mobility: Mobility,
+}
+
+impl TdfReader {
+    /// Is this a `.d` directory with TDF data?
+    pub fn is_tdf<P: AsRef<Path>>(path: P) -> bool {
+        path.as_ref().join(TDF_FILE).is_file()
+    }
+
+    /// Read the metadata of a `.d` directory.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, TdfError> {
+        let path = path.as_ref();
+        let conn = Connection::open_with_flags(
+            path.join(TDF_FILE),
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+
+        let metadata: HashMap<String, String> = conn
+            .prepare("SELECT Key, Value FROM GlobalMetadata")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        let get = |key: &str| -> Result<f64, TdfError> {
+            metadata
+                .get(key)
+                .and_then(|x| x.trim().parse().ok())
+                .ok_or_else(|| TdfError::MissingMetadata(key.to_string()))
+        };
+
+        let compression = get("TimsCompressionType")? as i64;
+        if compression != ZSTD_COMPRESSION {
+            return Err(TdfError::UnsupportedCompression(compression));
+        }
+
+        let calibrations = read_calibrations(&conn)?;
+        if calibrations.is_empty() {
+            warn!(
+                "{} has no m/z calibration, so its m/z values are approximated from the acquisition range.",
+                path.display()
+            );
+        }
+        let calibration_column = match calibrations.is_empty() {
+            true => "NULL",
+            false => "MzCalibration",
+        };
+
+        let frames = conn
+            .prepare(&format!(
+                "SELECT Id, Time, Polarity, MsMsType, TimsId, {} FROM Frames ORDER BY Id",
+                calibration_column
+            ))?
+            .query_map([], |row| {
+                let polarity: String = row.get(2)?;
+                let msms_type: i64 = row.get(3)?;
+                Ok(Frame {
+                    id: row.get(0)?,
+                    time: row.get::<_, f64>(1)? / 60.,
+                    polarity: match polarity.as_str() {
+                        "+" => Polarity::Positive,
+                        "-" => Polarity::Negative,
+                        _ => Polarity::Unknown,
+                    },
+                    ms_level: if msms_type == 0 { 1 } else { 2 },
+                    offset: row.get(4)?,
+                    calibration: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let max_scans: f64 = conn.query_row("SELECT MAX(NumScans) FROM Frames", [], |row| {
+            Ok(row.get::<_, Option<f64>>(0)?.unwrap_or(1.))
+        })?;
+
+        let mz_range = (get("MzAcqRangeLower")?, get("MzAcqRangeUpper")?);
+        let tof = Converter {
+            intercept: mz_range.0.sqrt(),
+            slope: (mz_range.1.sqrt() - mz_range.0.sqrt()) / get("DigitizerNumSamples")?,
+        };
+        let (im_lower, im_upper) = (
+            get("OneOverK0AcqRangeLower")?,
+            get("OneOverK0AcqRangeUpper")?,
+        );
+        let scan = Converter {
+            intercept: im_upper,
+            slope: (im_lower - im_upper) / max_scans,
+        };
+
+        Ok(Self {
+            bin: Arc::new(Mutex::new(File::open(path.join(TDF_BIN_FILE))?)),
+            frames,
+            precursors: read_precursors(&conn)?,
+            calibrations,
+            tof,
+            scan,
+            mz_range,
+            mobility: Mobility::default(),
+        })
+    }
+
+    /// Collapse or retain the ion mobility of each peak.
+    pub fn with_mobility(mut self, mobility: Mobility) -> Self {
+        self.mobility = mobility;
+        self
+    }
+
+    /// The m/z range that was acquired.
+    pub fn scan_range(&self) -> (f64, f64) {
+        self.mz_range
+    }
+
+    /// The spectra of a frame.
+    ///
+    /// MS1 frames are a single spectrum, unless they have no ion current.
+    /// PASEF MS2 frames are a spectrum for each precursor that was first
+    /// selected in the frame, while other MS2 frames are a single spectrum
+    /// without precursors. As with [`crate::mzml::MzMLReader::stream`],
+    /// the peaks of MS2 spectra are not decoded, so `peaks` is only given
+    /// for MS1 frames.
+    fn frame_spectra(&self, frame: &Frame, peaks: Option<FramePeaks>) -> Vec<Spectrum> {
+        let base = Spectrum {
+            ms_level: frame.ms_level,
+            id: format!("frame={}", frame.id),
+            polarity: frame.polarity,
+            scan_start_time: frame.time,
+            scan_windows: vec![self.mz_range],
+            ..Default::default()
+        };
+
+        if let Some(peaks) = peaks {
+            let spectrum = self.ms1_spectrum(frame, peaks, base);
+            return match spectrum.total_ion_current > 0. {
+                true => vec![spectrum],
+                false => Vec::new(),
+            };
+        }
+
+        match (self.precursors.is_empty(), self.precursors.get(&frame.id)) {
+            (true, _) => vec![base],
+            (false, Some(precursors)) => precursors
+                .iter()
+                .map(|(id, precursor)| Spectrum {
+                    id: format!("frame={} precursor={}", frame.id, id),
+                    precursors: vec![precursor.clone()],
+                    ..base.clone()
+                })
+                .collect(),
+            (false, None) => Vec::new(),
+        }
+    }
+
+    /// Add the peaks of an MS1 frame to its spectrum.
+    fn ms1_spectrum(&self, frame: &Frame, peaks: FramePeaks, mut spectrum: Spectrum) -> Spectrum {
+        let calibration = frame.calibration.and_then(|id| self.calibrations.get(&id));
+        let mut order: Vec<usize> = (0..peaks.tof.len()).collect();
+        order.sort_by_key(|idx| peaks.tof[*idx]);
+
+        match self.mobility {
+            Mobility::Collapse => {
+                let mut last_tof = None;
+                for idx in order {
+                    let intensity = peaks.intensity[idx] as f64;
+                    match (
+                        last_tof == Some(peaks.tof[idx]),
+                        spectrum.intensity.last_mut(),
+                    ) {
+                        (true, Some(last)) => *last += intensity,
+                        _ => {
+                            spectrum.mz.push(self.mz(calibration, peaks.tof[idx]));
+                            spectrum.intensity.push(intensity);
+                        }
+                    }
+                    last_tof = Some(peaks.tof[idx]);
+                }
+            }
+            Mobility::Retain => {
+                for idx in order {
+                    spectrum.mz.push(self.mz(calibration, peaks.tof[idx]));
+                    spectrum.intensity.push(peaks.intensity[idx] as f64);
+                    spectrum
+                        .mobility
+                        .push(self.scan.intercept + self.scan.slope * peaks.scan[idx] as f64);
+                }
+            }
+        }
+
+        spectrum.total_ion_current = spectrum.intensity.iter().sum();
+        spectrum
+    }
+
+    /// The spectra of consecutive frames.
+    ///
+    /// The compressed peaks of the MS1 frames are read while holding the
+    /// binary file, and are only decompressed after releasing it, so that
+    /// other tasks can read their frames in the meantime.
+    fn read_frames(&self, frames: &[Frame]) -> Result<Vec<Spectrum>, TdfError> {
+        let raw = {
+            // Every read seeks first, so a panic elsewhere cannot leave the
+            // file in a bad state:
+            let mut file = self.bin.lock().unwrap_or_else(|err| err.into_inner());
+            frames
+                .iter()
+                .map(|frame| match frame.ms_level {
+                    1 => read_frame(&mut *file, frame.offset).map(Some),
+                    _ => Ok(None),
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        let mut spectra = Vec::new();
+        for (frame, raw) in frames.iter().zip(raw) {
+            let peaks = raw.map(|x| x.decode()).transpose()?;
+            spectra.extend(self.frame_spectra(frame, peaks));
+        }
+        Ok(spectra)
+    }
+
+    /// Convert a TOF index to m/z.
+    ///
+    /// Without a calibration, the square root of m/z is interpolated
+    /// linearly between the bounds of the acquisition range.
+    fn mz(&self, calibration: Option<&MzCalibration>, tof: u32) -> f64 {
+        match calibration {
+            Some(calibration) => calibration.mz(tof),
+            None => (self.tof.intercept + self.tof.slope * tof as f64).powi(2),
+        }
+    }
+
+    /// Read all of the MS1 spectra into memory, decoding chunks of frames
+    /// in parallel.
+    pub fn parse(&self) -> Result<MS1Spectra, TdfError> {
+        let frames: Vec<Frame> = self
+            .frames
+            .iter()
+            .filter(|frame| frame.ms_level == 1)
+            .cloned()
+            .collect();
+        let spectra = frames
+            .par_chunks(CHUNK_SIZE)
+            .map(|chunk| self.read_frames(chunk))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        Ok(MS1Spectra::new(spectra))
+    }
+
+    /// Read the MS1 and MS2 spectra, decoding chunks of frames in parallel.
+    ///
+    /// Each chunk is read and decoded on a blocking thread, and the chunks
+    /// are yielded in order, with only a few of them held in memory at once.
+    pub fn stream(self) -> impl Stream<Item = Result<Spectrum, MzMLError>> {
+        let reader = Arc::new(self);
+        let starts: Vec<usize> = (0..reader.frames.len()).step_by(CHUNK_SIZE).collect();
+        let workers = std::thread::available_parallelism().map_or(1, |x| x.get());
+        futures::stream::iter(starts)
+            .map(move |start| {
+                let reader = reader.clone();
+                async move {
+                    tokio::task::spawn_blocking(move || {
+                        let end = (start + CHUNK_SIZE).min(reader.frames.len());
+                        reader.read_frames(&reader.frames[start..end])
+                    })
+                    .await
+                    .map_err(|err| {
+                        MzMLError::IOError(std::io::Error::new(std::io::ErrorKind::Other, err))
+                    })?
+                    .map_err(MzMLError::from)
+                }
+            })
+            .buffered(workers)
+            .flat_map(|chunk| {
+                let spectra: Vec<_> = match chunk {
+                    Ok(spectra) => spectra.into_iter().map(Ok).collect(),
+                    Err(err) => vec![Err(err)],
+                };
+                futures::stream::iter(spectra)
+            })
+    }
+}
+
+/// Read the m/z calibrations of the run, by their id, if it has any.
+fn read_calibrations(conn: &Connection) -> Result<HashMap<usize, MzCalibration>, TdfError> {
+    let exists: bool = conn.query_row(
+        "SELECT COUNT(*) = 1 FROM sqlite_master WHERE type = 'table' AND name = 'MzCalibration'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Ok(HashMap::new());
+    }
+
+    let calibrations = conn
+        .prepare(
+            "SELECT Id, DigitizerTimebase, DigitizerDelay, C0, C1, C2, C3, C4 FROM MzCalibration",
+        )?
+        .query_map([], |row| {
+            let calibration = MzCalibration {
+                timebase: row.get(1)?,
+                delay: row.get(2)?,
+                coefficients: [
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                    row.get(6)?,
+                    row.get(7)?,
+                ],
+            };
+            Ok((row.get(0)?, calibration))
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(calibrations)
+}
+
+/// Read the precursors of PASEF MS2 frames, if there are any.
+fn read_precursors(conn: &Connection) -> Result<HashMap<usize, Vec<(usize, Precursor)>>, TdfError> {
+    let mut precursors: HashMap<usize, Vec<(usize, Precursor)>> = HashMap::new();
+    let pasef: bool = conn.query_row(
+        "SELECT COUNT(*) = 2 FROM sqlite_master \
+         WHERE type = 'table' AND name IN ('Precursors', 'PasefFrameMsMsInfo')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !pasef {
+        return Ok(precursors);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT p.Id, MIN(i.Frame), COALESCE(p.MonoisotopicMz, p.LargestPeakMz), \
+         p.Charge, p.Intensity \
+         FROM Precursors p JOIN PasefFrameMsMsInfo i ON i.Precursor = p.Id \
+         GROUP BY p.Id ORDER BY p.Id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        let precursor = Precursor {
+            mz: row.get(2)?,
+            charge: row.get(3)?,
+            intensity: row.get(4)?,
+        };
+        Ok((row.get::<_, usize>(0)?, row.get::<_, usize>(1)?, precursor))
+    })?;
+    for row in rows {
+        let (id, frame, precursor) = row?;
+        precursors.entry(frame).or_default().push((id, precursor));
+    }
+    Ok(precursors)
+}
+
+/// The raw peaks of a frame.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct FramePeaks {
+    tof: Vec<u32>,
+    intensity: Vec<u32>,
+    scan: Vec<u32>,
+}
+
+/// The compressed peaks of a frame, as stored in the binary file.
+#[derive(Debug)]
+struct RawFrame {
+    offset: u64,
+    n_scans: usize,
+    compressed: Vec<u8>,
+}
+
+/// Read the compressed peaks of a frame.
+///
+/// Each frame starts with its size in bytes and its number of scans,
+/// followed by the compressed peaks.
+fn read_frame<R: Read + Seek>(reader: &mut R, offset: u64) -> Result<RawFrame, TdfError> {
+    let mut header = [0u8; 8];
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.read_exact(&mut header)?;
+    let size = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let n_scans = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+
+    let mut compressed = vec![0u8; size.saturating_sub(header.len())];
+    reader.read_exact(&mut compressed)?;
+    Ok(RawFrame {
+        offset,
+        n_scans,
+        compressed,
+    })
+}
+
+impl RawFrame {
+    /// Decode the peaks.
+    ///
+    /// The peaks are zstd compressed, byte-shuffled 32-bit words. The first
+    /// words hold twice the number of peaks in each scan, offset by one
+    /// scan, and the rest are pairs of delta-encoded TOF indices and
+    /// intensities.
+    fn decode(&self) -> Result<FramePeaks, TdfError> {
+        let (offset, n_scans) = (self.offset, self.n_scans);
+        let mut peaks = FramePeaks::default();
+        if self.compressed.is_empty() || n_scans == 0 {
+            return Ok(peaks);
+        }
+        let shuffled = zstd::stream::decode_all(&self.compressed[..])?;
+
+        let n_words = shuffled.len() / 4;
+        let words: Vec<u32> = (0..n_words)
+            .map(|idx| {
+                u32::from_le_bytes([
+                    shuffled[idx],
+                    shuffled[n_words + idx],
+                    shuffled[2 * n_words + idx],
+                    shuffled[3 * n_words + idx],
+                ])
+            })
+            .collect();
+        if words.len() < n_scans {
+            return Err(TdfError::MalformedFrame(offset));
+        }
+
+        let n_peaks = (words.len() - n_scans) / 2;
+        let mut start = 0;
+        for scan in 0..n_scans {
+            let end = match words.get(scan + 1) {
+                Some(count) if scan + 1 < n_scans => start + *count as usize / 2,
+                _ => n_peaks,
+            };
+            if end > n_peaks {
+                return Err(TdfError::MalformedFrame(offset));
+            }
+
+            let mut tof = 0u32;
+            for peak in start..end {
+                tof = tof.wrapping_add(words[n_scans + 2 * peak]);
+                peaks.tof.push(tof.wrapping_sub(1));
+                peaks.intensity.push(words[n_scans + 2 * peak + 1]);
+                peaks.scan.push(scan as u32);
+            }
+            start = end;
+        }
+
+        Ok(peaks)
+    }
+}
+
+#[derive(Debug)]
+pub enum TdfError {
+    SqliteError(rusqlite::Error),
+    IOError(std::io::Error),
+    MissingMetadata(String),
+    UnsupportedCompression(i64),
+    MalformedFrame(u64),
+}
+
+impl std::fmt::Display for TdfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TdfError::SqliteError(s) => write!(f, "TdfError: SQLite error {}", s),
+            TdfError::IOError(s) => write!(f, "TdfError: IO error {}", s),
+            TdfError::MissingMetadata(s) => write!(f, "TdfError: missing metadata {}", s),
+            TdfError::UnsupportedCompression(s) => {
+                write!(f, "TdfError: unsupported compression type {}", s)
+            }
+            TdfError::MalformedFrame(s) => write!(f, "TdfError: malformed frame at byte {}", s),
+        }
+    }
+}
+
+impl std::error::Error for TdfError {}
+
+impl From<rusqlite::Error> for TdfError {
+    fn from(residual: rusqlite::Error) -> Self {
+        Self::SqliteError(residual)
+    }
+}
+
+impl From<std::io::Error> for TdfError {
+    fn from(residual: std::io::Error) -> Self {
+        Self::IOError(residual)
+    }
+}
+
+impl From<TdfError> for MzMLError {
+    fn from(residual: TdfError) -> Self {
+        match residual {
+            TdfError::IOError(err) => Self::IOError(err),
+            err => Self::IOError(std::io::Error::new(std::io::ErrorKind::Other, err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_frame, FramePeaks, Mobility, MzCalibration, TdfReader};
+    use crate::mzml::{Polarity, Precursor};
+    use crate::testing::TempDir;
+    use futures::StreamExt;
+    use rusqlite::Connection;
+    use std::io::Cursor;
+    use std::path::{Path, PathBuf};
+
+    /// Encode a frame from the (TOF index, intensity) peaks of each scan.
+    fn encode_frame(scans: &[Vec<(u32, u32)>]) -> Vec<u8> {
+        let mut words: Vec<u32> = (0..scans.len())
+            .map(|idx| match idx {
+                0 => 0,
+                _ => 2 * scans[idx - 1].len() as u32,
+            })
+            .collect();
+        for scan in scans {
+            let mut last = 0;
+            for (tof, intensity) in scan {
+                words.push(tof + 1 - last);
+                words.push(*intensity);
+                last = tof + 1;
+            }
+        }
+
+        let n_words = words.len();
+        let mut shuffled = vec![0u8; 4 * n_words];
+        for (idx, word) in words.iter().enumerate() {
+            for (byte, value) in word.to_le_bytes().iter().enumerate() {
+                shuffled[byte * n_words + idx] = *value;
+            }
+        }
+        let compressed = zstd::stream::encode_all(&shuffled[..], 0).unwrap();
+
+        let mut frame = ((compressed.len() + 8) as u32).to_le_bytes().to_vec();
+        frame.extend((scans.len() as u32).to_le_bytes());
+        frame.extend(compressed);
+        frame
+    }
+
+    /// A realistic m/z calibration.
+    const CALIBRATION: MzCalibration = MzCalibration {
+        timebase: 0.2,
+        delay: 24864.,
+        coefficients: [313.577, 157424.07, 0.000017, 0., 1e-9],
+    };
+
+    /// Create a `.d` directory with two MS1 frames and a PASEF MS2 frame,
+    /// with or without an m/z calibration.
+    fn write_tdf(dir: &TempDir, calibrated: bool) -> PathBuf {
+        let path = dir.join("test.d");
+        std::fs::create_dir_all(&path).unwrap();
+        let tdf = path.join("analysis.tdf");
+
+        let frames = [
+            encode_frame(&[vec![(100, 10), (200, 5)], vec![], vec![(100, 2)]]),
+            encode_frame(&[vec![(300, 1)], vec![(300, 3)], vec![]]),
+        ];
+        let mut bin = Vec::new();
+        let mut offsets = Vec::new();
+        for frame in frames.iter() {
+            offsets.push(bin.len());
+            bin.extend(frame);
+        }
+        std::fs::write(path.join("analysis.tdf_bin"), bin).unwrap();
+
+        let conn = Connection::open(tdf).unwrap();
+        conn.execute_batch(&format!(
+            "CREATE TABLE GlobalMetadata (Key TEXT, Value TEXT);
+             INSERT INTO GlobalMetadata VALUES
+                 ('TimsCompressionType', '2'), ('MzAcqRangeLower', '100'),
+                 ('MzAcqRangeUpper', '1600'), ('DigitizerNumSamples', '1000'),
+                 ('OneOverK0AcqRangeLower', '0.6'), ('OneOverK0AcqRangeUpper', '1.6');
+             CREATE TABLE Frames (Id INTEGER, Time REAL, Polarity TEXT, MsMsType INTEGER,
+                 TimsId INTEGER, NumScans INTEGER);
+             INSERT INTO Frames VALUES
+                 (1, 60.0, '+', 0, {}, 4), (2, 61.2, '+', 8, 0, 4), (3, 62.4, '+', 0, {}, 4);
+             CREATE TABLE Precursors (Id INTEGER, LargestPeakMz REAL, MonoisotopicMz REAL,
+                 Charge INTEGER, Intensity REAL);
+             INSERT INTO Precursors VALUES (1, 195.2, 195.1227, 1, 1000), (2, 300.5, NULL, NULL, 50);
+             CREATE TABLE PasefFrameMsMsInfo (Frame INTEGER, Precursor INTEGER);
+             INSERT INTO PasefFrameMsMsInfo VALUES (2, 1), (2, 2);",
+            offsets[0], offsets[1],
+        ))
+        .unwrap();
+        if calibrated {
+            conn.execute_batch(
+                "CREATE TABLE MzCalibration (Id INTEGER, ModelType INTEGER,
+                     DigitizerTimebase REAL, DigitizerDelay REAL, T1 REAL, T2 REAL,
+                     dC1 REAL, dC2 REAL, C0 REAL, C1 REAL, C2 REAL, C3 REAL, C4 REAL);
+                 INSERT INTO MzCalibration VALUES
+                     (1, 2, 0.2, 24864.0, 25.0, 25.0, 0, 0, 313.577, 157424.07, 0.000017, 0, 1e-9);
+                 ALTER TABLE Frames ADD COLUMN MzCalibration INTEGER;
+                 UPDATE Frames SET MzCalibration = 1;",
+            )
+            .unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn test_read_frame() {
+        let frame = encode_frame(&[vec![(100, 10), (200, 5)], vec![], vec![(50, 2)]]);
+        let peaks = read_frame(&mut Cursor::new(frame), 0)
+            .unwrap()
+            .decode()
+            .unwrap();
+        assert_eq!(
+            peaks,
+            FramePeaks {
+                tof: vec![100, 200, 50],
+                intensity: vec![10, 5, 2],
+                scan: vec![0, 0, 2],
+            }
+        );
+    }
+
+    #[test]
+    fn test_mz_calibration() {
+        // The m/z of each TOF index, found by bisecting the calibration:
+        let expected = [
+            (100, 95.0377936667),
+            (159035, 500.0037415102),
+            (395000, 1688.0084889708),
+        ];
+        for (tof, mz) in expected {
+            assert!((CALIBRATION.mz(tof) - mz).abs() < 1e-8);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tdf() {
+        let dir = TempDir::new("tdf");
+        let path = write_tdf(&dir, true);
+        assert!(TdfReader::is_tdf(&path));
+        assert!(!TdfReader::is_tdf(Path::new("data")));
+
+        let reader = TdfReader::open(&path).unwrap();
+        let spectra: Vec<_> = reader.clone().stream().collect().await;
+        let spectra: Vec<_> = spectra.into_iter().map(|x| x.unwrap()).collect();
+        assert_eq!(spectra.len(), 4);
+
+        // Peaks with the same TOF index are summed across scans:
+        let first = &spectra[0];
+        assert_eq!(first.ms_level, 1);
+        assert_eq!(first.scan_start_time, 1.);
+        assert_eq!(first.polarity, Polarity::Positive);
+        assert_eq!(first.intensity, vec![12., 5.]);
+        assert_eq!(first.total_ion_current, 17.);
+        assert!((first.mz[0] - CALIBRATION.mz(100)).abs() < 1e-9);
+        assert!(first.mobility.is_empty());
+
+        // Each PASEF precursor is a separate MS2 spectrum:
+        assert_eq!(spectra[1].id, "frame=2 precursor=1");
+        assert_eq!(
+            spectra[2].precursors,
+            vec![Precursor {
+                mz: 300.5,
+                charge: None,
+                intensity: Some(50.),
+            }]
+        );
+
+        let retained = reader.with_mobility(Mobility::Retain).parse().unwrap();
+        assert_eq!(retained.spectra.len(), 2);
+        assert_eq!(retained.scan_range, (100., 1600.));
+        assert_eq!(retained.spectra[1].intensity, vec![1., 3.]);
+        assert_eq!(retained.spectra[1].mz[0], retained.spectra[1].mz[1]);
+        assert!((retained.spectra[1].mobility[0] - 1.6).abs() < 1e-9);
+        assert!((retained.spectra[1].mobility[1] - 1.35).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_uncalibrated() {
+        // Without a calibration, m/z is interpolated over the acquisition range:
+        let dir = TempDir::new("tdf_uncalibrated");
+        let reader = TdfReader::open(write_tdf(&dir, false)).unwrap();
+        let spectra = reader.parse().unwrap();
+        let sqrt_mz = 10. + 30. / 1000. * 100.;
+        assert!((spectra.spectra[0].mz[0] - sqrt_mz * sqrt_mz).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,35 @@
+//! Helpers shared by the unit tests.
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static N_DIRS: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary directory that is removed when it is dropped.
+///
+/// The name includes the process id and a counter, so tests running
+/// concurrently, even from other `cargo test` processes, never share one.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "mzsniffer_{}_{}_{}",
+            name,
+            std::process::id(),
+            N_DIRS.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    /// A path within the directory.
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}